@@ -0,0 +1,344 @@
+use crate::game::arena::Arena;
+use crate::game::bot::BotDifficulty;
+use crate::game::gameloop::{start_game_loop, LoopConfig};
+use crate::game::gamemanager::GameManager;
+use crate::game::matchrules::MatchRules;
+use crate::game::replay::{Replay, ReplayStatus};
+use crate::game::state::entityid::EntityId;
+use crate::game::team::spawnpattern::SpawnPattern;
+
+use crate::network::clientrequest::ClientRequest;
+use crate::network::socketmanager::DEFAULT_PORT;
+use crate::startup::startup::{ManagedSenders, StartupManager};
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+
+type SharedManager = Arc<tokio::sync::Mutex<StartupManager>>;
+
+#[tauri::command]
+fn client_request(managed: tauri::State<ManagedSenders>, request: ClientRequest) {
+    // lock the inner senders
+    let senders = managed.inner.lock().unwrap();
+    // send on the specific channel
+    let _ = senders.frontend_request_tx.send(request);
+}
+
+#[tauri::command]
+fn set_game_settings(
+    player_count: u8,
+    rules: MatchRules,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    println!(
+        "Got the player count: {} and rules: {:?}",
+        player_count, rules
+    );
+
+    let mut gm = gm.lock().unwrap();
+    gm.set_game_settings(player_count, rules)
+}
+
+///Fills a lobby slot with a bot, `team_id` left out picks the emptiest team.
+#[tauri::command]
+fn add_bot(
+    team_id: Option<u8>,
+    difficulty: BotDifficulty,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<EntityId, String> {
+    gm.lock().unwrap().add_bot(team_id, difficulty)
+}
+
+#[tauri::command]
+fn remove_bot(
+    player_id: EntityId,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    gm.lock().unwrap().remove_bot(player_id)
+}
+
+#[tauri::command]
+fn fill_with_bots(difficulty: BotDifficulty, gm: tauri::State<Arc<Mutex<GameManager>>>) -> usize {
+    gm.lock().unwrap().fill_with_bots(difficulty)
+}
+
+///`pattern` is "Line", "Diamond" or "Spread", teams hold up to 4 players.
+#[tauri::command]
+fn set_team_formation(
+    team_id: u8,
+    pattern: SpawnPattern,
+    max_players: usize,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    gm.lock()
+        .unwrap()
+        .set_team_formation(team_id, pattern, max_players)
+}
+
+#[tauri::command]
+async fn host_game<'a>(port: u16, startup: tauri::State<'a, SharedManager>) -> Result<(), String> {
+    let mut start_lock = startup.lock().await;
+    let port = if port == 0 { DEFAULT_PORT } else { port };
+    start_lock.init_host(port).await.map_err(|e| e.to_string())
+}
+
+///`address` can be "ip:port", "[ipv6]:port", or "hostname:port". The port defaults to 8080.
+#[tauri::command]
+async fn join_game<'a>(
+    address: String,
+    startup: tauri::State<'a, SharedManager>,
+) -> Result<(), String> {
+    let mut start_lock = startup.lock().await;
+    start_lock
+        .init_join(&address)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+///Fails until the lobby is full and everyone in it is ready.
+#[tauri::command]
+fn start_game(gm: tauri::State<Arc<Mutex<GameManager>>>) -> Result<(), String> {
+    let mut gm = gm.lock().unwrap();
+    gm.start_game()
+}
+
+#[tauri::command]
+fn kick_player(
+    player_id: EntityId,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    gm.lock().unwrap().kick_player(player_id)
+}
+
+///Moves players off the bigger teams, returns how many moved.
+#[tauri::command]
+fn balance_teams(gm: tauri::State<Arc<Mutex<GameManager>>>) -> Result<usize, String> {
+    gm.lock().unwrap().balance_teams()
+}
+
+//This is for ending a game session, like a round
+#[tauri::command]
+fn end_game(gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    let mut gm = gm.lock().unwrap();
+    gm.end_game();
+}
+
+//This is for quitting the game where you'd reset #players etc.
+#[tauri::command]
+fn quit_game(gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    let mut gm = gm.lock().unwrap();
+    gm.quit_game();
+}
+
+//Maps
+
+fn map_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let base = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    Ok(base.join("maps"))
+}
+
+///Built in maps, then any `<name>.json` in the app data maps folder.
+#[tauri::command]
+fn list_maps(app: AppHandle) -> Result<Vec<String>, String> {
+    Arena::list(Some(&map_dir(&app)?)).map_err(|e| e.to_string())
+}
+
+///`name` is one from list_maps. Returns the map so the renderer can size itself and draw its zones.
+#[tauri::command]
+fn select_map(
+    name: String,
+    app: AppHandle,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<Arena, String> {
+    let arena = Arena::find(&name, Some(&map_dir(&app)?)).map_err(|e| e.to_string())?;
+    gm.lock().unwrap().select_map(arena.clone())?;
+    Ok(arena)
+}
+
+//Replays
+
+fn replay_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let base = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    Ok(base.join("replays"))
+}
+
+#[tauri::command]
+fn set_replay_recording(
+    enabled: bool,
+    app: AppHandle,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    let dir = if enabled {
+        Some(replay_dir(&app)?)
+    } else {
+        None
+    };
+    gm.lock().unwrap().replay_dir = dir;
+    Ok(())
+}
+
+#[tauri::command]
+fn list_replays(app: AppHandle) -> Result<Vec<String>, String> {
+    Replay::list(&replay_dir(&app)?).map_err(|e| e.to_string())
+}
+
+///`name` is a file name from list_replays. The replay takes over the world until stop_replay.
+#[tauri::command]
+fn play_replay(
+    name: String,
+    app: AppHandle,
+    gm: tauri::State<Arc<Mutex<GameManager>>>,
+) -> Result<(), String> {
+    if std::path::Path::new(&name).file_name() != Some(name.as_ref()) {
+        return Err(format!("invalid replay name {name}"));
+    }
+    let replay = Replay::load(&replay_dir(&app)?.join(&name)).map_err(|e| e.to_string())?;
+    gm.lock().unwrap().play_replay(replay);
+    Ok(())
+}
+
+#[tauri::command]
+fn pause_replay(paused: bool, gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    gm.lock().unwrap().pause_replay(paused);
+}
+
+///`tick` counts from the start of the match, see replay_status for the length.
+#[tauri::command]
+fn seek_replay(tick: u64, gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    gm.lock().unwrap().seek_replay(tick);
+}
+
+#[tauri::command]
+fn set_replay_speed(speed: f32, gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    gm.lock().unwrap().set_replay_speed(speed);
+}
+
+#[tauri::command]
+fn replay_status(gm: tauri::State<Arc<Mutex<GameManager>>>) -> Option<ReplayStatus> {
+    gm.lock().unwrap().replay_status()
+}
+
+#[tauri::command]
+fn stop_replay(gm: tauri::State<Arc<Mutex<GameManager>>>) {
+    gm.lock().unwrap().stop_replay();
+}
+
+#[tauri::command]
+fn list_animation_folders(app: AppHandle) -> Result<Vec<String>, String> {
+    let base = app.path().resource_dir().map_err(|e| e.to_string())?;
+
+    let animations = base.join("assets/animations");
+    println!("Animations path: {:?}", animations);
+
+    let entries = std::fs::read_dir(&animations).map_err(|e| e.to_string())?;
+
+    let mut dirs = Vec::new();
+
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.path().is_dir() {
+            if let Some(name) = entry.file_name().to_str() {
+                dirs.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(dirs)
+}
+
+// src-tauri
+#[tauri::command]
+fn list_audio_files(path: String) -> Vec<String> {
+    let mut files = vec![];
+
+    for entry in std::fs::read_dir(path).unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+
+        if path.is_file() {
+            if let Some(ext) = path.extension() {
+                if ext == "ogg" || ext == "wav" {
+                    files.push(path.to_string_lossy().to_string());
+                }
+            }
+        }
+    }
+
+    files
+}
+
+#[tauri::command]
+fn list_files(path: String) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(&path).map_err(|e| e.to_string())?;
+
+    let mut files = Vec::new();
+
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.path().is_file() {
+            if let Some(name) = entry.file_name().to_str() {
+                files.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub async fn run() -> std::io::Result<()> {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_process::init())
+        .setup(|app| {
+            //game manager
+            let gm = Arc::new(Mutex::new(GameManager::new(Arena::default())));
+            app.manage(gm.clone());
+
+            let gm_for_loop = Arc::clone(&gm);
+            //startup
+            let startup: SharedManager = Arc::new(tokio::sync::Mutex::new(StartupManager::new(
+                gm,
+                app.handle().clone(),
+            )));
+
+            app.manage(startup.clone());
+
+            //game loop
+            start_game_loop(gm_for_loop, LoopConfig::default());
+
+            Ok(())
+        }) // makes GameManager available to all commands
+        .invoke_handler(tauri::generate_handler![
+            set_game_settings,
+            set_team_formation,
+            add_bot,
+            remove_bot,
+            fill_with_bots,
+            client_request,
+            start_game,
+            kick_player,
+            balance_teams,
+            host_game,
+            join_game,
+            end_game,
+            list_animation_folders,
+            list_audio_files,
+            list_files,
+            quit_game,
+            list_maps,
+            select_map,
+            set_replay_recording,
+            list_replays,
+            play_replay,
+            pause_replay,
+            seek_replay,
+            set_replay_speed,
+            replay_status,
+            stop_replay,
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use rebound_lib::{run_server, ServerConfig};
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let config = match ServerConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{}", rebound_lib::SERVER_USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    run_server(config).await
+}
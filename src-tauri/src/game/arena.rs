@@ -0,0 +1,389 @@
+//! Arena files.
+//!
+//! An arena is a JSON file laying out the playing field: how big it is, the
+//! walls and goals inside it, where the ball and each team's players start,
+//! and zones that only get drawn. The maps under `src-tauri/maps` are built
+//! into the game, more can be dropped into the `maps` folder in app data.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    scoremanager::Color,
+    state::enums::Shape,
+    team::{MAX_TEAMS, MIN_TEAMS},
+};
+
+pub const MAP_EXTENSION: &str = "json";
+pub const DEFAULT_MAP: &str = "classic";
+
+const BUILTIN_MAPS: [(&str, &str); 3] = [
+    ("classic", include_str!("../../maps/classic.json")),
+    ("crossroads", include_str!("../../maps/crossroads.json")),
+    ("fourway", include_str!("../../maps/fourway.json")),
+];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+    /// Facing, radians
+    #[serde(default)]
+    pub angle: f32,
+}
+
+///A static wall, `x`/`y` is placed the same way as the entity's pos.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct WallLayout {
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(flatten)]
+    pub shape: Shape,
+}
+
+///Goal rectangle, `team_id` is the team it belongs to.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct GoalLayout {
+    pub team_id: u8,
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TeamLayout {
+    pub team_id: u8,
+    pub name: String,
+    /// `[r, g, b, a]`
+    pub color: Color,
+    /// Middle of the team's formation, the angle is the way it faces
+    pub spawn: SpawnPoint,
+}
+
+///Only drawn, nothing collides with a zone.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Zone {
+    #[serde(default)]
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    #[serde(default)]
+    pub angle: f32,
+    #[serde(flatten)]
+    pub shape: Shape,
+    /// Any css colour, the renderer picks one when missing
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Arena {
+    pub name: String,
+    pub width: f32,
+    pub height: f32,
+    /// Thickness of the walls put around the edge, 0 for none
+    #[serde(default)]
+    pub border: f32,
+    #[serde(default)]
+    pub walls: Vec<WallLayout>,
+    pub goals: Vec<GoalLayout>,
+    /// Kickoffs go through these in order
+    pub ball_spawns: Vec<SpawnPoint>,
+    pub teams: Vec<TeamLayout>,
+    #[serde(default)]
+    pub zones: Vec<Zone>,
+}
+
+#[derive(Debug)]
+pub enum ArenaError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    Invalid(Vec<String>),
+    UnknownMap(String),
+}
+
+impl fmt::Display for ArenaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArenaError::Io(e) => write!(f, "could not read map: {e}"),
+            ArenaError::Parse(e) => write!(f, "map is not valid: {e}"),
+            ArenaError::Invalid(problems) => write!(f, "bad map: {}", problems.join("; ")),
+            ArenaError::UnknownMap(name) => write!(f, "no map named {name}"),
+        }
+    }
+}
+
+impl std::error::Error for ArenaError {}
+
+impl From<io::Error> for ArenaError {
+    fn from(e: io::Error) -> Self {
+        ArenaError::Io(e)
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::builtin(DEFAULT_MAP).expect("the default map ships with the game")
+    }
+}
+
+impl Arena {
+    ///Parses and validates a map.
+    pub fn from_json(json: &str) -> Result<Self, ArenaError> {
+        let arena: Arena = serde_json::from_str(json).map_err(ArenaError::Parse)?;
+        arena.validate()?;
+        Ok(arena)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ArenaError> {
+        Arena::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (_, json) = BUILTIN_MAPS.iter().find(|(id, _)| *id == name)?;
+        Arena::from_json(json).ok()
+    }
+
+    ///Built in maps first, then the files in `dir` that don't shadow one.
+    pub fn list(dir: Option<&Path>) -> io::Result<Vec<String>> {
+        let mut names: Vec<String> = BUILTIN_MAPS.iter().map(|(id, _)| id.to_string()).collect();
+
+        if let Some(dir) = dir.filter(|dir| dir.exists()) {
+            let mut files: Vec<String> = fs::read_dir(dir)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == MAP_EXTENSION))
+                .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+                .filter(|name| !names.contains(name))
+                .collect();
+            files.sort_unstable();
+            names.append(&mut files);
+        }
+        Ok(names)
+    }
+
+    ///`name` is one from `list`, built in maps win over files.
+    pub fn find(name: &str, dir: Option<&Path>) -> Result<Self, ArenaError> {
+        if let Some((_, json)) = BUILTIN_MAPS.iter().find(|(id, _)| *id == name) {
+            return Arena::from_json(json);
+        }
+
+        let path = dir.and_then(|dir| Arena::file_in(dir, name));
+        match path {
+            Some(path) if path.exists() => Arena::load(&path),
+            _ => Err(ArenaError::UnknownMap(name.to_string())),
+        }
+    }
+
+    pub fn spawn_for(&self, team_id: u8) -> Option<SpawnPoint> {
+        self.team(team_id).map(|team| team.spawn)
+    }
+
+    pub fn team(&self, team_id: u8) -> Option<&TeamLayout> {
+        self.teams.iter().find(|team| team.team_id == team_id)
+    }
+
+    pub fn team_ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.teams.iter().map(|team| team.team_id)
+    }
+
+    ///Every problem with the map at once, so a map maker can fix them in one go.
+    pub fn validate(&self) -> Result<(), ArenaError> {
+        let mut problems = Vec::new();
+
+        if self.name.trim().is_empty() {
+            problems.push("name is empty".to_string());
+        }
+        if !positive(self.width) || !positive(self.height) {
+            problems.push(format!(
+                "size {}x{} must be positive",
+                self.width, self.height
+            ));
+        }
+        if !self.border.is_finite() || self.border < 0.0 {
+            problems.push(format!("border {} can't be negative", self.border));
+        }
+
+        for (n, wall) in self.walls.iter().enumerate() {
+            if !shape_ok(&wall.shape) || !wall.x.is_finite() || !wall.y.is_finite() {
+                problems.push(format!("wall {n} has a bad position or size"));
+            }
+        }
+
+        if !(MIN_TEAMS..=MAX_TEAMS).contains(&self.teams.len()) {
+            problems.push(format!(
+                "needs {MIN_TEAMS} to {MAX_TEAMS} teams, has {}",
+                self.teams.len()
+            ));
+        }
+        for (n, team) in self.teams.iter().enumerate() {
+            if self.teams[..n].iter().any(|t| t.team_id == team.team_id) {
+                problems.push(format!("team {} is listed twice", team.team_id));
+                continue;
+            }
+            if team.name.trim().is_empty() {
+                problems.push(format!("team {} has no name", team.team_id));
+            }
+            if !self.goals.iter().any(|goal| goal.team_id == team.team_id) {
+                problems.push(format!("team {} has no goal", team.team_id));
+            }
+            if !self.contains(&team.spawn) {
+                problems.push(format!(
+                    "team {} spawn ({}, {}) is outside the arena",
+                    team.team_id, team.spawn.x, team.spawn.y
+                ));
+            }
+        }
+
+        if self.goals.is_empty() {
+            problems.push("no goals".to_string());
+        }
+        for (n, goal) in self.goals.iter().enumerate() {
+            if !positive(goal.w) || !positive(goal.h) || !goal.x.is_finite() || !goal.y.is_finite()
+            {
+                problems.push(format!("goal {n} has a bad position or size"));
+            }
+            if !self.teams.iter().any(|team| team.team_id == goal.team_id) {
+                problems.push(format!("goal {n} belongs to unknown team {}", goal.team_id));
+            }
+        }
+
+        if self.ball_spawns.is_empty() {
+            problems.push("no ball spawns".to_string());
+        }
+        for spawn in &self.ball_spawns {
+            if !self.contains(spawn) {
+                problems.push(format!(
+                    "ball spawn ({}, {}) is outside the arena",
+                    spawn.x, spawn.y
+                ));
+            }
+        }
+
+        for (n, zone) in self.zones.iter().enumerate() {
+            if !shape_ok(&zone.shape) {
+                problems.push(format!("zone {n} has a bad size"));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ArenaError::Invalid(problems))
+        }
+    }
+
+    //Helpers
+
+    fn contains(&self, point: &SpawnPoint) -> bool {
+        (0.0..=self.width).contains(&point.x) && (0.0..=self.height).contains(&point.y)
+    }
+
+    fn file_in(dir: &Path, name: &str) -> Option<PathBuf> {
+        // Only plain names, nothing that walks out of the maps folder
+        if Path::new(name).file_name() != Some(name.as_ref()) {
+            return None;
+        }
+        Some(dir.join(format!("{name}.{MAP_EXTENSION}")))
+    }
+}
+
+fn positive(v: f32) -> bool {
+    v.is_finite() && v > 0.0
+}
+
+fn shape_ok(shape: &Shape) -> bool {
+    match *shape {
+        Shape::Circle { radius } => positive(radius),
+        Shape::Rectangle { w, h } => positive(w) && positive(h),
+        Shape::Capsule { radius, length } => {
+            positive(radius) && length.is_finite() && length >= 0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_maps_are_valid() {
+        for (name, json) in BUILTIN_MAPS {
+            if let Err(e) = Arena::from_json(json) {
+                panic!("{name}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let json = r#"{
+            "name": "broken",
+            "width": 100, "height": 0,
+            "goals": [{ "team_id": 3, "x": 0, "y": 0, "w": 10, "h": 10 }],
+            "ball_spawns": [],
+            "teams": [
+                { "team_id": 0, "name": "Blue", "color": [0, 0, 255, 255], "spawn": { "x": 500, "y": 10 } },
+                { "team_id": 0, "name": "Blue", "color": [0, 0, 255, 255], "spawn": { "x": 50, "y": 0 } },
+                { "team_id": 1, "name": " ", "color": [255, 0, 0, 255], "spawn": { "x": 50, "y": 0 } }
+            ]
+        }"#;
+
+        let Err(ArenaError::Invalid(problems)) = Arena::from_json(json) else {
+            panic!("map should not load");
+        };
+        assert_eq!(
+            problems,
+            [
+                "size 100x0 must be positive",
+                "team 0 has no goal",
+                "team 0 spawn (500, 10) is outside the arena",
+                "team 0 is listed twice",
+                "team 1 has no name",
+                "team 1 has no goal",
+                "goal 0 belongs to unknown team 3",
+                "no ball spawns",
+            ]
+        );
+    }
+
+    #[test]
+    fn walls_and_zones_take_any_shape() {
+        let json = r#"{
+            "name": "shapes",
+            "width": 100, "height": 100,
+            "walls": [{ "type": "capsule", "x": 50, "y": 50, "angle": 0.5, "radius": 4, "length": 30 }],
+            "goals": [
+                { "team_id": 0, "x": 0, "y": 40, "w": 10, "h": 20 },
+                { "team_id": 1, "x": 90, "y": 40, "w": 10, "h": 20 }
+            ],
+            "ball_spawns": [{ "x": 50, "y": 20 }],
+            "teams": [
+                { "team_id": 0, "name": "Blue", "color": [0, 0, 255, 255], "spawn": { "x": 20, "y": 50 } },
+                { "team_id": 1, "name": "Red", "color": [255, 0, 0, 255], "spawn": { "x": 80, "y": 50, "angle": 3.142 } }
+            ],
+            "zones": [{ "type": "circle", "x": 50, "y": 50, "radius": 0 }]
+        }"#;
+
+        let Err(ArenaError::Invalid(problems)) = Arena::from_json(json) else {
+            panic!("map should not load");
+        };
+        assert_eq!(problems, ["zone 0 has a bad size"]);
+
+        let arena = Arena::from_json(&json.replace("\"radius\": 0", "\"radius\": 12")).unwrap();
+        assert_eq!(
+            arena.walls[0].shape,
+            Shape::Capsule {
+                radius: 4.0,
+                length: 30.0
+            }
+        );
+    }
+}
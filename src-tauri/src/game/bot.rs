@@ -0,0 +1,308 @@
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::game::{
+    collision::Collision,
+    gamemanager::GRAB_RADIUS,
+    input::{inputframe::Vec2, InputFrame},
+    physics::Physics,
+    state::{entityid::EntityId, enums::Kind, State},
+    world::World,
+};
+
+/// Close enough to the goal to let fly
+const SHOT_RANGE: f32 = 140.0;
+/// Near enough to a ball carrier to dash into them or wall them off
+const PRESSURE_RANGE: f32 = 60.0;
+/// How far out from their own goal defenders hold
+const GUARD_DISTANCE: f32 = 40.0;
+/// Facing this close to the aim is good enough to shoot
+const AIM_TOLERANCE: f32 = 0.3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl BotDifficulty {
+    /// Seconds between decisions
+    fn reaction_time(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.4,
+            BotDifficulty::Normal => 0.2,
+            BotDifficulty::Hard => 0.05,
+        }
+    }
+
+    /// Radians a shot can be off by
+    fn aim_error(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.35,
+            BotDifficulty::Normal => 0.15,
+            BotDifficulty::Hard => 0.04,
+        }
+    }
+
+    /// How far the stick gets pushed
+    fn speed(self) -> f32 {
+        match self {
+            BotDifficulty::Easy => 0.6,
+            BotDifficulty::Normal => 0.85,
+            BotDifficulty::Hard => 1.0,
+        }
+    }
+
+    /// Easy bots never dash or build
+    fn uses_abilities(self) -> bool {
+        self != BotDifficulty::Easy
+    }
+}
+
+///A server side player, makes up an InputFrame from the world instead of a controller.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Bot {
+    pub player_id: EntityId,
+    pub difficulty: BotDifficulty,
+    think_in: f32,
+    frame: InputFrame,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BotManager {
+    bots: Vec<Bot>,
+}
+
+impl BotManager {
+    pub fn new() -> Self {
+        Self { bots: Vec::new() }
+    }
+
+    pub fn add(&mut self, player_id: EntityId, difficulty: BotDifficulty) {
+        println!("Player {:?} is a {:?} bot", player_id, difficulty);
+        self.bots.push(Bot {
+            player_id,
+            difficulty,
+            think_in: 0.0,
+            frame: InputFrame::new(),
+        });
+    }
+
+    pub fn remove(&mut self, player_id: EntityId) {
+        self.bots.retain(|bot| bot.player_id != player_id);
+    }
+
+    pub fn is_bot(&self, player_id: EntityId) -> bool {
+        self.bots.iter().any(|bot| bot.player_id == player_id)
+    }
+
+    pub fn clear(&mut self) {
+        self.bots.clear();
+    }
+
+    ///Every bot's input for this tick. `rng` is the match's so replays play out the same.
+    pub fn think(
+        &mut self,
+        world: &World,
+        dt: f32,
+        rng: &mut StdRng,
+    ) -> Vec<(EntityId, InputFrame)> {
+        self.bots
+            .iter_mut()
+            .filter_map(|bot| Some((bot.player_id, bot.update(world, dt, rng)?)))
+            .collect()
+    }
+}
+
+impl Bot {
+    fn update(&mut self, world: &World, dt: f32, rng: &mut StdRng) -> Option<InputFrame> {
+        self.think_in -= dt;
+        if self.think_in > 0.0 {
+            // Buttons are taps, let go the tick after pressing
+            self.frame.buttons.grab = false;
+            self.frame.buttons.dash = false;
+            self.frame.buttons.place = false;
+            return Some(self.frame.clone());
+        }
+
+        self.think_in = self.difficulty.reaction_time();
+        self.frame = self.decide(world, rng)?;
+        Some(self.frame.clone())
+    }
+
+    ///Carry it at their goal, otherwise the closest on the team chases and the rest guard.
+    fn decide(&self, world: &World, rng: &mut StdRng) -> Option<InputFrame> {
+        let me = &world.entities[world.index_of(self.player_id)?];
+        let team_id = me.team_id?;
+        let ball = world.entities.iter().find(|s| s.kind == Kind::Ball)?;
+        let pos = me.physics_state.pos;
+        let ball_pos = ball.physics_state.pos;
+        let mut frame = InputFrame::new();
+
+        if ball.held_by == Some(self.player_id) {
+            let goal = Self::goals(world)
+                .filter(|(id, _)| *id != team_id)
+                .map(|(_, centre)| centre)
+                .min_by(|a, b| distance(pos, *a).total_cmp(&distance(pos, *b)))?;
+            let error = self.difficulty.aim_error();
+            let aim = angle_to(pos, goal) + rng.gen_range(-error..=error);
+
+            frame.move_axis = self.steer(pos, goal);
+            frame.look = Vec2 {
+                x: aim.cos(),
+                y: aim.sin(),
+            };
+            let facing = Physics::normalize_angle(aim - me.physics_state.angle).abs();
+            frame.buttons.grab = distance(pos, goal) < SHOT_RANGE && facing < AIM_TOLERANCE;
+            return Some(frame);
+        }
+
+        let carrier = ball
+            .held_by
+            .and_then(|id| world.index_of(id))
+            .map(|i| &world.entities[i]);
+        let opponent_has_it = carrier.is_some_and(|c| c.team_id != Some(team_id));
+        let chaser = world
+            .entities
+            .iter()
+            .filter(|s| s.kind == Kind::Player && s.team_id == Some(team_id))
+            .min_by(|a, b| {
+                distance(a.physics_state.pos, ball_pos)
+                    .total_cmp(&distance(b.physics_state.pos, ball_pos))
+                    .then(a.entity_id.0.cmp(&b.entity_id.0))
+            })
+            .map(|s| s.entity_id);
+        let near_ball = distance(pos, ball_pos) < PRESSURE_RANGE;
+
+        frame.look = unit(ball_pos.x - pos.x, ball_pos.y - pos.y);
+        if chaser == Some(self.player_id) {
+            frame.move_axis = self.steer(pos, ball_pos);
+            frame.buttons.grab = carrier.is_none() && distance(pos, ball_pos) < GRAB_RADIUS * 0.8;
+            frame.buttons.dash = opponent_has_it && near_ball && self.difficulty.uses_abilities();
+        } else {
+            let own_goal = Self::goals(world).find(|(id, _)| *id == team_id);
+            let guard = match own_goal {
+                Some((_, goal)) => {
+                    let out = unit(ball_pos.x - goal.x, ball_pos.y - goal.y);
+                    Vec2 {
+                        x: goal.x + out.x * GUARD_DISTANCE,
+                        y: goal.y + out.y * GUARD_DISTANCE,
+                    }
+                }
+                None => ball_pos,
+            };
+            frame.move_axis = self.steer(pos, guard);
+            frame.buttons.place = opponent_has_it && near_ball && self.difficulty.uses_abilities();
+        }
+        Some(frame)
+    }
+
+    fn steer(&self, from: Vec2, to: Vec2) -> Vec2 {
+        if distance(from, to) < 2.0 {
+            return Vec2 { x: 0.0, y: 0.0 };
+        }
+        let dir = unit(to.x - from.x, to.y - from.y);
+        let speed = self.difficulty.speed();
+        Vec2 {
+            x: dir.x * speed,
+            y: dir.y * speed,
+        }
+    }
+
+    ///Each goal's team and centre.
+    fn goals(world: &World) -> impl Iterator<Item = (u8, Vec2)> + '_ {
+        world
+            .entities
+            .iter()
+            .filter(|s| s.kind == Kind::Goal)
+            .filter_map(|s: &State| {
+                let ps = &s.physics_state;
+                let (x1, y1, x2, y2) = Collision::bounds(&ps.shape, ps.pos, ps.angle);
+                let centre = Vec2 {
+                    x: (x1 + x2) / 2.0,
+                    y: (y1 + y2) / 2.0,
+                };
+                Some((s.team_id?, centre))
+            })
+    }
+}
+
+fn distance(a: Vec2, b: Vec2) -> f32 {
+    (b.x - a.x).hypot(b.y - a.y)
+}
+
+fn angle_to(from: Vec2, to: Vec2) -> f32 {
+    (to.y - from.y).atan2(to.x - from.x)
+}
+
+fn unit(x: f32, y: f32) -> Vec2 {
+    let length = x.hypot(y);
+    if length == 0.0 {
+        return Vec2 { x: 0.0, y: 0.0 };
+    }
+    Vec2 {
+        x: x / length,
+        y: y / length,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn bot(player_id: EntityId, difficulty: BotDifficulty) -> Bot {
+        Bot {
+            player_id,
+            difficulty,
+            think_in: 0.0,
+            frame: InputFrame::new(),
+        }
+    }
+
+    #[test]
+    fn chases_a_loose_ball_and_grabs_it_in_reach() {
+        let mut world = World::new();
+        let player = world.add_player(State::new_player(0, 100.0, 100.0, 0.0));
+        world.add_entity(State::new_ball(200.0, 100.0));
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let frame = bot(player, BotDifficulty::Hard)
+            .decide(&world, &mut rng)
+            .unwrap();
+        assert!(frame.move_axis.x > 0.9 && frame.move_axis.y.abs() < 0.01);
+        assert!(!frame.buttons.grab);
+
+        world.entities[1].physics_state.pos.x = 110.0;
+        let frame = bot(player, BotDifficulty::Hard)
+            .decide(&world, &mut rng)
+            .unwrap();
+        assert!(frame.buttons.grab);
+    }
+
+    #[test]
+    fn shoots_at_the_other_goal_once_lined_up() {
+        let mut world = World::new();
+        let player = world.add_player(State::new_player(0, 200.0, 90.0, 0.0));
+        let mut ball = State::new_ball(216.0, 90.0);
+        ball.held_by = Some(player);
+        world.add_entity(ball);
+        world.add_entity(State::new_goal(0.0, 70.0, 10.0, 40.0, 0));
+        world.add_entity(State::new_goal(310.0, 70.0, 10.0, 40.0, 1));
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let frame = bot(player, BotDifficulty::Hard)
+            .decide(&world, &mut rng)
+            .unwrap();
+        assert!(frame.move_axis.x > 0.9);
+        assert!(frame.buttons.grab);
+
+        // Facing away, turn before shooting
+        world.entities[0].physics_state.angle = std::f32::consts::PI;
+        let frame = bot(player, BotDifficulty::Hard)
+            .decide(&world, &mut rng)
+            .unwrap();
+        assert!(!frame.buttons.grab);
+    }
+}
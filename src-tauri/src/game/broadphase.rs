@@ -0,0 +1,237 @@
+//! Broad phase.
+//!
+//! A uniform grid over the entity bounds, rebuilt every tick. Each entity is
+//! dropped into every cell its box touches, and a pair is only reported from
+//! the first cell both boxes share, so it comes out once however many cells
+//! they have in common.
+
+use std::collections::HashMap;
+
+/// Roughly a few players wide, walls just span more cells
+pub const DEFAULT_CELL_SIZE: f32 = 32.0;
+
+type Bounds = (f32, f32, f32, f32); // min_x, min_y, max_x, max_y
+
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    bounds: Vec<Bounds>,
+    pairs: Vec<(usize, usize)>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            bounds: Vec::new(),
+            pairs: Vec::new(),
+        }
+    }
+
+    ///Refills the grid, entry `i` of `bounds` is entity `i`.
+    pub fn rebuild(&mut self, bounds: impl IntoIterator<Item = Bounds>) {
+        // Keep the cell vecs around, the arena looks about the same every tick
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+        self.bounds.clear();
+        self.bounds.extend(bounds);
+
+        for (index, bounds) in self.bounds.iter().enumerate() {
+            let (x1, y1, x2, y2) = self.cell_range(*bounds);
+            for cx in x1..=x2 {
+                for cy in y1..=y2 {
+                    self.cells.entry((cx, cy)).or_default().push(index);
+                }
+            }
+        }
+
+        self.pairs.clear();
+        for (&(cx, cy), members) in self.cells.iter() {
+            for (n, &i) in members.iter().enumerate() {
+                for &j in &members[n + 1..] {
+                    let (a, b) = (self.bounds[i], self.bounds[j]);
+                    if !Self::overlaps(a, b) {
+                        continue;
+                    }
+
+                    // Only the first shared cell reports the pair
+                    let (ax, ay, ..) = self.cell_range(a);
+                    let (bx, by, ..) = self.cell_range(b);
+                    if (ax.max(bx), ay.max(by)) != (cx, cy) {
+                        continue;
+                    }
+                    self.pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+
+        // HashMap order isn't stable, replays need the same order every run
+        self.pairs.sort_unstable();
+    }
+
+    ///Every pair of overlapping boxes, lower index first, each pair once.
+    pub fn pairs(&self) -> &[(usize, usize)] {
+        &self.pairs
+    }
+
+    //Helpers
+
+    fn cell_range(&self, (x1, y1, x2, y2): Bounds) -> (i32, i32, i32, i32) {
+        let cell = |v: f32| (v / self.cell_size).floor() as i32;
+        (cell(x1), cell(y1), cell(x2), cell(y2))
+    }
+
+    fn overlaps(a: Bounds, b: Bounds) -> bool {
+        a.0 < b.2 && a.2 > b.0 && a.1 < b.3 && a.3 > b.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::game::{
+        eventqueue::EventQueue,
+        physics::Physics,
+        state::{entityid::EntityId, State},
+        world::World,
+    };
+
+    fn xorshift(seed: &mut u32) -> f32 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 17;
+        *seed ^= *seed << 5;
+        *seed as f32 / u32::MAX as f32
+    }
+
+    fn scattered_bounds(count: usize, seed: u32) -> Vec<Bounds> {
+        let mut seed = seed;
+        (0..count)
+            .map(|_| {
+                let x = xorshift(&mut seed) * 320.0;
+                let y = xorshift(&mut seed) * 180.0;
+                let size = 2.0 + xorshift(&mut seed) * 10.0;
+                (x, y, x + size, y + size)
+            })
+            .collect()
+    }
+
+    fn brute_force_pairs(bounds: &[Bounds]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..bounds.len() {
+            for j in i + 1..bounds.len() {
+                if SpatialGrid::overlaps(bounds[i], bounds[j]) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn crowded_world(count: usize) -> World {
+        let mut world = World::new();
+        let mut seed = 0xBAD5_EED5;
+        for n in 0..count {
+            let x = 10.0 + xorshift(&mut seed) * 300.0;
+            let y = 10.0 + xorshift(&mut seed) * 160.0;
+            let mut state = match n % 3 {
+                0 => State::new_player((n % 2) as u8, x, y, 0.0),
+                1 => State::new_ball(x, y),
+                _ => State::new_brick(x, y, 8.0, EntityId(1)),
+            };
+            state.time_to_live = None;
+            state.physics_state.vel.x = (xorshift(&mut seed) - 0.5) * 400.0;
+            state.physics_state.vel.y = (xorshift(&mut seed) - 0.5) * 400.0;
+            world.add_entity(state);
+        }
+        world.add_entity(State::new_wall(0.0, -10.0, 320.0, 10.0));
+        world.add_entity(State::new_wall(0.0, 180.0, 320.0, 10.0));
+        world.add_entity(State::new_wall(-10.0, 0.0, 10.0, 180.0));
+        world.add_entity(State::new_wall(320.0, 0.0, 10.0, 180.0));
+        world
+    }
+
+    #[test]
+    fn grid_finds_the_same_pairs_as_brute_force_once_each() {
+        let mut bounds = scattered_bounds(400, 0x9E37_79B9);
+        // Arena sized walls cover most of the grid
+        bounds.push((0.0, -10.0, 320.0, 0.0));
+        bounds.push((-10.0, 0.0, 0.0, 180.0));
+        bounds.push((-4.0, -4.0, 330.0, 190.0));
+
+        let mut grid = SpatialGrid::new(DEFAULT_CELL_SIZE);
+        grid.rebuild(bounds.iter().copied());
+
+        assert_eq!(grid.pairs(), brute_force_pairs(&bounds).as_slice());
+    }
+
+    #[test]
+    fn rebuild_forgets_last_tick() {
+        let mut grid = SpatialGrid::new(DEFAULT_CELL_SIZE);
+        grid.rebuild([(0.0, 0.0, 5.0, 5.0), (2.0, 2.0, 8.0, 8.0)]);
+        assert_eq!(grid.pairs(), &[(0, 1)]);
+
+        grid.rebuild([(0.0, 0.0, 5.0, 5.0), (100.0, 100.0, 108.0, 108.0)]);
+        assert!(grid.pairs().is_empty());
+    }
+
+    /// `cargo test --release broad_phase_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn broad_phase_benchmark() {
+        const TICKS: u32 = 200;
+
+        for count in [100, 300, 600] {
+            let bounds = scattered_bounds(count, 0xC0FF_EE11);
+
+            let start = Instant::now();
+            let mut found = 0;
+            for _ in 0..TICKS {
+                found = brute_force_pairs(&bounds).len();
+            }
+            let brute = start.elapsed() / TICKS;
+
+            let mut grid = SpatialGrid::new(DEFAULT_CELL_SIZE);
+            let start = Instant::now();
+            for _ in 0..TICKS {
+                grid.rebuild(bounds.iter().copied());
+            }
+            let gridded = start.elapsed() / TICKS;
+            assert_eq!(grid.pairs().len(), found);
+
+            println!(
+                "{count} entities, {found} pairs: all pairs {brute:?}, grid {gridded:?} per tick ({:.1}x)",
+                brute.as_secs_f64() / gridded.as_secs_f64()
+            );
+        }
+
+        // Whole physics step on a crowded arena, against the old every-entity-vs-every-entity loop
+        let mut events = EventQueue::new();
+        let mut world = crowded_world(500);
+        let start = Instant::now();
+        for _ in 0..TICKS {
+            for i in 0..world.entities.len() {
+                Physics::step_entity(&mut world, i, 1.0 / 60.0, &mut events);
+            }
+            events.drain().for_each(drop);
+        }
+        let brute = start.elapsed() / TICKS;
+
+        let mut world = crowded_world(500);
+        let start = Instant::now();
+        for _ in 0..TICKS {
+            Physics::update(&mut world, 1.0 / 60.0, &mut events);
+            events.drain().for_each(drop);
+        }
+        let gridded = start.elapsed() / TICKS;
+
+        println!(
+            "{} entities, full physics tick: all pairs {brute:?}, grid {gridded:?} ({:.1}x)",
+            world.entities.len(),
+            brute.as_secs_f64() / gridded.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,561 @@
+//! Narrow phase, plus swept circle tests for bodies too fast for it.
+//!
+//! Every shape is treated as a convex core grown by a radius: a circle is a
+//! point, a capsule is a segment and a rectangle is a box with no radius.
+//! Rectangles keep `pos` as the corner of the unrotated box and turn around
+//! their centre by `angle`, the same way the frontend draws them. Capsules are
+//! centred on `pos` and lie along `angle`.
+
+use crate::game::{input::inputframe::Vec2, state::enums::Shape};
+
+const EPSILON: f32 = 1e-6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Unit normal pointing from the first shape into the second
+    pub nx: f32,
+    pub ny: f32,
+    /// How far they overlap along the normal
+    pub depth: f32,
+}
+
+enum Collider {
+    Round { a: Vec2, b: Vec2, radius: f32 },
+    Box { corners: [Vec2; 4], axes: [Vec2; 2] },
+}
+
+/// Where a swept circle first touches a shape
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Impact {
+    /// Fraction of the move done when they touch, 0..=1
+    pub toi: f32,
+    pub contact: Contact,
+}
+
+pub struct Collision;
+
+impl Collision {
+    ///Contact between two shapes, None when they don't overlap.
+    pub fn contact(
+        a_shape: &Shape,
+        a_pos: Vec2,
+        a_angle: f32,
+        b_shape: &Shape,
+        b_pos: Vec2,
+        b_angle: f32,
+    ) -> Option<Contact> {
+        let a = Collider::new(a_shape, a_pos, a_angle);
+        let b = Collider::new(b_shape, b_pos, b_angle);
+
+        match (&a, &b) {
+            (Collider::Round { .. }, Collider::Round { .. }) => Self::round_round(&a, &b),
+            (Collider::Box { axes: a_axes, .. }, Collider::Box { axes: b_axes, .. }) => {
+                let axes = [a_axes[0], a_axes[1], b_axes[0], b_axes[1]];
+                Self::separating_axes(&a, &b, &axes)
+            }
+            (round @ Collider::Round { .. }, boxed @ Collider::Box { .. })
+            | (boxed @ Collider::Box { .. }, round @ Collider::Round { .. }) => {
+                let axes = Self::round_box_axes(round, boxed);
+                Self::separating_axes(&a, &b, &axes)
+            }
+        }
+    }
+
+    ///Moves a circle from `from` to `to` and finds the first time it touches the
+    ///shape. The contact normal points from the circle into the shape. A circle
+    ///that already overlaps only counts while it keeps moving further in.
+    pub fn sweep_circle(
+        radius: f32,
+        from: Vec2,
+        to: Vec2,
+        shape: &Shape,
+        pos: Vec2,
+        angle: f32,
+    ) -> Option<Impact> {
+        let motion = sub(to, from);
+        let circle = Shape::Circle { radius };
+        if let Some(contact) = Self::contact(&circle, from, 0.0, shape, pos, angle) {
+            let inward = contact.nx * motion.x + contact.ny * motion.y;
+            return (inward > 0.0).then_some(Impact { toi: 0.0, contact });
+        }
+
+        // Cast the centre against the shape grown by the radius: its outline is
+        // the core's edges pushed out plus a circle on every corner
+        let mut first: Option<(f32, Vec2)> = None;
+        let mut consider = |hit: Option<(f32, Vec2)>| {
+            if let Some((toi, normal)) = hit {
+                if first.is_none_or(|(best, _)| toi < best) {
+                    first = Some((toi, normal));
+                }
+            }
+        };
+
+        match Collider::new(shape, pos, angle) {
+            Collider::Round { a, b, radius: r } => {
+                let reach = r + radius;
+                consider(ray_circle(from, motion, a, reach));
+                consider(ray_circle(from, motion, b, reach));
+
+                let along = sub(b, a);
+                let len = dot(along, along).sqrt();
+                if len > EPSILON {
+                    let side = Vec2 {
+                        x: -along.y / len,
+                        y: along.x / len,
+                    };
+                    for normal in [side, scale(side, -1.0)] {
+                        let offset = scale(normal, reach);
+                        consider(ray_segment(
+                            from,
+                            motion,
+                            add(a, offset),
+                            add(b, offset),
+                            normal,
+                        ));
+                    }
+                }
+            }
+            Collider::Box { corners, .. } => {
+                for k in 0..corners.len() {
+                    let (c1, c2) = (corners[k], corners[(k + 1) % corners.len()]);
+                    let edge = sub(c2, c1);
+                    let len = dot(edge, edge).sqrt();
+                    if len > EPSILON {
+                        let normal = Vec2 {
+                            x: edge.y / len,
+                            y: -edge.x / len,
+                        };
+                        let offset = scale(normal, radius);
+                        consider(ray_segment(
+                            from,
+                            motion,
+                            add(c1, offset),
+                            add(c2, offset),
+                            normal,
+                        ));
+                    }
+                    consider(ray_circle(from, motion, c1, radius));
+                }
+            }
+        }
+
+        first.map(|(toi, normal)| Impact {
+            toi,
+            contact: Contact {
+                nx: -normal.x,
+                ny: -normal.y,
+                depth: 0.0,
+            },
+        })
+    }
+
+    ///Axis aligned (min_x, min_y, max_x, max_y) around a shape.
+    pub fn bounds(shape: &Shape, pos: Vec2, angle: f32) -> (f32, f32, f32, f32) {
+        let collider = Collider::new(shape, pos, angle);
+        let x = Vec2 { x: 1.0, y: 0.0 };
+        let y = Vec2 { x: 0.0, y: 1.0 };
+        let (min_x, max_x) = collider.project(x);
+        let (min_y, max_y) = collider.project(y);
+        (min_x, min_y, max_x, max_y)
+    }
+
+    //Helpers
+
+    ///Two points or segments with radii: the closest points decide it.
+    fn round_round(a: &Collider, b: &Collider) -> Option<Contact> {
+        let (
+            Collider::Round {
+                a: a1,
+                b: a2,
+                radius: ar,
+            },
+            Collider::Round {
+                a: b1,
+                b: b2,
+                radius: br,
+            },
+        ) = (a, b)
+        else {
+            return None;
+        };
+
+        let (pa, pb) = closest_between_segments(*a1, *a2, *b1, *b2);
+        let d = sub(pb, pa);
+        let dist_sq = dot(d, d);
+        let reach = ar + br;
+        if dist_sq >= reach * reach {
+            return None;
+        }
+
+        let dist = dist_sq.sqrt();
+        let normal = if dist > EPSILON {
+            scale(d, 1.0 / dist)
+        } else {
+            // Cores touch, fall back to the centres, then to any direction
+            let centres = sub(b.center(), a.center());
+            let len = dot(centres, centres).sqrt();
+            if len > EPSILON {
+                scale(centres, 1.0 / len)
+            } else {
+                Vec2 { x: 1.0, y: 0.0 }
+            }
+        };
+
+        Some(Contact {
+            nx: normal.x,
+            ny: normal.y,
+            depth: reach - dist,
+        })
+    }
+
+    ///The box's face normals, the segment's normal and, for every corner, the
+    ///direction from the nearest point on the segment.
+    fn round_box_axes(round: &Collider, boxed: &Collider) -> Vec<Vec2> {
+        let (Collider::Round { a, b, .. }, Collider::Box { corners, axes }) = (round, boxed) else {
+            return Vec::new();
+        };
+
+        let mut candidates = vec![axes[0], axes[1]];
+        let along = sub(*b, *a);
+        candidates.push(Vec2 {
+            x: -along.y,
+            y: along.x,
+        });
+        for corner in corners {
+            candidates.push(sub(*corner, closest_on_segment(*a, *b, *corner)));
+        }
+
+        candidates
+            .into_iter()
+            .filter_map(|axis| {
+                let len = dot(axis, axis).sqrt();
+                (len > EPSILON).then(|| scale(axis, 1.0 / len))
+            })
+            .collect()
+    }
+
+    ///SAT: any axis with a gap means no contact, otherwise the shallowest one wins.
+    fn separating_axes(a: &Collider, b: &Collider, axes: &[Vec2]) -> Option<Contact> {
+        let mut best: Option<Contact> = None;
+
+        for axis in axes {
+            let (a_min, a_max) = a.project(*axis);
+            let (b_min, b_max) = b.project(*axis);
+
+            // Push b out whichever way is shorter
+            let forward = a_max - b_min;
+            let backward = b_max - a_min;
+            if forward <= 0.0 || backward <= 0.0 {
+                return None;
+            }
+            let (depth, sign) = if forward < backward {
+                (forward, 1.0)
+            } else {
+                (backward, -1.0)
+            };
+
+            if best.is_none_or(|contact| depth < contact.depth) {
+                best = Some(Contact {
+                    nx: axis.x * sign,
+                    ny: axis.y * sign,
+                    depth,
+                });
+            }
+        }
+
+        best
+    }
+}
+
+impl Collider {
+    fn new(shape: &Shape, pos: Vec2, angle: f32) -> Self {
+        match *shape {
+            Shape::Circle { radius } => Collider::Round {
+                a: pos,
+                b: pos,
+                radius,
+            },
+            Shape::Capsule { radius, length } => {
+                let half = scale(
+                    Vec2 {
+                        x: angle.cos(),
+                        y: angle.sin(),
+                    },
+                    length / 2.0,
+                );
+                Collider::Round {
+                    a: sub(pos, half),
+                    b: add(pos, half),
+                    radius,
+                }
+            }
+            Shape::Rectangle { w, h } => {
+                let center = Vec2 {
+                    x: pos.x + w / 2.0,
+                    y: pos.y + h / 2.0,
+                };
+                let ux = Vec2 {
+                    x: angle.cos(),
+                    y: angle.sin(),
+                };
+                let uy = Vec2 { x: -ux.y, y: ux.x };
+                let hx = scale(ux, w / 2.0);
+                let hy = scale(uy, h / 2.0);
+
+                Collider::Box {
+                    corners: [
+                        sub(sub(center, hx), hy),
+                        sub(add(center, hx), hy),
+                        add(add(center, hx), hy),
+                        add(sub(center, hx), hy),
+                    ],
+                    axes: [ux, uy],
+                }
+            }
+        }
+    }
+
+    fn center(&self) -> Vec2 {
+        match self {
+            Collider::Round { a, b, .. } => scale(add(*a, *b), 0.5),
+            Collider::Box { corners, .. } => scale(add(corners[0], corners[2]), 0.5),
+        }
+    }
+
+    fn project(&self, axis: Vec2) -> (f32, f32) {
+        match self {
+            Collider::Round { a, b, radius } => {
+                let (da, db) = (dot(*a, axis), dot(*b, axis));
+                (da.min(db) - radius, da.max(db) + radius)
+            }
+            Collider::Box { corners, .. } => {
+                corners
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), corner| {
+                        let d = dot(*corner, axis);
+                        (min.min(d), max.max(d))
+                    })
+            }
+        }
+    }
+}
+
+//Vector helpers
+
+fn add(a: Vec2, b: Vec2) -> Vec2 {
+    Vec2 {
+        x: a.x + b.x,
+        y: a.y + b.y,
+    }
+}
+
+fn sub(a: Vec2, b: Vec2) -> Vec2 {
+    Vec2 {
+        x: a.x - b.x,
+        y: a.y - b.y,
+    }
+}
+
+fn scale(v: Vec2, s: f32) -> Vec2 {
+    Vec2 {
+        x: v.x * s,
+        y: v.y * s,
+    }
+}
+
+fn dot(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.x + a.y * b.y
+}
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+///First time `origin + motion * t`, t in 0..=1, enters a circle, with the outward normal there.
+fn ray_circle(origin: Vec2, motion: Vec2, center: Vec2, radius: f32) -> Option<(f32, Vec2)> {
+    let m = sub(origin, center);
+    let a = dot(motion, motion);
+    let b = dot(m, motion);
+    let c = dot(m, m) - radius * radius;
+    if a <= EPSILON || c < 0.0 || b >= 0.0 {
+        return None; // not moving, already inside or heading away
+    }
+
+    let disc = b * b - a * c;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = (-b - disc.sqrt()) / a;
+    if !(0.0..=1.0).contains(&t) {
+        return None;
+    }
+
+    let hit = add(origin, scale(motion, t));
+    Some((t, scale(sub(hit, center), 1.0 / radius)))
+}
+
+///First time `origin + motion * t` crosses segment pq from the side `normal` faces.
+fn ray_segment(origin: Vec2, motion: Vec2, p: Vec2, q: Vec2, normal: Vec2) -> Option<(f32, Vec2)> {
+    if dot(motion, normal) >= 0.0 {
+        return None;
+    }
+
+    let edge = sub(q, p);
+    let denom = cross(motion, edge);
+    if denom.abs() <= EPSILON {
+        return None;
+    }
+    let w = sub(p, origin);
+    let t = cross(w, edge) / denom;
+    let s = cross(w, motion) / denom;
+    if !(0.0..=1.0).contains(&t) || !(0.0..=1.0).contains(&s) {
+        return None;
+    }
+    Some((t, normal))
+}
+
+fn closest_on_segment(a: Vec2, b: Vec2, p: Vec2) -> Vec2 {
+    let ab = sub(b, a);
+    let len_sq = dot(ab, ab);
+    if len_sq <= EPSILON {
+        return a;
+    }
+    let t = (dot(sub(p, a), ab) / len_sq).clamp(0.0, 1.0);
+    add(a, scale(ab, t))
+}
+
+///Closest points between segments p1q1 and p2q2, either may be a single point.
+fn closest_between_segments(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> (Vec2, Vec2) {
+    let d1 = sub(q1, p1);
+    let d2 = sub(q2, p2);
+    let r = sub(p1, p2);
+    let a = dot(d1, d1);
+    let e = dot(d2, d2);
+    let f = dot(d2, r);
+
+    if a <= EPSILON && e <= EPSILON {
+        return (p1, p2);
+    }
+    if a <= EPSILON {
+        return (p1, closest_on_segment(p2, q2, p1));
+    }
+    let c = dot(d1, r);
+    if e <= EPSILON {
+        return (closest_on_segment(p1, q1, p2), p2);
+    }
+
+    let b = dot(d1, d2);
+    let denom = a * e - b * b;
+    // Parallel segments: any s works, start from p1
+    let mut s = if denom > EPSILON {
+        ((b * f - c * e) / denom).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let mut t = (b * s + f) / e;
+    if t < 0.0 {
+        t = 0.0;
+        s = (-c / a).clamp(0.0, 1.0);
+    } else if t > 1.0 {
+        t = 1.0;
+        s = ((b - c) / a).clamp(0.0, 1.0);
+    }
+
+    (add(p1, scale(d1, s)), add(p2, scale(d2, t)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f32, y: f32) -> Vec2 {
+        Vec2 { x, y }
+    }
+
+    fn assert_contact(contact: Option<Contact>, nx: f32, ny: f32, depth: f32) {
+        let contact = contact.expect("shapes should overlap");
+        assert!(
+            (contact.nx - nx).abs() < 1e-3
+                && (contact.ny - ny).abs() < 1e-3
+                && (contact.depth - depth).abs() < 1e-3,
+            "got {contact:?}, expected ({nx}, {ny}) deep {depth}"
+        );
+    }
+
+    const SQUARE: Shape = Shape::Rectangle { w: 20.0, h: 20.0 };
+    const BALL: Shape = Shape::Circle { radius: 5.0 };
+    const STICK: Shape = Shape::Capsule {
+        radius: 5.0,
+        length: 40.0,
+    };
+
+    #[test]
+    fn circle_against_box_face_and_corner() {
+        let face = Collision::contact(&SQUARE, v(0.0, 0.0), 0.0, &BALL, v(22.0, 10.0), 0.0);
+        assert_contact(face, 1.0, 0.0, 3.0);
+
+        // Normal flips with the order
+        let face = Collision::contact(&BALL, v(22.0, 10.0), 0.0, &SQUARE, v(0.0, 0.0), 0.0);
+        assert_contact(face, -1.0, 0.0, 3.0);
+
+        // Past the corner it gets pushed away from the corner, not off a face
+        let corner = Collision::contact(&SQUARE, v(0.0, 0.0), 0.0, &BALL, v(23.0, 23.0), 0.0);
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_contact(corner, diagonal, diagonal, 5.0 - 3.0 * 2f32.sqrt());
+    }
+
+    #[test]
+    fn rotated_box_corner_into_box_face() {
+        // Turned 45 degrees about its centre, its left corner 3 into the square's right face
+        let reach = 10.0 * 2f32.sqrt();
+        let diamond = v(17.0 + reach - 10.0, 0.0);
+        let contact = Collision::contact(
+            &SQUARE,
+            v(0.0, 0.0),
+            0.0,
+            &SQUARE,
+            diamond,
+            std::f32::consts::FRAC_PI_4,
+        );
+        assert_contact(contact, 1.0, 0.0, 3.0);
+    }
+
+    #[test]
+    fn capsule_against_circle() {
+        // Standing upright, the ball beside its middle
+        let contact = Collision::contact(
+            &STICK,
+            v(0.0, 0.0),
+            std::f32::consts::FRAC_PI_2,
+            &BALL,
+            v(8.0, 10.0),
+            0.0,
+        );
+        assert_contact(contact, 1.0, 0.0, 2.0);
+
+        // Past the end only the rounded cap counts
+        let contact = Collision::contact(&STICK, v(0.0, 0.0), 0.0, &BALL, v(26.0, 0.0), 0.0);
+        assert_contact(contact, 1.0, 0.0, 4.0);
+    }
+
+    #[test]
+    fn capsule_lying_on_box() {
+        let contact = Collision::contact(&STICK, v(10.0, -3.0), 0.0, &SQUARE, v(0.0, 0.0), 0.0);
+        assert_contact(contact, 0.0, 1.0, 2.0);
+    }
+
+    #[test]
+    fn separated_shapes_have_no_contact() {
+        let origin = v(0.0, 0.0);
+        assert!(Collision::contact(&SQUARE, origin, 0.0, &BALL, v(26.0, 10.0), 0.0).is_none());
+        // Its bounds overlap the square's corner, the ball itself doesn't
+        assert!(Collision::contact(&SQUARE, origin, 0.0, &BALL, v(24.0, 24.0), 0.0).is_none());
+        assert!(Collision::contact(&STICK, origin, 0.0, &BALL, v(0.0, 11.0), 0.0).is_none());
+        assert!(Collision::contact(&STICK, v(10.0, -6.0), 0.0, &SQUARE, origin, 0.0).is_none());
+
+        // The diamond's bounds overlap the square, its corner doesn't
+        let diamond = v(20.0, 20.0);
+        let turned = std::f32::consts::FRAC_PI_4;
+        assert!(Collision::contact(&SQUARE, origin, 0.0, &SQUARE, diamond, turned).is_none());
+    }
+}
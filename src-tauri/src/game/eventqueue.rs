@@ -3,7 +3,10 @@ use crate::game::state::entityid::EntityId;
 #[derive(Debug, Clone)]
 pub enum GameEvent {
     GoalScored {
-        team_id: u8,
+        team_id: u8, // whose goal the ball went into
+    },
+    BallTouched {
+        player_id: EntityId,
     },
     TryGrab {
         player_id: EntityId,
@@ -11,9 +14,15 @@ pub enum GameEvent {
     Shoot {
         player_id: EntityId,
     },
+    Dash {
+        player_id: EntityId,
+        pos: (f32, f32),
+        angle: f32,
+    },
     Place {
         player_id: EntityId,
         pos: (f32, f32),
+        angle: f32,
     },
     Die {
         owner_id: EntityId,
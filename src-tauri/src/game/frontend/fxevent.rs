@@ -1,7 +1,19 @@
+use crate::game::{scoremanager::GoalRecord, state::entityid::EntityId};
+
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub enum FxEvent {
-    BallWallHit { pos: (f32, f32), intensity: f32 },
-    GoalScored { team_id: u8 },
+    BallWallHit {
+        pos: (f32, f32),
+        intensity: f32,
+    },
+    GoalScored {
+        goal: GoalRecord,
+    },
+    PlayerDashed {
+        player_id: EntityId,
+        pos: (f32, f32),
+        angle: f32,
+    },
     // PlaySound {
     //     kind: SoundKind,
     //     pos: (f32, f32),
@@ -7,15 +7,17 @@ use crate::game::{
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct GamePayload {
-    render_states: Vec<RenderState>,
-    score_manager: ScoreManager,
-    game_phase: GamePhase,
+    pub tick: u64,
+    pub render_states: Vec<RenderState>,
+    pub score_manager: ScoreManager,
+    pub game_phase: GamePhase,
     pub fx_events: Vec<FxEvent>,
     pub lobby_state: LobbyState,
 }
 
 impl GamePayload {
     pub fn new(
+        tick: u64,
         states: &Vec<State>,
         score_manager: &ScoreManager,
         game_phase: &GamePhase,
@@ -25,6 +27,7 @@ impl GamePayload {
         let render_states: Vec<RenderState> = states.iter().map(RenderState::from).collect();
 
         Self {
+            tick,
             render_states,
             score_manager: score_manager.clone(),
             game_phase: game_phase.clone(),
@@ -37,6 +40,7 @@ impl GamePayload {
 impl From<&GameManager> for GamePayload {
     fn from(gm: &GameManager) -> Self {
         GamePayload::new(
+            gm.tick,
             &gm.world.entities,
             &gm.score_manager,
             &gm.phase,
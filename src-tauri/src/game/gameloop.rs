@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::game::frontend::gamepayload::GamePayload;
+use crate::game::gamemanager::{GameManager, DEFAULT_TICK_RATE};
+use crate::network::serverevent::ServerEvent;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LoopConfig {
+    /// Simulation ticks per second
+    pub tick_rate: f32,
+    /// WorldSnapshot broadcasts per second, capped at the tick rate
+    pub snapshot_rate: f32,
+    /// Most ticks run in one go when the loop falls behind, the rest of the backlog is dropped
+    pub max_catch_up_ticks: u32,
+}
+
+impl Default for LoopConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: DEFAULT_TICK_RATE,
+            snapshot_rate: DEFAULT_TICK_RATE,
+            max_catch_up_ticks: 5,
+        }
+    }
+}
+
+impl LoopConfig {
+    pub fn ticks_per_snapshot(&self) -> u64 {
+        (self.tick_rate / self.snapshot_rate.min(self.tick_rate))
+            .round()
+            .max(1.0) as u64
+    }
+}
+
+///Fixed timestep loop: real time goes into an accumulator and the simulation
+///always advances in whole ticks of 1 / tick_rate, no matter how slow the host is.
+pub fn start_game_loop(gm: Arc<Mutex<GameManager>>, config: LoopConfig) {
+    thread::spawn(move || {
+        let tick_duration = Duration::from_secs_f32(1.0 / config.tick_rate);
+        let ticks_per_snapshot = config.ticks_per_snapshot();
+
+        gm.lock().unwrap().set_tick_rate(config.tick_rate);
+
+        let mut accumulator = Duration::ZERO;
+        let mut last_time = Instant::now();
+        let mut last_snapshot_tick = 0;
+
+        loop {
+            let now = Instant::now();
+            accumulator += now - last_time;
+            last_time = now;
+
+            {
+                // lock the GameManager inside the loop
+                let mut gm = gm.lock().unwrap();
+
+                let mut ticks_run = 0;
+                while accumulator >= tick_duration && ticks_run < config.max_catch_up_ticks {
+                    gm.update(); // apply input & physics
+                    accumulator -= tick_duration;
+                    ticks_run += 1;
+                }
+
+                // Too far behind to catch up, slow down instead of spiralling
+                if accumulator >= tick_duration {
+                    println!(
+                        "Game loop fell behind, dropping {} ticks",
+                        accumulator.as_secs_f32() / tick_duration.as_secs_f32()
+                    );
+                    accumulator = Duration::ZERO;
+                }
+
+                if gm.tick - last_snapshot_tick >= ticks_per_snapshot {
+                    last_snapshot_tick = gm.tick;
+
+                    let mut payload = GamePayload::from(&*gm);
+                    payload.fx_events = gm.drain_fx_events();
+
+                    if let Some(sender) = gm.snapshot_tx.as_ref() {
+                        let _ = sender.send(ServerEvent::WorldSnapshot { snapshot: payload });
+                    }
+                }
+            }
+
+            // sleep until the next tick is due
+            if accumulator < tick_duration {
+                thread::sleep(tick_duration - accumulator);
+            }
+        }
+    });
+}
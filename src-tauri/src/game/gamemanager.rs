@@ -1,31 +1,63 @@
+use crate::game::arena::Arena;
+use crate::game::bot::{BotDifficulty, BotManager};
 use crate::game::eventqueue::{EventQueue, GameEvent};
 use crate::game::frontend::fxevent::FxEvent;
-use crate::game::input::InputFrame;
+use crate::game::input::{inputqueue::InputQueue, InputFrame};
+use crate::game::matchrules::MatchRules;
 use crate::game::physics::Physics;
-use crate::game::scoremanager::{ScoreManager, Team};
+use crate::game::replay::{Replay, ReplayPlayer, ReplayStatus, REPLAY_VERSION};
+use crate::game::scoremanager::{MatchOutcome, ScoreManager, Team};
 use crate::game::spawnmanager::SpawnManager;
 
 use crate::game::state::entityid::EntityId;
+use crate::game::state::lobbyplayer::LobbyPlayer;
+use crate::game::state::renderstate::RenderState;
+use crate::game::state::State;
+use crate::game::team::{spawnpattern::SpawnPattern, TeamManager};
 use crate::game::world::World;
 use crate::network::clientid::ClientId;
 use crate::network::clientrequest::ClientRequest;
 use crate::network::serverevent::ServerEvent;
+use rand::{rngs::StdRng, SeedableRng};
 use std::collections::HashMap;
+use std::path::PathBuf;
 
-use tauri::window::Color;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
 pub enum GamePhase {
     Waiting,
-    Countdown { time_left: f32 },
+    Countdown {
+        time_left: f32,
+    },
     Playing,
-    GameOver,
+    /// Play runs on after a goal, then the team whose goal it was kicks off
+    Celebrating {
+        conceded: u8,
+        time_left: f32,
+    },
+    /// Shows the result for `time_left` seconds, then back to the lobby
+    GameOver {
+        winner: Option<u8>,
+        time_left: f32,
+    },
+}
+
+impl GamePhase {
+    ///Players are in control and the world is moving.
+    pub fn is_live(&self) -> bool {
+        matches!(self, GamePhase::Playing | GamePhase::Celebrating { .. })
+    }
 }
 
 pub struct GameManager {
     pub world: World,
-    pending_inputs: HashMap<EntityId, InputFrame>,
+    input_queues: HashMap<EntityId, InputQueue>,
+    /// Which client added each player, only they get to steer or remove it
+    owners: HashMap<EntityId, ClientId>,
+    /// Clients that dropped, with the seconds they have left to come back
+    disconnected: Vec<(ClientId, f32)>,
+    pub reconnect_grace: f32, // seconds a dropped client's players are kept
     pub snapshot_tx: Option<UnboundedSender<ServerEvent>>,
     pub client_request_rx: Option<UnboundedReceiver<(ClientRequest, ClientId)>>,
     pub phase: GamePhase,
@@ -33,38 +65,52 @@ pub struct GameManager {
     fx_events: Vec<FxEvent>,
     pub score_manager: ScoreManager,
     pub spawn_manager: SpawnManager,
+    pub team_manager: TeamManager,
+    pub bots: BotManager,
+    pub tick: u64,
+    dt: f32,
+
+    //Replays
+    pub replay_dir: Option<PathBuf>, // record matches into here when set
+    recording: Option<Replay>,
+    playback: Option<ReplayPlayer>,
+    seed: u64,
+    pub rng: StdRng,
 }
 
 pub const GRAB_RADIUS: f32 = 32.0;
-pub const DT: f32 = 0.016; // ~0.016
+pub const SHOT_POWER: f32 = 1000.0;
+pub const DEFAULT_TICK_RATE: f32 = 60.0; // Hz
+pub const CELEBRATION_TIME: f32 = 2.0; // seconds
+pub const KICKOFF_COUNTDOWN: f32 = 3.0; // seconds
+pub const DEFAULT_RECONNECT_GRACE: f32 = 30.0; // seconds
 
 impl GameManager {
-    pub fn new(width: f32, height: f32) -> Self {
-        let score_manager = ScoreManager::new(
-            Team {
-                id: 0,
-                name: "Blue".into(),
-                color: Color(0, 0, 255, 255),
-                score: 0,
-            },
-            Team {
-                id: 1,
-                name: "Red".into(),
-                color: Color(255, 0, 0, 255),
-                score: 0,
-            },
-        );
+    pub fn new(arena: Arena) -> Self {
+        let score_manager = ScoreManager::new(arena.teams.iter().map(Team::from).collect());
 
         let gm = Self {
             world: World::new(),
-            pending_inputs: HashMap::new(),
+            input_queues: HashMap::new(),
+            owners: HashMap::new(),
+            disconnected: Vec::new(),
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
             snapshot_tx: None,
             client_request_rx: None,
             phase: GamePhase::Waiting,
             event_queue: EventQueue::new(),
             fx_events: Vec::new(),
             score_manager: score_manager,
-            spawn_manager: SpawnManager::new(width, height),
+            team_manager: TeamManager::new(arena.team_ids()),
+            spawn_manager: SpawnManager::new(arena),
+            bots: BotManager::new(),
+            tick: 0,
+            dt: 1.0 / DEFAULT_TICK_RATE,
+            replay_dir: None,
+            recording: None,
+            playback: None,
+            seed: 0,
+            rng: StdRng::seed_from_u64(0),
         };
 
         gm
@@ -79,7 +125,25 @@ impl GameManager {
         self.client_request_rx = client_request_rx;
     }
 
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.dt = 1.0 / tick_rate;
+    }
+
+    pub fn tick_rate(&self) -> f32 {
+        1.0 / self.dt
+    }
+
     pub fn handle_client_request(&mut self, request: ClientRequest, id: ClientId) {
+        if let Err(reason) = self.authorize(&request, id) {
+            println!("Rejected request from client {:?}: {}", id, reason);
+            return;
+        }
+
+        // Tagged with the tick that will simulate it
+        if let Some(replay) = &mut self.recording {
+            replay.record(replay.length + 1, id, &request);
+        }
+
         match request {
             ClientRequest::Add { team_id } => {
                 self.try_get_new_player(id, team_id);
@@ -87,72 +151,446 @@ impl GameManager {
             ClientRequest::Remove { id } => {
                 self.remove_player(id);
             }
-            ClientRequest::Input { entity_id, frame } => {
-                self.queue_input(entity_id, frame);
+            ClientRequest::Input {
+                entity_id,
+                frame,
+                recent,
+            } => {
+                for mut frame in recent.into_iter().chain([frame]) {
+                    frame.clamp_axes();
+                    self.queue_input(entity_id, frame);
+                }
+            }
+            ClientRequest::Disconnected => self.client_disconnected(id),
+            ClientRequest::Reconnected => self.client_reconnected(id),
+            ClientRequest::SetName { entity_id, name } => {
+                if let Err(e) = self.set_player_name(entity_id, &name) {
+                    println!("Couldn't rename player {}: {}", entity_id.0, e);
+                }
+            }
+            ClientRequest::SetReady { entity_id, ready } => {
+                if let Err(e) = self.set_ready(entity_id, ready) {
+                    println!("Couldn't ready player {}: {}", entity_id.0, e);
+                }
+            }
+            ClientRequest::SwitchTeam { entity_id, team_id } => {
+                if let Err(e) = self.switch_team(entity_id, team_id) {
+                    println!("Couldn't switch player {} teams: {}", entity_id.0, e);
+                }
             }
             _ => {}
         }
     }
 
+    ///Clients only get to steer, set up and remove the players they added. The host can
+    ///remove anyone's player, but nobody gets to remove walls, balls or bots this way.
+    fn authorize(&self, request: &ClientRequest, client_id: ClientId) -> Result<(), String> {
+        let (entity_id, host_allowed) = match request {
+            ClientRequest::Input { entity_id, .. }
+            | ClientRequest::SetName { entity_id, .. }
+            | ClientRequest::SetReady { entity_id, .. }
+            | ClientRequest::SwitchTeam { entity_id, .. } => (*entity_id, false),
+            ClientRequest::Remove { id } => (*id, true),
+            ClientRequest::Disconnected | ClientRequest::Reconnected
+                if client_id == ClientId::HOST =>
+            {
+                return Err("the host can't drop out".into());
+            }
+            _ => return Ok(()),
+        };
+
+        match self.owners.get(&entity_id) {
+            Some(owner) if *owner == client_id => Ok(()),
+            Some(_) if host_allowed && client_id == ClientId::HOST => Ok(()),
+            Some(owner) => Err(format!(
+                "player {} belongs to client {}",
+                entity_id.0, owner.0
+            )),
+            None => Err(format!("entity {} isn't a client's player", entity_id.0)),
+        }
+    }
+
     pub fn queue_input(&mut self, player: EntityId, frame: InputFrame) {
-        self.pending_inputs.insert(player, frame);
+        self.input_queues.entry(player).or_default().push(frame);
     }
 
-    pub fn set_game_settings(&mut self, player_count: u8, target_score: u8) {
+    ///Can change with players already waiting, who then have to ready up again.
+    pub fn set_game_settings(&mut self, player_count: u8, rules: MatchRules) -> Result<(), String> {
+        if !self.in_lobby() {
+            return Err("settings can only be changed in the lobby".into());
+        }
+        rules.validate()?;
+        let joined = self.world.curr_player_count();
+        if usize::from(player_count) < joined {
+            return Err(format!("{} players are already in the lobby", joined));
+        }
+
         self.world.set_expected_player_count(player_count.into());
-        self.score_manager.set_target_score(target_score);
+        self.score_manager.set_rules(rules.clone());
+        self.world.lobby_state_mut().unready();
+        self.send_event(ServerEvent::LobbySettings {
+            player_count,
+            rules,
+        });
+        self.broadcast_lobby();
+        Ok(())
     }
 
-    pub fn start_game(&mut self) {
+    ///Swaps the map between matches, players already in the lobby keep their teams.
+    pub fn select_map(&mut self, arena: Arena) -> Result<(), String> {
+        if !self.in_lobby() {
+            return Err("the map can only be changed in the lobby".into());
+        }
+
+        for player in &self.world.get_lobby_state().players {
+            let team_id = player.team_id.unwrap_or(0);
+            if arena.spawn_for(team_id).is_none() {
+                return Err(format!("{} has no team {}", arena.name, team_id));
+            }
+        }
+
+        self.team_manager.set_teams(arena.team_ids());
+        self.score_manager
+            .set_teams(arena.teams.iter().map(Team::from).collect());
+        self.spawn_manager.set_arena(arena);
+        self.world.lobby_state_mut().unready();
+        self.broadcast_lobby();
+        Ok(())
+    }
+
+    pub fn set_team_formation(
+        &mut self,
+        team_id: u8,
+        spawn_pattern: SpawnPattern,
+        max_players: usize,
+    ) -> Result<(), String> {
+        self.team_manager
+            .set_formation(team_id, spawn_pattern, max_players)
+    }
+
+    pub fn start_game(&mut self) -> Result<(), String> {
         if self.phase != GamePhase::Waiting {
-            println!("Cannot start game, not in waiting phase");
-            return;
+            return Err("Cannot start game, not in waiting phase".into());
         } else if !self.world.reached_expected_player_count() {
-            println!("Cannot start game, not enough players");
-            return;
+            return Err("Cannot start game, not enough players".into());
+        } else if !self.world.get_lobby_state().all_ready() {
+            return Err("Cannot start game, not everyone is ready".into());
         }
 
-        self.phase = GamePhase::Countdown { time_left: 3.0 };
+        self.phase = GamePhase::Countdown {
+            time_left: KICKOFF_COUNTDOWN,
+        };
+        self.score_manager.start_match();
         self.spawn_manager.spawn_states(&mut self.world);
+        self.spawn_manager
+            .reset_states(&mut self.world, &self.team_manager);
+        self.reseed(rand::random());
+        // Only the latest of whatever piled up in the lobby counts
+        for queue in self.input_queues.values_mut() {
+            queue.settle();
+        }
+        self.begin_recording();
+        Ok(())
     }
 
     pub fn end_game(&mut self) {
+        self.finish_recording();
         self.spawn_manager.remove_non_player(&mut self.world);
         self.score_manager.reset();
         self.phase = GamePhase::Waiting;
     }
 
     pub fn quit_game(&mut self) {
-        self.spawn_manager.remove_all(&mut self.world);
+        self.finish_recording();
+        self.spawn_manager
+            .remove_all(&mut self.world, &mut self.team_manager);
+        self.bots.clear();
+        self.input_queues.clear();
+        self.owners.clear();
+        self.disconnected.clear();
         self.score_manager.reset();
         self.phase = GamePhase::Waiting;
     }
 
     pub fn try_get_new_player(&mut self, client_id: ClientId, team_id: u8) {
-        let id = self.spawn_manager.try_add_player(&mut self.world, team_id);
+        let id =
+            self.spawn_manager
+                .try_add_player(&mut self.world, &mut self.team_manager, team_id);
         if let Some(new_id) = id {
             println!("Added new player with id {:?}", new_id);
+            self.owners.insert(new_id, client_id);
+            if let Some(player) = self.world.lobby_player_mut(new_id) {
+                player.client_id = Some(client_id);
+            }
 
-            // Broadcast the "PlayerAdded" event
-            if let Some(tx) = &self.snapshot_tx {
-                let _ = tx.send(ServerEvent::AddedPlayer {
+            // Broadcast the "PlayerAdded" event, replayed joins aren't real clients
+            if self.playback.is_none() {
+                self.send_event(ServerEvent::AddedPlayer {
                     entity: new_id,
                     client: client_id,
                 });
             }
+            self.broadcast_lobby();
+        }
+    }
+
+    ///Puts a bot in a lobby slot, on the emptiest team unless `team_id` says otherwise.
+    pub fn add_bot(
+        &mut self,
+        team_id: Option<u8>,
+        difficulty: BotDifficulty,
+    ) -> Result<EntityId, String> {
+        if !self.in_lobby() {
+            return Err("bots can only be added in the lobby".into());
+        }
+        let team_id = team_id
+            .or_else(|| self.team_manager.open_team())
+            .ok_or("every team is full")?;
+        let player_id = self
+            .spawn_manager
+            .try_add_player(&mut self.world, &mut self.team_manager, team_id)
+            .ok_or("no free slot for a bot")?;
+
+        if let Some(player) = self.world.lobby_player_mut(player_id) {
+            player.name = format!("{:?} Bot {}", difficulty, player_id.0);
+            player.ready = true;
+            player.bot = Some(difficulty);
         }
+        self.bots.add(player_id, difficulty);
+        self.broadcast_lobby();
+        Ok(player_id)
+    }
+
+    pub fn remove_bot(&mut self, player_id: EntityId) -> Result<(), String> {
+        if !self.bots.is_bot(player_id) {
+            return Err(format!("player {} isn't a bot", player_id.0));
+        }
+        self.remove_player(player_id);
+        Ok(())
+    }
+
+    ///Bots in every slot nobody has taken yet, returns how many joined.
+    pub fn fill_with_bots(&mut self, difficulty: BotDifficulty) -> usize {
+        let mut added = 0;
+        while !self.world.reached_expected_player_count() && self.add_bot(None, difficulty).is_ok()
+        {
+            added += 1;
+        }
+        added
     }
 
     pub fn remove_player(&mut self, id: EntityId) {
-        self.spawn_manager.remove_player(&mut self.world, id);
-        //self.update_player_list();
+        self.release_ball(id);
+        self.spawn_manager
+            .remove_player(&mut self.world, &mut self.team_manager, id);
+        self.bots.remove(id);
+        self.input_queues.remove(&id);
+        self.owners.remove(&id);
+        self.broadcast_lobby();
+    }
+
+    //Lobby
+
+    fn in_lobby(&self) -> bool {
+        self.phase == GamePhase::Waiting && self.playback.is_none()
+    }
+
+    fn lobby_player(&mut self, player_id: EntityId) -> Result<&mut LobbyPlayer, String> {
+        if !self.in_lobby() {
+            return Err("the match has already started".into());
+        }
+        self.world
+            .lobby_player_mut(player_id)
+            .ok_or_else(|| format!("no player {} in the lobby", player_id.0))
+    }
+
+    fn set_player_name(&mut self, player_id: EntityId, name: &str) -> Result<(), String> {
+        let name = LobbyPlayer::clean_name(name)?;
+        self.lobby_player(player_id)?.name = name;
+        self.broadcast_lobby();
+        Ok(())
+    }
+
+    fn set_ready(&mut self, player_id: EntityId, ready: bool) -> Result<(), String> {
+        self.lobby_player(player_id)?.ready = ready;
+        self.broadcast_lobby();
+        Ok(())
+    }
+
+    ///Moves a player to another team, where they have to ready up again.
+    fn switch_team(&mut self, player_id: EntityId, team_id: u8) -> Result<(), String> {
+        self.lobby_player(player_id)?;
+        self.spawn_manager.switch_team(
+            &mut self.world,
+            &mut self.team_manager,
+            player_id,
+            team_id,
+        )?;
+        if let Some(player) = self.world.lobby_player_mut(player_id) {
+            player.ready = player.bot.is_some();
+        }
+        self.broadcast_lobby();
+        Ok(())
+    }
+
+    ///Evens the teams out, returns how many players got moved.
+    pub fn balance_teams(&mut self) -> Result<usize, String> {
+        if !self.in_lobby() {
+            return Err("teams can only be balanced in the lobby".into());
+        }
+        let mut moved = 0;
+        while let Some((player_id, team_id)) = self.team_manager.unbalanced_move() {
+            self.switch_team(player_id, team_id)?;
+            moved += 1;
+        }
+        Ok(moved)
+    }
+
+    ///Takes a client's player out of the lobby and tells the client why.
+    pub fn kick_player(&mut self, player_id: EntityId) -> Result<(), String> {
+        if !self.in_lobby() {
+            return Err("players can only be kicked in the lobby".into());
+        }
+        let client = *self
+            .owners
+            .get(&player_id)
+            .ok_or_else(|| format!("player {} isn't a client's player", player_id.0))?;
+        if client == ClientId::HOST {
+            return Err("the host can't kick their own players".into());
+        }
+
+        println!("Kicking player {:?} of client {:?}", player_id, client);
+        self.remove_player(player_id);
+        self.send_event(ServerEvent::Kicked {
+            entity: player_id,
+            client,
+        });
+        Ok(())
+    }
+
+    fn send_event(&self, event: ServerEvent) {
+        if let Some(tx) = &self.snapshot_tx {
+            let _ = tx.send(event);
+        }
+    }
+
+    ///Tells every client who's in the lobby now, replays keep to themselves.
+    fn broadcast_lobby(&self) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.send_event(ServerEvent::LobbyUpdated {
+            lobby: self.world.get_lobby_state().clone(),
+        });
+    }
+
+    ///Leaves the client's players standing where they are, marked disconnected,
+    ///until the client comes back or `reconnect_grace` runs out.
+    fn client_disconnected(&mut self, client_id: ClientId) {
+        let players = self.players_of(client_id);
+        if players.is_empty() {
+            return;
+        }
+        println!(
+            "Client {:?} disconnected, keeping {:?} for {}s",
+            client_id, players, self.reconnect_grace
+        );
+
+        for player_id in players {
+            self.release_ball(player_id);
+            self.input_queues.remove(&player_id);
+            if let Some(controller) = self
+                .world
+                .grab_entity(player_id)
+                .and_then(|state| state.player_controller.as_mut())
+            {
+                controller.input = InputFrame::new();
+            }
+            if let Some(player) = self.world.lobby_player_mut(player_id) {
+                player.disconnected = true;
+            }
+        }
+        self.disconnected.retain(|(id, _)| *id != client_id);
+        self.disconnected.push((client_id, self.reconnect_grace));
+        self.broadcast_lobby();
+    }
+
+    ///The client's players, score and all, are theirs again.
+    fn client_reconnected(&mut self, client_id: ClientId) {
+        if !self.disconnected.iter().any(|(id, _)| *id == client_id) {
+            return;
+        }
+        println!("Client {:?} reconnected", client_id);
+        self.disconnected.retain(|(id, _)| *id != client_id);
+        for player_id in self.players_of(client_id) {
+            if let Some(player) = self.world.lobby_player_mut(player_id) {
+                player.disconnected = false;
+            }
+        }
+        self.broadcast_lobby();
+    }
+
+    ///Counts down dropped clients' grace windows, removing their players once one runs out.
+    fn expire_disconnected(&mut self, dt: f32) {
+        let mut expired = Vec::new();
+        self.disconnected.retain_mut(|(client_id, time_left)| {
+            *time_left -= dt;
+            if *time_left > 0.0 {
+                return true;
+            }
+            expired.push(*client_id);
+            false
+        });
+
+        for client_id in expired {
+            println!(
+                "Client {:?} didn't come back, removing their players",
+                client_id
+            );
+            for player_id in self.players_of(client_id) {
+                self.remove_player(player_id);
+            }
+        }
+    }
+
+    ///Every player `client_id` added, sorted so they get removed the same way every run.
+    fn players_of(&self, client_id: ClientId) -> Vec<EntityId> {
+        let mut players: Vec<EntityId> = self
+            .owners
+            .iter()
+            .filter(|(_, owner)| **owner == client_id)
+            .map(|(player_id, _)| *player_id)
+            .collect();
+        players.sort_unstable_by_key(|player_id| player_id.0);
+        players
+    }
+
+    ///Drops the ball where it is if `player_id` has it.
+    fn release_ball(&mut self, player_id: EntityId) {
+        let Some(ball_id) = self.spawn_manager.get_ball_id() else {
+            return;
+        };
+        if let Some((ball, player)) = self.world.grab_two_entities(ball_id, player_id) {
+            if ball.held_by == Some(player_id) {
+                ball.held_by = None;
+                player.set_holding(false);
+            }
+        }
     }
 
     pub fn drain_fx_events(&mut self) -> Vec<FxEvent> {
         std::mem::take(&mut self.fx_events)
     }
 
+    ///Advances the simulation by exactly one fixed tick of `dt`.
     pub fn update(&mut self) {
+        if self.playback.is_some() {
+            self.update_playback();
+            return;
+        }
+
+        self.tick += 1;
+
         //Check for network input
         if let Some(mut rx) = self.client_request_rx.take() {
             while let Ok(client_request) = rx.try_recv() {
@@ -161,16 +599,47 @@ impl GameManager {
             self.client_request_rx = Some(rx);
         }
 
+        if let Some(replay) = &mut self.recording {
+            replay.length += 1;
+        }
+
+        self.simulate();
+    }
+
+    ///Everything a tick does once its requests are in, shared by live play and replays.
+    fn simulate(&mut self) {
+        let dt = self.dt;
+        self.expire_disconnected(dt);
+
+        let mut round_over = None;
+
         //Check Events
         for event in self.event_queue.drain() {
             match event {
                 GameEvent::GoalScored { team_id } => {
-                    if self.score_manager.add_point(team_id) {
-                        self.phase = GamePhase::GameOver;
+                    // The ball sits in the net until the kickoff
+                    if !self.score_manager.can_score || round_over.is_some() {
+                        continue;
+                    }
+                    let goal = self.score_manager.concede(team_id);
+                    self.fx_events.push(FxEvent::GoalScored { goal });
+                    round_over = self
+                        .score_manager
+                        .check_goal()
+                        .map(|outcome| (outcome, Some(team_id)));
+                    self.phase = GamePhase::Celebrating {
+                        conceded: team_id,
+                        time_left: CELEBRATION_TIME,
+                    };
+                }
+                GameEvent::BallTouched { player_id } => {
+                    if let Some(team_id) = self
+                        .world
+                        .grab_entity(player_id)
+                        .and_then(|player| player.team_id)
+                    {
+                        self.score_manager.touch(player_id, team_id);
                     }
-                    self.spawn_manager.reset_states(&mut self.world);
-                    self.score_manager.enable_score();
-                    self.fx_events.push(FxEvent::GoalScored { team_id });
                 }
                 GameEvent::TryGrab { player_id } => {
                     if let Some(ball_id) = self.spawn_manager.get_ball_id() {
@@ -178,7 +647,7 @@ impl GameManager {
                             self.world.grab_two_entities(ball_id, player_id)
                         {
                             if ball.held_by.is_some() {
-                                return;
+                                continue;
                             }
                             let dx = ball.physics_state.pos.x - player.physics_state.pos.x;
                             let dy = ball.physics_state.pos.y - player.physics_state.pos.y;
@@ -186,6 +655,9 @@ impl GameManager {
                             if dx * dx + dy * dy < GRAB_RADIUS.powi(2) {
                                 ball.held_by = Some(player_id);
                                 player.set_holding(true);
+                                if let Some(team_id) = player.team_id {
+                                    self.score_manager.touch(player_id, team_id);
+                                }
                             }
                         }
                     }
@@ -198,7 +670,7 @@ impl GameManager {
                             // Only shoot if this player is actually holding the ball
                             if ball.held_by != Some(player_id) {
                                 println!("Ball held by: {:?}, cannot shoot", ball.held_by);
-                                return; // not holding, can't shoot
+                                continue; // not holding, can't shoot
                             }
 
                             // Release the ball and apply impulse
@@ -207,14 +679,32 @@ impl GameManager {
                             Physics::apply_impulse(
                                 &mut ball.physics_state,
                                 player.physics_state.angle,
-                                1000.0,
+                                SHOT_POWER,
                             );
+                            if let Some(team_id) = player.team_id {
+                                self.score_manager.touch(player_id, team_id);
+                            }
                         }
                     }
                 }
-                GameEvent::Place { player_id, pos } => {
+                GameEvent::Dash {
+                    player_id,
+                    pos,
+                    angle,
+                } => {
+                    self.fx_events.push(FxEvent::PlayerDashed {
+                        player_id,
+                        pos,
+                        angle,
+                    });
+                }
+                GameEvent::Place {
+                    player_id,
+                    pos,
+                    angle,
+                } => {
                     self.spawn_manager
-                        .add_brick(&mut self.world, pos, player_id);
+                        .add_brick(&mut self.world, pos, angle, player_id);
                     if let Some(player) = &mut self.world.grab_entity(player_id) {
                         println!("Player {:?} placed a brick", player_id);
                         player.player_controller.as_mut().unwrap().add_brick();
@@ -229,10 +719,23 @@ impl GameManager {
             }
         }
 
+        if let Some((outcome, conceded)) = round_over {
+            self.finish_round(outcome, conceded);
+            return;
+        }
+
+        if self.phase.is_live() {
+            for (player_id, frame) in self.bots.think(&self.world, dt, &mut self.rng) {
+                self.queue_input(player_id, frame);
+            }
+        }
+        self.consume_inputs(self.phase.is_live());
+
         //GamePhase
         match &mut self.phase {
+            // Inputs still get used up, the players just don't move until the kickoff
             GamePhase::Countdown { time_left } => {
-                *time_left -= DT;
+                *time_left -= dt;
 
                 if *time_left <= 0.0 {
                     self.phase = GamePhase::Playing;
@@ -240,27 +743,505 @@ impl GameManager {
             }
 
             GamePhase::Playing => {
-                //Apply physics
-                for (player_id, frame) in self.pending_inputs.drain() {
-                    if let Some(state) = self
-                        .world
-                        .entities
-                        .iter_mut()
-                        .find(|s| s.entity_id == player_id)
-                    {
-                        if let Some(controller) = &mut state.player_controller {
-                            controller.input = frame;
-                        }
-                    }
+                if let Some(outcome) = self.score_manager.tick_clock(dt) {
+                    self.finish_round(outcome, None);
+                    return;
                 }
 
-                Physics::update(&mut self.world, DT, &mut self.event_queue);
+                //Apply physics
+                Physics::update(&mut self.world, dt, &mut self.event_queue);
+            }
+
+            GamePhase::Celebrating {
+                conceded,
+                time_left,
+            } => {
+                *time_left -= dt;
+
+                if *time_left <= 0.0 {
+                    let conceded = *conceded;
+                    self.kickoff(Some(conceded));
+                } else {
+                    Physics::update(&mut self.world, dt, &mut self.event_queue);
+                }
             }
 
             GamePhase::Waiting => { /* do nothing */ }
-            GamePhase::GameOver => {
-                self.end_game();
+            GamePhase::GameOver { time_left, .. } => {
+                *time_left -= dt;
+
+                if *time_left <= 0.0 {
+                    self.end_game();
+                }
+            }
+        }
+    }
+
+    ///Takes exactly one frame off every player's queue, handing it to their controller if `apply`.
+    fn consume_inputs(&mut self, apply: bool) {
+        for (player_id, queue) in self.input_queues.iter_mut() {
+            let frame = queue.next();
+            if !apply {
+                continue;
+            }
+            if let Some(controller) = self
+                .world
+                .grab_entity(*player_id)
+                .and_then(|state| state.player_controller.as_mut())
+            {
+                controller.input = frame.clone();
+            }
+        }
+    }
+
+    ///Back into formation for a countdown, `possession`'s closest player starts on the ball.
+    fn kickoff(&mut self, possession: Option<u8>) {
+        self.spawn_manager.next_ball_spawn();
+        self.spawn_manager
+            .reset_states(&mut self.world, &self.team_manager);
+        if let Some(team_id) = possession {
+            if let Some(player_id) =
+                self.spawn_manager
+                    .give_possession(&mut self.world, &self.team_manager, team_id)
+            {
+                self.score_manager.touch(player_id, team_id);
+            }
+        }
+        self.score_manager.enable_score();
+        self.phase = GamePhase::Countdown {
+            time_left: KICKOFF_COUNTDOWN,
+        };
+    }
+
+    ///A round won on a goal still gets its celebration, and the team that conceded it
+    ///kicks off the next one.
+    fn finish_round(&mut self, outcome: MatchOutcome, conceded: Option<u8>) {
+        match outcome {
+            MatchOutcome::NextRound { .. } => match conceded {
+                Some(conceded) => {
+                    // The new round's scoring opens at its kickoff
+                    self.score_manager.can_score = false;
+                    self.phase = GamePhase::Celebrating {
+                        conceded,
+                        time_left: CELEBRATION_TIME,
+                    };
+                }
+                None => self.kickoff(None),
+            },
+            MatchOutcome::Over { winner } => {
+                println!("Game over, winner: {:?}", winner);
+                self.phase = GamePhase::GameOver {
+                    winner,
+                    time_left: self.score_manager.rules.game_over_delay,
+                };
+            }
+        }
+    }
+
+    //Replays
+
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    fn begin_recording(&mut self) {
+        if self.replay_dir.is_none() || self.playback.is_some() {
+            return;
+        }
+
+        // Inputs that arrived before the start haven't been applied yet
+        let inputs = self
+            .world
+            .entities
+            .iter()
+            .filter_map(|s| {
+                let controller = s.player_controller.as_ref()?;
+                let frame = self
+                    .input_queues
+                    .get(&s.entity_id)
+                    .map_or(&controller.input, |queue| queue.current());
+                Some((s.entity_id, frame.clone()))
+            })
+            .collect();
+
+        self.recording = Some(Replay {
+            version: REPLAY_VERSION,
+            tick_rate: 1.0 / self.dt,
+            seed: self.seed,
+            entities: self.world.entities.iter().map(RenderState::from).collect(),
+            lobby_state: self.world.get_lobby_state().clone(),
+            entity_ids: self.world.entity_ids().clone(),
+            ball_id: self.spawn_manager.get_ball_id(),
+            ball_start: self.spawn_manager.get_ball_start(),
+            arena: self.spawn_manager.arena().clone(),
+            teams: self.team_manager.clone(),
+            bots: self.bots.clone(),
+            score_manager: self.score_manager.clone(),
+            phase: self.phase.clone(),
+            inputs,
+            owners: self
+                .owners
+                .iter()
+                .map(|(id, owner)| (*id, *owner))
+                .collect(),
+            disconnected: self.disconnected.clone(),
+            requests: Vec::new(),
+            length: 0,
+        });
+    }
+
+    fn finish_recording(&mut self) {
+        let Some(replay) = self.recording.take() else {
+            return;
+        };
+        let Some(dir) = &self.replay_dir else {
+            return;
+        };
+
+        match replay.save(dir) {
+            Ok(path) => println!("Saved replay to {}", path.display()),
+            Err(e) => eprintln!("Failed to save replay: {}", e),
+        }
+    }
+
+    ///Takes over the world with a recorded match until stop_replay.
+    pub fn play_replay(&mut self, replay: Replay) {
+        self.finish_recording();
+        let (live_dt, live_arena, live_teams) = match self.playback.take() {
+            Some(player) => (player.live_dt, player.live_arena, player.live_teams),
+            None => (
+                self.dt,
+                self.spawn_manager.arena().clone(),
+                self.team_manager.clone(),
+            ),
+        };
+        self.playback = Some(ReplayPlayer::new(replay, live_dt, live_arena, live_teams));
+        self.restore_replay_start();
+    }
+
+    pub fn stop_replay(&mut self) {
+        let Some(player) = self.playback.take() else {
+            return;
+        };
+        self.dt = player.live_dt;
+        self.spawn_manager.set_arena(player.live_arena);
+        self.team_manager = player.live_teams;
+        self.quit_game();
+    }
+
+    pub fn pause_replay(&mut self, paused: bool) {
+        if let Some(player) = &mut self.playback {
+            player.set_paused(paused);
+        }
+    }
+
+    pub fn set_replay_speed(&mut self, speed: f32) {
+        if let Some(player) = &mut self.playback {
+            player.set_speed(speed);
+        }
+    }
+
+    ///Jumps to `position` ticks into the match. Going back replays from the start.
+    pub fn seek_replay(&mut self, position: u64) {
+        let Some(player) = &self.playback else {
+            return;
+        };
+        if position < player.position() {
+            self.restore_replay_start();
+        }
+
+        while self
+            .playback
+            .as_ref()
+            .is_some_and(|p| p.position() < position)
+        {
+            if !self.step_playback() {
+                break;
             }
         }
+
+        // Skipped-over goals and hits shouldn't all play at once
+        self.fx_events.clear();
+    }
+
+    pub fn replay_status(&self) -> Option<ReplayStatus> {
+        self.playback.as_ref().map(|p| p.status())
+    }
+
+    fn restore_replay_start(&mut self) {
+        let Some(player) = &mut self.playback else {
+            return;
+        };
+        player.rewind();
+        let replay = &player.replay;
+
+        let mut entities: Vec<State> = replay.entities.iter().map(State::from).collect();
+        for state in entities.iter_mut() {
+            let input = replay.inputs.iter().find(|(id, _)| *id == state.entity_id);
+            if let (Some(pc), Some((_, frame))) = (&mut state.player_controller, input) {
+                pc.input = frame.clone();
+            }
+        }
+
+        self.world.restore(
+            entities,
+            replay.lobby_state.clone(),
+            replay.entity_ids.clone(),
+        );
+        self.spawn_manager.set_arena(replay.arena.clone());
+        self.team_manager = replay.teams.clone();
+        self.bots = replay.bots.clone();
+        self.spawn_manager
+            .restore_ball(replay.ball_id, replay.ball_start);
+        self.score_manager = replay.score_manager.clone();
+        self.phase = replay.phase.clone();
+        self.dt = 1.0 / replay.tick_rate;
+        let seed = replay.seed;
+
+        self.input_queues = replay
+            .inputs
+            .iter()
+            .map(|(id, frame)| (*id, InputQueue::holding(frame.clone())))
+            .collect();
+        self.owners = replay.owners.iter().copied().collect();
+        self.disconnected = replay.disconnected.clone();
+        self.reseed(seed);
+        self.event_queue.drain().for_each(drop);
+        self.fx_events.clear();
+    }
+
+    fn update_playback(&mut self) {
+        // Live requests don't belong in a recorded match
+        if let Some(rx) = &mut self.client_request_rx {
+            while rx.try_recv().is_ok() {}
+        }
+
+        let due = self.playback.as_mut().map_or(0, |p| p.ticks_due());
+        for _ in 0..due {
+            if !self.step_playback() {
+                break;
+            }
+        }
+    }
+
+    fn step_playback(&mut self) -> bool {
+        let Some(requests) = self.playback.as_mut().and_then(|p| p.advance()) else {
+            return false;
+        };
+
+        self.tick += 1;
+        for (request, client_id) in requests {
+            self.handle_client_request(request, client_id);
+        }
+        self.simulate();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::frontend::gamepayload::GamePayload;
+
+    ///A two player match on the default map, past the countdown.
+    fn playing(rules: MatchRules) -> (GameManager, EntityId, EntityId) {
+        playing_against(rules, ClientId::HOST)
+    }
+
+    ///The host on team 0 against `client`'s player on team 1.
+    fn playing_against(rules: MatchRules, client: ClientId) -> (GameManager, EntityId, EntityId) {
+        let mut gm = GameManager::new(Arena::default());
+        gm.set_game_settings(2, rules).unwrap();
+        gm.try_get_new_player(ClientId::HOST, 0);
+        let host_player = gm.players_of(ClientId::HOST)[0];
+        gm.try_get_new_player(client, 1);
+        let client_player = *gm
+            .players_of(client)
+            .iter()
+            .find(|id| **id != host_player)
+            .unwrap();
+        for player_id in [host_player, client_player] {
+            gm.set_ready(player_id, true).unwrap();
+        }
+        gm.start_game().unwrap();
+        gm.phase = GamePhase::Playing;
+        (gm, host_player, client_player)
+    }
+
+    #[test]
+    fn failed_grab_doesnt_swallow_a_goal_in_the_same_tick() {
+        let (mut gm, holder, other) = playing(MatchRules::default());
+        let ball_id = gm.spawn_manager.get_ball_id().unwrap();
+        gm.world.grab_entity(ball_id).unwrap().held_by = Some(holder);
+
+        gm.event_queue.push(GameEvent::TryGrab { player_id: other });
+        gm.event_queue.push(GameEvent::Shoot { player_id: other });
+        gm.event_queue.push(GameEvent::GoalScored { team_id: 1 });
+        gm.simulate();
+
+        let goals: u8 = gm.score_manager.teams.iter().map(|t| t.score).sum();
+        assert_eq!(goals, 1);
+        assert!(matches!(
+            gm.phase,
+            GamePhase::Celebrating { conceded: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn round_winning_goal_is_celebrated_and_the_conceding_team_kicks_off() {
+        let rules = MatchRules {
+            score_limit: 1,
+            best_of: 3,
+            ..MatchRules::default()
+        };
+        let (mut gm, team_0_player, _) = playing(rules);
+
+        gm.event_queue.push(GameEvent::GoalScored { team_id: 0 });
+        gm.simulate();
+        assert_eq!(gm.score_manager.round, 2);
+        assert!(matches!(
+            gm.phase,
+            GamePhase::Celebrating { conceded: 0, .. }
+        ));
+
+        while matches!(gm.phase, GamePhase::Celebrating { .. }) {
+            gm.simulate();
+        }
+        assert!(matches!(gm.phase, GamePhase::Countdown { .. }));
+        let ball_id = gm.spawn_manager.get_ball_id().unwrap();
+        let ball = &gm.world.entities[gm.world.index_of(ball_id).unwrap()];
+        assert_eq!(ball.held_by, Some(team_0_player));
+    }
+
+    #[test]
+    fn recorded_match_replays_identically() {
+        let mut gm = GameManager::new(Arena::default());
+        gm.set_game_settings(2, MatchRules::default()).unwrap();
+        gm.try_get_new_player(ClientId::HOST, 0);
+        let human = gm.players_of(ClientId::HOST)[0];
+        gm.set_ready(human, true).unwrap();
+        // The bot's decisions come off the match rng
+        gm.add_bot(Some(1), BotDifficulty::Hard).unwrap();
+        gm.start_game().unwrap();
+
+        // Nothing gets saved, the recording is taken before the match ends
+        gm.replay_dir = Some(std::env::temp_dir());
+        gm.reseed(42);
+        gm.begin_recording();
+
+        for tick in 1..=900u32 {
+            let mut frame = InputFrame::new();
+            frame.sequence = tick;
+            frame.move_axis.x = (tick as f32 / 50.0).cos();
+            frame.move_axis.y = (tick as f32 / 70.0).sin();
+            frame.buttons.grab = tick % 40 == 0;
+            frame.buttons.dash = tick % 90 == 0;
+            gm.handle_client_request(
+                ClientRequest::Input {
+                    entity_id: human,
+                    frame,
+                    recent: Vec::new(),
+                },
+                ClientId::HOST,
+            );
+            gm.update();
+        }
+        let live = GamePayload::from(&gm);
+        let replay = gm.recording.take().unwrap();
+        assert_eq!(replay.length, 900);
+        // Something has to have happened for the comparison to mean anything
+        assert_ne!(
+            serde_json::to_value(&replay.entities).unwrap(),
+            serde_json::to_value(&live.render_states).unwrap()
+        );
+
+        gm.play_replay(replay);
+        while gm.step_playback() {}
+        let replayed = GamePayload::from(&gm);
+
+        // RenderState has no PartialEq, the json shows exactly what got sent
+        assert_eq!(
+            serde_json::to_value(&live.render_states).unwrap(),
+            serde_json::to_value(&replayed.render_states).unwrap()
+        );
+        assert_eq!(live.score_manager, replayed.score_manager);
+        assert_eq!(live.game_phase, replayed.game_phase);
+    }
+
+    #[test]
+    fn replayed_joins_arent_announced_to_live_clients() {
+        let client = ClientId(3);
+        let (mut gm, _, player) = playing_against(MatchRules::default(), client);
+        gm.replay_dir = Some(std::env::temp_dir());
+        gm.begin_recording();
+
+        // The client swaps its player out mid match
+        gm.handle_client_request(ClientRequest::Remove { id: player }, client);
+        gm.update();
+        gm.handle_client_request(ClientRequest::Add { team_id: 1 }, client);
+        gm.update();
+        assert_eq!(gm.players_of(client).len(), 1);
+        let replay = gm.recording.take().unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        gm.snapshot_tx = Some(tx);
+        gm.play_replay(replay);
+        while gm.step_playback() {}
+
+        assert_eq!(gm.players_of(client).len(), 1);
+        while let Ok(event) = rx.try_recv() {
+            assert!(
+                !matches!(event, ServerEvent::AddedPlayer { .. }),
+                "replay announced {event:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dropped_client_gets_its_players_back_within_the_grace_window() {
+        let client = ClientId(3);
+        let (mut gm, _, player) = playing_against(MatchRules::default(), client);
+        gm.reconnect_grace = 1.0;
+
+        gm.handle_client_request(ClientRequest::Disconnected, client);
+        let lobby_player = |gm: &GameManager| {
+            gm.world
+                .get_lobby_state()
+                .players
+                .iter()
+                .find(|p| p.player_id == player)
+                .cloned()
+        };
+        assert!(lobby_player(&gm).unwrap().disconnected);
+
+        // Half the window later they're back, and stay past the rest of it
+        for _ in 0..30 {
+            gm.simulate();
+        }
+        gm.handle_client_request(ClientRequest::Reconnected, client);
+        for _ in 0..60 {
+            gm.simulate();
+        }
+        assert!(!lobby_player(&gm).unwrap().disconnected);
+        assert_eq!(gm.players_of(client), [player]);
+    }
+
+    #[test]
+    fn dropped_client_loses_its_players_when_the_grace_runs_out() {
+        let client = ClientId(3);
+        let (mut gm, _, player) = playing_against(MatchRules::default(), client);
+        gm.reconnect_grace = 1.0;
+
+        gm.handle_client_request(ClientRequest::Disconnected, client);
+        for _ in 0..61 {
+            gm.simulate();
+        }
+        assert!(gm.players_of(client).is_empty());
+        assert!(gm.world.index_of(player).is_none());
+
+        // Too late, nothing to hand back
+        gm.handle_client_request(ClientRequest::Reconnected, client);
+        assert!(gm.players_of(client).is_empty());
     }
 }
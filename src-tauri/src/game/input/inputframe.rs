@@ -7,12 +7,47 @@ pub struct Vec2 {
     pub y: f32,
 }
 
+impl Vec2 {
+    ///Shortened to length 1 if it's longer, junk like NaN counts as 0.
+    pub fn clamp_to_unit(self) -> Self {
+        let x = if self.x.is_finite() { self.x } else { 0.0 };
+        let y = if self.y.is_finite() { self.y } else { 0.0 };
+        let length = x.hypot(y);
+        if length <= 1.0 {
+            return Vec2 { x, y };
+        }
+        Vec2 {
+            x: x / length,
+            y: y / length,
+        }
+    }
+}
+
 // Button state
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub struct Buttons {
     pub grab: bool,
     pub place: bool,
-    pub dash: bool, //todo: implement dash
+    pub dash: bool,
+}
+
+impl Buttons {
+    pub fn none() -> Self {
+        Self {
+            grab: false,
+            place: false,
+            dash: false,
+        }
+    }
+
+    ///Pressed in either.
+    pub fn or(self, other: Buttons) -> Self {
+        Self {
+            grab: self.grab || other.grab,
+            place: self.place || other.place,
+            dash: self.dash || other.dash,
+        }
+    }
 }
 
 // InputFrame sent by frontend
@@ -21,6 +56,9 @@ pub struct InputFrame {
     pub move_axis: Vec2, // `move` is a keyword in Rust
     pub look: Vec2,
     pub buttons: Buttons,
+    /// Stamped by clients that predict locally, the frontend leaves it at 0
+    #[serde(default)]
+    pub sequence: u32,
 }
 
 impl InputFrame {
@@ -28,11 +66,14 @@ impl InputFrame {
         Self {
             move_axis: Vec2 { x: 0.0, y: 0.0 },
             look: Vec2 { x: 0.0, y: 0.0 },
-            buttons: Buttons {
-                grab: false,
-                place: false,
-                dash: false,
-            },
+            buttons: Buttons::none(),
+            sequence: 0,
         }
     }
+
+    ///Sticks can't be pushed past the edge, whatever a client claims.
+    pub fn clamp_axes(&mut self) {
+        self.move_axis = self.move_axis.clamp_to_unit();
+        self.look = self.look.clamp_to_unit();
+    }
 }
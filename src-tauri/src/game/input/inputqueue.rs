@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+
+use crate::game::input::{inputframe::Buttons, InputFrame};
+
+/// About 100ms at 60 Hz, a client running further ahead than this loses its oldest frames
+pub const MAX_QUEUED_INPUTS: usize = 6;
+
+///One player's inputs waiting for the ticks that will use them, one frame per tick.
+///
+///Sequenced frames (from predicting clients) come in with a few earlier ones resent
+///alongside, so anything at or below the last sequence seen is a repeat and dropped.
+///Unsequenced frames (sequence 0, the local frontend) queue in arrival order, except
+///that one only changing the sticks replaces the newest waiting frame instead of
+///queueing behind it.
+#[derive(Debug, Clone)]
+pub struct InputQueue {
+    frames: VecDeque<InputFrame>,
+    last_sequence: u32,
+    /// Used again on ticks nothing new arrived for
+    current: InputFrame,
+    /// Presses from frames that got dropped, carried onto the next one used
+    latched: Buttons,
+}
+
+impl Default for InputQueue {
+    fn default() -> Self {
+        Self::holding(InputFrame::new())
+    }
+}
+
+impl InputQueue {
+    ///An empty queue that keeps using `frame` until something arrives.
+    pub fn holding(frame: InputFrame) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            last_sequence: frame.sequence,
+            current: frame,
+            latched: Buttons::none(),
+        }
+    }
+
+    pub fn push(&mut self, frame: InputFrame) {
+        if frame.sequence != 0 {
+            if frame.sequence <= self.last_sequence {
+                return;
+            }
+            self.last_sequence = frame.sequence;
+        } else if let Some(last) = self.frames.back_mut() {
+            if last.buttons == frame.buttons {
+                *last = frame;
+                return;
+            }
+        }
+
+        if self.frames.len() == MAX_QUEUED_INPUTS {
+            if let Some(dropped) = self.frames.pop_front() {
+                self.latched = self.latched.or(dropped.buttons);
+            }
+        }
+        self.frames.push_back(frame);
+    }
+
+    ///The frame for this tick: the oldest waiting, or the last one again if none are.
+    pub fn next(&mut self) -> &InputFrame {
+        if let Some(mut frame) = self.frames.pop_front() {
+            frame.buttons = frame.buttons.or(self.latched);
+            self.latched = Buttons::none();
+            self.current = frame;
+        }
+        &self.current
+    }
+
+    ///The frame used last tick.
+    pub fn current(&self) -> &InputFrame {
+        &self.current
+    }
+
+    ///Skips to the newest frame, e.g. at the start of a match.
+    pub fn settle(&mut self) {
+        if let Some(frame) = self.frames.pop_back() {
+            self.current = frame;
+        }
+        self.frames.clear();
+        self.latched = Buttons::none();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(sequence: u32, grab: bool) -> InputFrame {
+        let mut frame = InputFrame::new();
+        frame.sequence = sequence;
+        frame.buttons.grab = grab;
+        frame
+    }
+
+    #[test]
+    fn one_frame_per_tick_and_resends_are_dropped() {
+        let mut queue = InputQueue::default();
+        // Each packet carries the newest frame plus the ones before it
+        for sequence in [1, 2, 3] {
+            queue.push(frame(sequence, false));
+        }
+        for sequence in [2, 3, 4] {
+            queue.push(frame(sequence, false));
+        }
+
+        let used: Vec<u32> = (0..5).map(|_| queue.next().sequence).collect();
+        assert_eq!(used, [1, 2, 3, 4, 4]);
+    }
+
+    #[test]
+    fn quick_press_between_ticks_still_gets_a_tick() {
+        let mut queue = InputQueue::default();
+        queue.push(frame(0, true));
+        queue.push(frame(0, false));
+
+        assert!(queue.next().buttons.grab);
+        assert!(!queue.next().buttons.grab);
+    }
+
+    #[test]
+    fn stick_only_updates_dont_pile_up() {
+        let mut queue = InputQueue::default();
+        for x in [0.1, 0.2, 0.3] {
+            let mut stick = frame(0, false);
+            stick.move_axis.x = x;
+            queue.push(stick);
+        }
+
+        assert_eq!(queue.next().move_axis.x, 0.3);
+        assert_eq!(queue.frames.len(), 0);
+    }
+
+    #[test]
+    fn presses_in_dropped_frames_are_latched() {
+        let mut queue = InputQueue::default();
+        queue.push(frame(1, true));
+        for sequence in 2..=(MAX_QUEUED_INPUTS as u32 + 1) {
+            queue.push(frame(sequence, false));
+        }
+
+        let first = queue.next();
+        assert_eq!(first.sequence, 2);
+        assert!(first.buttons.grab);
+        assert!(!queue.next().buttons.grab);
+    }
+}
@@ -1,3 +1,4 @@
 pub mod inputframe;
+pub mod inputqueue;
 pub mod playercontroller;
 pub use inputframe::InputFrame;
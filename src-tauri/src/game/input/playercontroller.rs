@@ -4,7 +4,12 @@ use crate::game::input::InputFrame;
 use crate::game::physics::Physics;
 use crate::game::state::entityid::EntityId;
 
-const PLACE_COOLDOWN_TICKS: u16 = 10;
+const PLACE_COOLDOWN: f32 = 10.0 / 60.0; // seconds
+const DASH_SPEED: f32 = 700.0;
+pub const DASH_DURATION: f32 = 0.15; // seconds
+const DASH_COOLDOWN: f32 = 1.2; // seconds
+/// Extra impulse a dashing player gives the ball or an enemy it runs into
+pub const DASH_KNOCKBACK: f32 = 250.0;
 
 #[derive(Clone)]
 pub struct PlayerController {
@@ -16,7 +21,11 @@ pub struct PlayerController {
     prev_action: bool,
     curr_brick_count: u8,
     max_bricks: u8,
-    place_cooldown: u16,
+    place_cooldown: f32,
+    prev_dash: bool,
+    dash_angle: f32,
+    dash_time_left: f32,
+    dash_cooldown: f32,
     pub is_holding: bool,
     pub player_id: EntityId,
     pub input: InputFrame,
@@ -33,7 +42,11 @@ impl PlayerController {
             curr_brick_count: 0,
             last_angle: 0.0,
             angular_acceleration: 16.0,
-            place_cooldown: 0,
+            place_cooldown: 0.0,
+            prev_dash: false,
+            dash_angle: 0.0,
+            dash_time_left: 0.0,
+            dash_cooldown: 0.0,
             max_bricks: 3,
             input: InputFrame::new(),
             player_id: player_id,
@@ -62,8 +75,8 @@ impl PlayerController {
         //handle movement
         let delta = self.input.move_axis;
         // apply acceleration to velocity
-        let mut vx = vel.x + delta.x * self.accel;
-        let mut vy = vel.y + delta.y * self.accel;
+        let mut vx = vel.x + delta.x * self.accel * dt;
+        let mut vy = vel.y + delta.y * self.accel * dt;
 
         // clamp velocity
         vx = vx.clamp(-self.max_speed, self.max_speed);
@@ -90,6 +103,18 @@ impl PlayerController {
         current_angle += delta_angle * angle_accel * dt;
 
         self.last_angle = current_angle;
+
+        //Handle Dash
+        if self.input.buttons.dash && !self.prev_dash && self.can_dash() {
+            self.start_dash(events, pos, current_angle);
+        }
+        self.prev_dash = self.input.buttons.dash;
+        if self.is_dashing() {
+            // Hold the burst for the whole dash, the clamp above would eat it
+            vx = self.dash_angle.cos() * DASH_SPEED;
+            vy = self.dash_angle.sin() * DASH_SPEED;
+        }
+
         //Handle Brick Placement
         if self.input.buttons.place {
             self.handle_brick_placement(events, pos, current_angle);
@@ -97,10 +122,14 @@ impl PlayerController {
         (pos.x, pos.y, vx, vy, Some(current_angle))
     }
 
-    pub fn tick(&mut self, _dt: f32) {
-        if self.place_cooldown > 0 {
-            self.place_cooldown -= 1;
-        }
+    pub fn tick(&mut self, dt: f32) {
+        self.place_cooldown = (self.place_cooldown - dt).max(0.0);
+        self.dash_time_left = (self.dash_time_left - dt).max(0.0);
+        self.dash_cooldown = (self.dash_cooldown - dt).max(0.0);
+    }
+
+    pub fn is_dashing(&self) -> bool {
+        self.dash_time_left > 0.0
     }
 
     pub fn add_brick(&mut self) {
@@ -114,12 +143,26 @@ impl PlayerController {
         }
     }
 
+    ///Already has the ball, so the next action press shoots
+    pub fn give_ball(&mut self) {
+        self.is_holding = true;
+        self.action_toggle = true;
+    }
+
+    ///Snaps the facing used for turning, e.g. to an angle the host sent
+    pub fn set_angle(&mut self, angle: f32) {
+        self.last_angle = angle;
+    }
+
     pub fn reset_player(&mut self, angle: f32) {
         self.curr_brick_count = 0;
-        self.place_cooldown = 0;
+        self.place_cooldown = 0.0;
         self.is_holding = false;
         self.action_toggle = false;
         self.prev_action = false;
+        self.prev_dash = false;
+        self.dash_time_left = 0.0;
+        self.dash_cooldown = 0.0;
         self.last_angle = angle;
     }
 
@@ -170,6 +213,7 @@ impl PlayerController {
         events.push(GameEvent::Place {
             player_id: self.player_id,
             pos: brick_pos,
+            angle,
         });
     }
 
@@ -178,9 +222,113 @@ impl PlayerController {
             return false;
         }
 
-        self.place_cooldown == 0
+        self.place_cooldown <= 0.0
     }
     fn on_place(&mut self) {
-        self.place_cooldown = PLACE_COOLDOWN_TICKS;
+        self.place_cooldown = PLACE_COOLDOWN;
+    }
+
+    fn can_dash(&self) -> bool {
+        !self.is_holding && self.dash_cooldown <= 0.0
+    }
+
+    fn start_dash(&mut self, events: &mut EventQueue, player_pos: Vec2, angle: f32) {
+        self.dash_angle = angle;
+        self.dash_time_left = DASH_DURATION;
+        self.dash_cooldown = DASH_COOLDOWN;
+
+        events.push(GameEvent::Dash {
+            player_id: self.player_id,
+            pos: (player_pos.x, player_pos.y),
+            angle,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::gamemanager::DEFAULT_TICK_RATE;
+
+    const DT: f32 = 1.0 / DEFAULT_TICK_RATE;
+    const STILL: Vec2 = Vec2 { x: 0.0, y: 0.0 };
+    const DASHING: Vec2 = Vec2 {
+        x: DASH_SPEED,
+        y: 0.0,
+    };
+
+    fn controller() -> PlayerController {
+        PlayerController::new(4500.0, 400.0, EntityId(1))
+    }
+
+    ///One tick with dash held or released, returns the x velocity and how many dashes started.
+    fn step(pc: &mut PlayerController, dash: bool, vel: Vec2) -> (f32, usize) {
+        let mut events = EventQueue::new();
+        pc.input.buttons.dash = dash;
+        let (_, _, vx, _, _) = pc.apply_input(STILL, vel, &mut events, DT);
+        pc.tick(DT);
+        let dashes = events
+            .drain()
+            .filter(|e| matches!(e, GameEvent::Dash { .. }))
+            .count();
+        (vx, dashes)
+    }
+
+    #[test]
+    fn dash_holds_its_speed_for_the_whole_duration() {
+        let mut pc = controller();
+
+        let (vx, dashes) = step(&mut pc, true, STILL);
+        assert_eq!(dashes, 1);
+        assert_eq!(vx, DASH_SPEED);
+
+        // Release straight away, the burst carries on by itself
+        let mut dash_ticks = 1;
+        while pc.is_dashing() {
+            let (vx, _) = step(&mut pc, false, STILL);
+            assert_eq!(vx, DASH_SPEED, "dash lost speed on tick {dash_ticks}");
+            dash_ticks += 1;
+        }
+        assert!(
+            (dash_ticks as f32 * DT - DASH_DURATION).abs() <= DT,
+            "dashed for {dash_ticks} ticks"
+        );
+
+        // Back under the normal clamp once it's over
+        let (vx, _) = step(&mut pc, false, DASHING);
+        assert!(vx < DASH_SPEED);
+    }
+
+    #[test]
+    fn cooldown_blocks_a_second_dash() {
+        let mut pc = controller();
+        assert_eq!(step(&mut pc, true, STILL).1, 1);
+        while pc.is_dashing() {
+            step(&mut pc, false, STILL);
+        }
+
+        // Dash is over but the cooldown isn't
+        let (vx, dashes) = step(&mut pc, true, STILL);
+        assert_eq!(dashes, 0);
+        assert!(!pc.is_dashing());
+        assert!(vx < DASH_SPEED);
+
+        let mut waited = 0.0;
+        while waited < DASH_COOLDOWN {
+            step(&mut pc, false, STILL);
+            waited += DT;
+        }
+        assert_eq!(step(&mut pc, true, STILL).1, 1);
+        assert!(pc.is_dashing());
+    }
+
+    #[test]
+    fn holding_dash_only_dashes_once() {
+        let mut pc = controller();
+        let mut dashes = 0;
+        for _ in 0..((DASH_COOLDOWN * 2.0) / DT) as usize {
+            dashes += step(&mut pc, true, STILL).1;
+        }
+        assert_eq!(dashes, 1);
     }
 }
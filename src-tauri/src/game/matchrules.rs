@@ -0,0 +1,54 @@
+///How a match is won, picked in the lobby and handed to set_game_settings.
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+#[serde(default)]
+pub struct MatchRules {
+    /// Goals that take a round, 0 plays to the clock
+    pub score_limit: u8,
+    /// Seconds on the round clock, 0 for no clock
+    pub time_limit: f32,
+    /// A tie when the clock runs out goes to next goal wins instead of a draw
+    pub sudden_death: bool,
+    /// Rounds in the match, taking more than half of them wins it
+    pub best_of: u8,
+    /// Seconds the result stays up before going back to the lobby
+    pub game_over_delay: f32,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        Self {
+            score_limit: 3,
+            time_limit: 0.0,
+            sudden_death: true,
+            best_of: 1,
+            game_over_delay: 5.0,
+        }
+    }
+}
+
+impl MatchRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.time_limit.is_finite() || self.time_limit < 0.0 {
+            return Err(format!("invalid time limit {}", self.time_limit));
+        }
+        if !self.game_over_delay.is_finite() || self.game_over_delay < 0.0 {
+            return Err(format!("invalid game over delay {}", self.game_over_delay));
+        }
+        if self.score_limit == 0 && self.time_limit == 0.0 {
+            return Err("a match needs a score limit or a time limit".into());
+        }
+        if self.best_of == 0 {
+            return Err("a match needs at least one round".into());
+        }
+        Ok(())
+    }
+
+    pub fn rounds_to_win(&self) -> u8 {
+        self.best_of / 2 + 1
+    }
+
+    ///A full round clock, None when rounds aren't timed.
+    pub fn clock(&self) -> Option<f32> {
+        (self.time_limit > 0.0).then_some(self.time_limit)
+    }
+}
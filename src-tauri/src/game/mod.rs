@@ -1,14 +1,24 @@
+pub mod arena;
+pub mod bot;
+pub mod broadphase;
+pub mod collision;
 pub mod eventqueue;
 pub mod frontend;
+pub mod gameloop;
 pub mod gamemanager; // tells Rust that util has a submodule input.rs
 
 pub mod input;
+pub mod matchrules;
 pub mod physics;
+pub mod prediction;
+pub mod replay;
 pub mod scoremanager;
 pub mod spawnmanager;
 
 #[path = "state/state.rs"]
 pub mod state;
+#[path = "team/team.rs"]
+pub mod team;
 pub mod world;
 
 pub mod util;
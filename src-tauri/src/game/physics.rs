@@ -1,101 +1,278 @@
 use crate::game::{
+    collision::{Collision, Impact},
     eventqueue::EventQueue,
     input::inputframe::Vec2,
-    state::{physicsstate::PhysicsState, State},
+    state::{enums::Shape, physicsstate::PhysicsState, State},
     world::World,
 };
 
+/// Hits a fast body can bounce off in one tick before it just stops for the tick
+const MAX_SUBSTEPS: usize = 8;
+/// Gap left after moving up to a hit so the next sweep doesn't start inside
+const SKIN: f32 = 0.01;
+/// How far in front of its holder a carried ball sits
+pub const HOLD_DISTANCE: f32 = 16.0;
+
 pub struct Physics;
 
 impl Physics {
+    ///Moves everything by a tick: integrate, then resolve each pair the broad
+    ///phase finds once, then move. Bodies fast enough to skip past something
+    ///are swept instead.
     pub fn update(world: &mut World, dt: f32, events: &mut EventQueue) {
-        for i in 0..world.entities.len() {
-            if world.entities[i].is_static || Physics::update_held_object(world, i, dt, events) {
+        let count = world.entities.len();
+        let mut next = Vec::with_capacity(count);
+        let mut moving = Vec::with_capacity(count);
+        let mut fast = Vec::with_capacity(count);
+        for i in 0..count {
+            let step = Physics::begin_step(world, i, dt, events);
+            moving.push(step.is_some());
+            fast.push(step.is_some_and(|to| Physics::is_fast(&world.entities[i], to)));
+            next.push(step.unwrap_or_else(|| Physics::resting_position(world, i, dt)));
+        }
+
+        world.rebuild_broad_phase(&next);
+        let pairs = world.candidate_pairs();
+
+        for &(i, j) in &pairs {
+            if fast[i] || fast[j] {
                 continue;
             }
+            Physics::collide_pair(world, i, j, next[i], next[j], events);
+        }
 
-            let s = &mut world.entities[i];
-
-            let ps = &mut s.physics_state;
-
-            //Apply player input
-            if let Some(controller) = &mut s.player_controller {
-                let (x, y, vx, vy, angle) = controller.apply_input(
-                    Vec2 {
-                        x: ps.pos.x,
-                        y: ps.pos.y,
-                    },
-                    Vec2 {
-                        x: ps.vel.x,
-                        y: ps.vel.y,
-                    },
-                    events,
-                    dt,
-                );
-
-                ps.pos.x = x;
-                ps.pos.y = y;
-                ps.vel.x = vx;
-                ps.vel.y = vy;
-                if angle.is_some() {
-                    ps.angle = angle.unwrap();
-                }
+        for i in (0..count).filter(|&i| fast[i]) {
+            let partners: Vec<usize> = pairs
+                .iter()
+                .filter_map(|&(a, b)| match (a == i, b == i) {
+                    (true, _) => Some(b),
+                    (_, true) => Some(a),
+                    _ => None,
+                })
+                .collect();
+            Physics::sweep_entity(world, i, &partners, dt, events);
+            moving[i] = false;
+        }
+
+        for (state, moving) in world.entities.iter_mut().zip(moving) {
+            if moving {
+                state.physics_state.update_position(dt);
+            }
+        }
+    }
+
+    ///Moves one entity by a tick and resolves its collisions against everything
+    ///else where it stands. Clients call this directly to predict their own players.
+    pub fn step_entity(world: &mut World, i: usize, dt: f32, events: &mut EventQueue) {
+        let Some(next_i) = Physics::begin_step(world, i, dt, events) else {
+            return;
+        };
+
+        if Physics::is_fast(&world.entities[i], next_i) {
+            let others: Vec<usize> = (0..world.entities.len()).filter(|&j| j != i).collect();
+            Physics::sweep_entity(world, i, &others, dt, events);
+            return;
+        }
+
+        for j in 0..world.entities.len() {
+            if i == j {
+                continue;
+            }
+            let pos = world.entities[j].physics_state.pos;
+            Physics::collide_pair(world, i, j, next_i, (pos.x, pos.y), events);
+        }
+        world.entities[i].physics_state.update_position(dt);
+    }
+
+    ///Applies input and friction. Returns where the entity is headed, None for
+    ///entities that don't integrate this tick (static or carried).
+    fn begin_step(
+        world: &mut World,
+        i: usize,
+        dt: f32,
+        events: &mut EventQueue,
+    ) -> Option<(f32, f32)> {
+        if world.entities[i].is_static || Physics::update_held_object(world, i, dt) {
+            return None;
+        }
+
+        let s = &mut world.entities[i];
+
+        let ps = &mut s.physics_state;
+
+        //Apply player input
+        if let Some(controller) = &mut s.player_controller {
+            let (x, y, vx, vy, angle) = controller.apply_input(
+                Vec2 {
+                    x: ps.pos.x,
+                    y: ps.pos.y,
+                },
+                Vec2 {
+                    x: ps.vel.x,
+                    y: ps.vel.y,
+                },
+                events,
+                dt,
+            );
+
+            ps.pos.x = x;
+            ps.pos.y = y;
+            ps.vel.x = vx;
+            ps.vel.y = vy;
+            if let Some(angle) = angle {
+                ps.angle = angle;
             }
+        }
+
+        let next = ps.predict_position(dt);
+        s.tick(dt, events);
+        Some(next)
+    }
+
+    ///Carried objects still sweep ahead on their damped velocity, everything else stays put.
+    fn resting_position(world: &World, i: usize, dt: f32) -> (f32, f32) {
+        let s = &world.entities[i];
+        if s.held_by.is_some() {
+            return s.physics_state.predict_position(dt);
+        }
+        (s.physics_state.pos.x, s.physics_state.pos.y)
+    }
+
+    ///Narrow phase for one pair, each at the position it's headed to.
+    fn collide_pair(
+        world: &mut World,
+        i: usize,
+        j: usize,
+        next_i: (f32, f32),
+        next_j: (f32, f32),
+        events: &mut EventQueue,
+    ) {
+        let (a, b) = (&world.entities[i], &world.entities[j]);
+        if Physics::ignores_pair(a, b) {
+            return;
+        }
+
+        let Some(contact) = a
+            .physics_state
+            .contact_between(next_i, &b.physics_state, next_j)
+        else {
+            return;
+        };
+
+        State::handle_collision(&mut world.entities, i, j, contact, events);
+    }
+
+    ///Circles that would move more than their radius this tick can jump clean
+    ///over a wall or brick, those get swept.
+    fn is_fast(state: &State, (to_x, to_y): (f32, f32)) -> bool {
+        let Shape::Circle { radius } = state.physics_state.shape else {
+            return false;
+        };
+        let dx = to_x - state.physics_state.pos.x;
+        let dy = to_y - state.physics_state.pos.y;
+        dx * dx + dy * dy > radius * radius
+    }
+
+    ///Moves a fast circle through its tick in pieces: up to the first thing it
+    ///hits, bounce, then on with what's left. Triggers it passes are fired once.
+    fn sweep_entity(
+        world: &mut World,
+        i: usize,
+        partners: &[usize],
+        dt: f32,
+        events: &mut EventQueue,
+    ) {
+        let Shape::Circle { radius } = world.entities[i].physics_state.shape else {
+            return;
+        };
+        let mut remaining = dt;
+        let mut triggered = Vec::new();
 
-            let (next_x, next_y) = ps.predict_position(dt);
-            s.tick(dt, events);
+        for _ in 0..MAX_SUBSTEPS {
+            let ps = &world.entities[i].physics_state;
+            let from = ps.pos;
+            let to = Vec2 {
+                x: from.x + ps.vel.x * remaining,
+                y: from.y + ps.vel.y * remaining,
+            };
 
-            for j in 0..world.entities.len() {
-                if i == j {
+            let mut hit: Option<(usize, Impact)> = None;
+            let mut crossed = Vec::new();
+            for &j in partners {
+                let (s, other) = (&world.entities[i], &world.entities[j]);
+                if Physics::ignores_pair(s, other) {
                     continue;
                 }
+                let ops = &other.physics_state;
+                let Some(impact) =
+                    Collision::sweep_circle(radius, from, to, &ops.shape, ops.pos, ops.angle)
+                else {
+                    continue;
+                };
 
-                if world.entities[j].held_by.is_some() {
-                    if world.entities[i].is_holding() {
-                        continue;
-                    }
+                if other.is_trigger {
+                    crossed.push((j, impact));
+                } else if hit.is_none_or(|(_, first)| impact.toi < first.toi) {
+                    hit = Some((j, impact));
                 }
+            }
 
-                if !world.entities[i].physics_state.check_collision_predicted(
-                    &world.entities[j].physics_state,
-                    next_x,
-                    next_y,
-                ) {
-                    continue;
+            // Triggers only count if we get to them before bouncing
+            let toi = hit.map_or(1.0, |(_, impact)| impact.toi);
+            for (j, impact) in crossed {
+                if impact.toi <= toi && !triggered.contains(&j) {
+                    triggered.push(j);
+                    State::handle_collision(&mut world.entities, i, j, impact.contact, events);
                 }
+            }
+
+            let ps = &mut world.entities[i].physics_state;
+            ps.pos.x = from.x + (to.x - from.x) * toi;
+            ps.pos.y = from.y + (to.y - from.y) * toi;
 
-                State::handle_collision(&mut world.entities, i, j, events);
+            let Some((j, impact)) = hit else {
+                return;
+            };
+            ps.pos.x -= impact.contact.nx * SKIN;
+            ps.pos.y -= impact.contact.ny * SKIN;
+            State::handle_collision(&mut world.entities, i, j, impact.contact, events);
+
+            remaining *= 1.0 - toi;
+            if remaining <= 0.0 {
+                return;
             }
-            world.entities[i].physics_state.update_position(dt);
         }
     }
 
+    fn ignores_pair(a: &State, b: &State) -> bool {
+        if a.is_fixed() && b.is_fixed() {
+            return true;
+        }
+        // The ball rides in front of whoever holds it
+        (a.held_by.is_some() && b.is_holding()) || (b.held_by.is_some() && a.is_holding())
+    }
+
     pub fn apply_impulse(state: &mut PhysicsState, angle: f32, power: f32) {
         state.vel.x += angle.cos() * power;
         state.vel.y += angle.sin() * power;
     }
 
     //Yeah prob not best
-    pub fn update_held_object(
-        world: &mut World,
-        i: usize,
-        dt: f32,
-        events: &mut EventQueue,
-    ) -> bool {
+    pub fn update_held_object(world: &mut World, i: usize, dt: f32) -> bool {
         if let Some(holder_id) = world.entities[i].held_by {
             if let Some((held, holder)) =
                 world.grab_two_entities(world.entities[i].entity_id, holder_id)
             {
                 let max_distance = 40.0;
-                let hold_distance = 16.0; // target in front of player
-                let follow_strength = 0.2;
-                let velocity_damping = 0.6;
+                // tuned per tick at 60 Hz, scaled so other tick rates feel the same
+                let follow_strength = 1.0 - 0.8_f32.powf(dt * 60.0);
+                let velocity_damping = 0.6_f32.powf(dt * 60.0);
 
                 // compute target position in front of player
                 let target_x =
-                    holder.physics_state.pos.x + holder.physics_state.angle.cos() * hold_distance;
+                    holder.physics_state.pos.x + holder.physics_state.angle.cos() * HOLD_DISTANCE;
                 let target_y =
-                    holder.physics_state.pos.y + holder.physics_state.angle.sin() * hold_distance;
+                    holder.physics_state.pos.y + holder.physics_state.angle.sin() * HOLD_DISTANCE;
 
                 // calculate distance to ball
                 let dx = target_x - held.physics_state.pos.x;
@@ -117,23 +294,6 @@ impl Physics {
                 held.physics_state.vel.x *= velocity_damping;
                 held.physics_state.vel.y *= velocity_damping;
 
-                let (next_x, next_y) = held.physics_state.predict_position(dt);
-
-                // Check triggers (goal zones, sensors, etc.)
-                for j in 0..world.entities.len() {
-                    if j != i && world.entities[j].entity_id != holder_id {
-                        if !world.entities[i].physics_state.check_collision_predicted(
-                            &world.entities[j].physics_state,
-                            next_x,
-                            next_y,
-                        ) {
-                            continue;
-                        }
-
-                        State::handle_pure_trigger(&mut world.entities, i, j, events);
-                    }
-                }
-
                 return true;
             }
         }
@@ -199,3 +359,112 @@ impl Physics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+    use crate::game::{
+        arena::Arena,
+        eventqueue::GameEvent,
+        gamemanager::{DEFAULT_TICK_RATE, SHOT_POWER},
+        spawnmanager::SpawnManager,
+        state::entityid::EntityId,
+    };
+
+    // The default map
+    const WIDTH: f32 = 320.0;
+    const HEIGHT: f32 = 180.0;
+    const DT: f32 = 1.0 / DEFAULT_TICK_RATE;
+
+    fn arena() -> (World, EntityId) {
+        let mut world = World::new();
+        let mut spawn_manager = SpawnManager::new(Arena::default());
+        spawn_manager.spawn_states(&mut world);
+        let ball_id = spawn_manager.get_ball_id().unwrap();
+        (world, ball_id)
+    }
+
+    fn shoot(world: &mut World, ball_id: EntityId, angle: f32, power: f32) {
+        let ball = world.grab_entity(ball_id).unwrap();
+        Physics::apply_impulse(&mut ball.physics_state, angle, power);
+    }
+
+    fn ball_pos(world: &World, ball_id: EntityId) -> Vec2 {
+        world.entities[world.index_of(ball_id).unwrap()]
+            .physics_state
+            .pos
+    }
+
+    ///Fires from the centre and checks the ball never leaves the arena.
+    fn assert_stays_inside(angle: f32, power: f32) {
+        let (mut world, ball_id) = arena();
+        let mut events = EventQueue::new();
+        shoot(&mut world, ball_id, angle, power);
+
+        for tick in 0..180 {
+            Physics::update(&mut world, DT, &mut events);
+            events.drain().for_each(drop);
+
+            let pos = ball_pos(&world, ball_id);
+            assert!(
+                (0.0..=WIDTH).contains(&pos.x) && (0.0..=HEIGHT).contains(&pos.y),
+                "ball fired at {angle:.3} rad with {power} left the arena on tick {tick}: ({:.1}, {:.1})",
+                pos.x,
+                pos.y
+            );
+        }
+    }
+
+    #[test]
+    fn full_power_shot_at_every_wall_stays_inside() {
+        for angle in [0.0, FRAC_PI_2, PI, -FRAC_PI_2] {
+            assert_stays_inside(angle, SHOT_POWER);
+        }
+    }
+
+    #[test]
+    fn shots_in_every_direction_stay_inside() {
+        for step in 0..64 {
+            let angle = step as f32 / 64.0 * 2.0 * PI;
+            assert_stays_inside(angle, SHOT_POWER);
+            // Well past anything a player can do
+            assert_stays_inside(angle, SHOT_POWER * 8.0);
+        }
+    }
+
+    #[test]
+    fn hard_shot_cannot_skip_a_goal() {
+        let (mut world, ball_id) = arena();
+        let mut events = EventQueue::new();
+        shoot(&mut world, ball_id, PI, SHOT_POWER * 8.0);
+
+        Physics::update(&mut world, DT, &mut events);
+        Physics::update(&mut world, DT, &mut events);
+
+        assert!(events
+            .drain()
+            .any(|event| matches!(event, GameEvent::GoalScored { team_id: 0 })));
+    }
+
+    #[test]
+    fn hard_shot_cannot_pass_a_brick() {
+        let (mut world, ball_id) = arena();
+        let mut events = EventQueue::new();
+        // 8 px brick halfway between the ball and the top wall
+        let mut brick = State::new_brick(156.0, 41.0, 8.0, EntityId::UNASSIGNED);
+        brick.time_to_live = None;
+        let brick_id = world.add_entity(brick);
+        shoot(&mut world, ball_id, -FRAC_PI_2, SHOT_POWER * 4.0);
+
+        for _ in 0..10 {
+            Physics::update(&mut world, DT, &mut events);
+            let ball = ball_pos(&world, ball_id);
+            let brick = world.entities[world.index_of(brick_id).unwrap()]
+                .physics_state
+                .pos;
+            assert!(ball.y > brick.y + 8.0, "ball went through the brick");
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Client-side prediction for the players a remote client controls.
+//!
+//! Every local tick the current input for each owned player gets the next
+//! sequence number, is sent to the host (along with the last few unacked
+//! frames, in case their packets got lost) and simulated straight away. When a
+//! snapshot arrives the local world is rebuilt from it and every input the
+//! host hasn't applied yet (sequence above the player's `input_sequence`) is
+//! replayed on top.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    game::{
+        eventqueue::EventQueue,
+        frontend::gamepayload::GamePayload,
+        gamemanager::GamePhase,
+        input::{playercontroller::PlayerController, InputFrame},
+        physics::Physics,
+        state::{entityid::EntityId, renderstate::RenderState, State},
+        world::World,
+    },
+    network::clientrequest::ClientRequest,
+};
+
+/// About two seconds at 60 Hz, anything older than that the host has long dropped
+pub const MAX_PENDING_INPUTS: usize = 128;
+/// Unacked frames resent with every input
+pub const REDUNDANT_INPUTS: usize = 3;
+
+struct PredictedPlayer {
+    current_input: InputFrame,
+    next_sequence: u32,
+    pending: VecDeque<PendingInput>,
+}
+
+struct PendingInput {
+    frame: InputFrame,
+    /// Cooldowns and button edges from just before this frame ran
+    controller: Option<PlayerController>,
+}
+
+pub struct Prediction {
+    players: HashMap<EntityId, PredictedPlayer>,
+    world: World,
+    phase: GamePhase,
+    dt: f32,
+}
+
+impl Prediction {
+    pub fn new(tick_rate: f32) -> Self {
+        Self {
+            players: HashMap::new(),
+            world: World::new(),
+            phase: GamePhase::Waiting,
+            dt: 1.0 / tick_rate,
+        }
+    }
+
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.dt = 1.0 / tick_rate;
+    }
+
+    pub fn track(&mut self, entity_id: EntityId) {
+        self.players
+            .entry(entity_id)
+            .or_insert_with(|| PredictedPlayer {
+                current_input: InputFrame::new(),
+                // 0 means "nothing applied yet" on the host side
+                next_sequence: 1,
+                pending: VecDeque::new(),
+            });
+    }
+
+    pub fn untrack(&mut self, entity_id: EntityId) {
+        self.players.remove(&entity_id);
+    }
+
+    pub fn is_tracking(&self, entity_id: EntityId) -> bool {
+        self.players.contains_key(&entity_id)
+    }
+
+    ///Latest frame from the frontend, used for every tick until it changes.
+    pub fn set_input(&mut self, entity_id: EntityId, frame: InputFrame) {
+        if let Some(player) = self.players.get_mut(&entity_id) {
+            player.current_input = frame;
+        }
+    }
+
+    ///Runs one local tick. Returns the Input requests to send to the host.
+    pub fn tick(&mut self) -> Vec<ClientRequest> {
+        let playing = self.is_playing();
+        let mut requests = Vec::with_capacity(self.players.len());
+
+        for (entity_id, player) in self.players.iter_mut() {
+            let mut frame = player.current_input.clone();
+            frame.sequence = player.next_sequence;
+            player.next_sequence += 1;
+
+            if player.pending.len() == MAX_PENDING_INPUTS {
+                player.pending.pop_front();
+            }
+            player.pending.push_back(PendingInput {
+                frame: frame.clone(),
+                controller: Self::controller(&self.world, *entity_id),
+            });
+
+            if playing {
+                Self::simulate(&mut self.world, *entity_id, &frame, self.dt);
+            }
+
+            let before = player.pending.len() - 1;
+            let recent = player
+                .pending
+                .range(before.saturating_sub(REDUNDANT_INPUTS)..before)
+                .map(|pending| pending.frame.clone())
+                .collect();
+            requests.push(ClientRequest::Input {
+                entity_id: *entity_id,
+                frame,
+                recent,
+            });
+        }
+
+        requests
+    }
+
+    ///Rewinds to the authoritative snapshot and replays the inputs it doesn't include yet.
+    pub fn reconcile(&mut self, snapshot: &GamePayload) {
+        let mut world = World::new();
+        for render_state in &snapshot.render_states {
+            world.insert_entity(State::from(render_state));
+        }
+
+        for (entity_id, player) in self.players.iter_mut() {
+            let Some(acked) = snapshot
+                .render_states
+                .iter()
+                .find(|rs| rs.id == entity_id.0)
+                .map(|rs| rs.input_sequence)
+            else {
+                continue;
+            };
+            player
+                .pending
+                .retain(|pending| pending.frame.sequence > acked);
+
+            // Keep cooldowns and button edges from the old prediction, as of the
+            // acked input. The frames after it get replayed and advance them again.
+            let controller = match player.pending.front() {
+                Some(first) => first.controller.clone(),
+                None => Self::controller(&self.world, *entity_id),
+            };
+            if let (Some(controller), Some(new)) = (controller, world.grab_entity(*entity_id)) {
+                if let Some(new_pc) = &mut new.player_controller {
+                    let angle = new.physics_state.angle;
+                    *new_pc = controller;
+                    new_pc.set_angle(angle);
+                }
+            }
+        }
+
+        self.world = world;
+        self.phase = snapshot.game_phase.clone();
+
+        if !self.is_playing() {
+            return;
+        }
+        for (entity_id, player) in self.players.iter_mut() {
+            for pending in player.pending.iter_mut() {
+                pending.controller = Self::controller(&self.world, *entity_id);
+                Self::simulate(&mut self.world, *entity_id, &pending.frame, self.dt);
+            }
+        }
+    }
+
+    ///Overwrites the predicted players in a payload with their local state.
+    pub fn apply_to(&self, payload: &mut GamePayload) {
+        for render_state in payload.render_states.iter_mut() {
+            let entity_id = EntityId(render_state.id);
+            if !self.players.contains_key(&entity_id) {
+                continue;
+            }
+            if let Some(index) = self.world.index_of(entity_id) {
+                let predicted = RenderState::from(&self.world.entities[index]);
+                render_state.x = predicted.x;
+                render_state.y = predicted.y;
+                render_state.vx = predicted.vx;
+                render_state.vy = predicted.vy;
+                render_state.angle = predicted.angle;
+                render_state.action_state = predicted.action_state;
+            }
+        }
+    }
+
+    //Helpers
+
+    fn is_playing(&self) -> bool {
+        self.phase.is_live()
+    }
+
+    fn controller(world: &World, entity_id: EntityId) -> Option<PlayerController> {
+        let index = world.index_of(entity_id)?;
+        world.entities[index].player_controller.clone()
+    }
+
+    fn simulate(world: &mut World, entity_id: EntityId, frame: &InputFrame, dt: f32) {
+        let Some(index) = world.index_of(entity_id) else {
+            return;
+        };
+        if let Some(pc) = &mut world.entities[index].player_controller {
+            pc.input = frame.clone();
+        }
+
+        // Grabs, shots and bricks are the host's call, local events are thrown away
+        let mut events = EventQueue::new();
+        Physics::step_entity(world, index, dt, &mut events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        input::playercontroller::DASH_DURATION, scoremanager::ScoreManager,
+        state::lobbyplayer::LobbyState,
+    };
+
+    const PLAYER: EntityId = EntityId(1);
+
+    ///The host's view: one player at `x`, having applied inputs up to `acked`.
+    fn snapshot(tick: u64, x: f32, acked: u32) -> GamePayload {
+        let mut player = RenderState::from(&State::new_player(0, x, 100.0, 0.0));
+        player.id = PLAYER.0;
+        player.input_sequence = acked;
+        GamePayload {
+            tick,
+            render_states: vec![player],
+            score_manager: ScoreManager::default(),
+            game_phase: GamePhase::Playing,
+            fx_events: Vec::new(),
+            lobby_state: LobbyState {
+                expected_players: 1,
+                players: Vec::new(),
+            },
+        }
+    }
+
+    fn predicted_x(prediction: &Prediction) -> f32 {
+        let index = prediction.world.index_of(PLAYER).unwrap();
+        prediction.world.entities[index].physics_state.pos.x
+    }
+
+    fn dashing(prediction: &Prediction) -> bool {
+        Prediction::controller(&prediction.world, PLAYER).is_some_and(|pc| pc.is_dashing())
+    }
+
+    ///Ticks a dash out, reconciling each tick against a host `lag` frames behind if given.
+    fn dash_ticks(lag: Option<u32>) -> u32 {
+        let mut prediction = Prediction::new(60.0);
+        prediction.track(PLAYER);
+        prediction.reconcile(&snapshot(0, 100.0, 0));
+
+        let mut dash = InputFrame::new();
+        dash.buttons.dash = true;
+        prediction.set_input(PLAYER, dash);
+        prediction.tick();
+        prediction.set_input(PLAYER, InputFrame::new());
+
+        let mut ticks = 1;
+        for tick in 1..60 {
+            if let Some(lag) = lag {
+                let acked = (tick as u32).saturating_sub(lag);
+                prediction.reconcile(&snapshot(tick, 100.0, acked));
+            }
+            if !dashing(&prediction) {
+                break;
+            }
+            prediction.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn unacked_inputs_are_replayed_on_top_of_a_correction() {
+        let mut right = InputFrame::new();
+        right.move_axis.x = 1.0;
+
+        let mut prediction = Prediction::new(60.0);
+        prediction.track(PLAYER);
+        prediction.reconcile(&snapshot(0, 100.0, 0));
+        prediction.set_input(PLAYER, right.clone());
+        for _ in 0..3 {
+            prediction.tick();
+        }
+
+        // The host has only used frame 1 so far, and put the player somewhere else
+        prediction.reconcile(&snapshot(1, 50.0, 1));
+        let pending: Vec<u32> = prediction.players[&PLAYER]
+            .pending
+            .iter()
+            .map(|pending| pending.frame.sequence)
+            .collect();
+        assert_eq!(pending, [2, 3]);
+
+        // Lands where running frames 2 and 3 from the corrected spot would
+        let mut fresh = Prediction::new(60.0);
+        fresh.track(PLAYER);
+        fresh.reconcile(&snapshot(1, 50.0, 1));
+        fresh.set_input(PLAYER, right);
+        fresh.tick();
+        fresh.tick();
+        assert!(predicted_x(&prediction) > 50.0);
+        assert_eq!(predicted_x(&prediction), predicted_x(&fresh));
+    }
+
+    #[test]
+    fn ticks_last_as_long_as_the_hosts() {
+        let mut right = InputFrame::new();
+        right.move_axis.x = 1.0;
+        let moved = |tick_rate: f32| {
+            let mut prediction = Prediction::new(60.0);
+            prediction.set_tick_rate(tick_rate);
+            prediction.track(PLAYER);
+            prediction.reconcile(&snapshot(0, 100.0, 0));
+            prediction.set_input(PLAYER, right.clone());
+            prediction.tick();
+            predicted_x(&prediction) - 100.0
+        };
+
+        assert!(moved(30.0) > moved(60.0) * 1.5);
+    }
+
+    #[test]
+    fn dash_lasts_as_long_across_reconciles() {
+        let unreconciled = dash_ticks(None);
+        // Timer rounding can give it one more tick
+        let expected = (DASH_DURATION * 60.0).round() as u32;
+        assert!(unreconciled.abs_diff(expected) <= 1, "{unreconciled} ticks");
+
+        // A snapshot every tick, with the host 6 frames (100ms) behind
+        assert_eq!(dash_ticks(Some(6)), unreconciled);
+        assert_eq!(dash_ticks(Some(1)), unreconciled);
+    }
+}
@@ -0,0 +1,232 @@
+//! Match recordings.
+//!
+//! A replay is the world as it was when the match started plus every
+//! ClientRequest the GameManager applied afterwards, keyed by how many ticks
+//! into the match it was applied. The simulation is deterministic, so feeding
+//! the same requests back through `handle_client_request` and stepping the
+//! same number of ticks reproduces the match.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    game::{
+        arena::Arena,
+        bot::BotManager,
+        gamemanager::GamePhase,
+        input::InputFrame,
+        scoremanager::ScoreManager,
+        state::{
+            entityid::{EntityId, EntityIdAllocator},
+            lobbyplayer::LobbyState,
+            renderstate::RenderState,
+        },
+        team::TeamManager,
+    },
+    network::{clientid::ClientId, clientrequest::ClientRequest},
+};
+
+pub const REPLAY_VERSION: u32 = 12;
+pub const REPLAY_EXTENSION: &str = "json";
+pub const MIN_SPEED: f32 = 0.1;
+pub const MAX_SPEED: f32 = 8.0;
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct RecordedRequest {
+    /// Ticks since the match started
+    pub tick: u64,
+    pub client_id: ClientId,
+    pub request: ClientRequest,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct Replay {
+    pub version: u32,
+    pub tick_rate: f32,
+    pub seed: u64,
+
+    //Starting world
+    pub entities: Vec<RenderState>,
+    pub lobby_state: LobbyState,
+    pub entity_ids: EntityIdAllocator,
+    pub ball_id: Option<EntityId>,
+    pub ball_start: Option<(f32, f32)>,
+    /// Map the match was played on, kickoffs after goals spawn from it
+    pub arena: Arena,
+    /// Formation slots, kickoffs put players back into them
+    pub teams: TeamManager,
+    /// Bots think with the match's rng, so they replay the same
+    pub bots: BotManager,
+    pub score_manager: ScoreManager,
+    pub phase: GamePhase,
+    /// Input each player was holding when the match started
+    pub inputs: Vec<(EntityId, InputFrame)>,
+    /// Client that added each player, requests from anyone else were dropped
+    pub owners: Vec<(EntityId, ClientId)>,
+    /// Clients that had dropped, with the seconds they had left to come back
+    pub disconnected: Vec<(ClientId, f32)>,
+
+    pub requests: Vec<RecordedRequest>,
+    /// Ticks the match lasted
+    pub length: u64,
+}
+
+impl Replay {
+    pub fn record(&mut self, tick: u64, client_id: ClientId, request: &ClientRequest) {
+        self.requests.push(RecordedRequest {
+            tick,
+            client_id,
+            request: request.clone(),
+        });
+    }
+
+    ///Writes the replay as `replay-<unix time>.json` into `dir`.
+    pub fn save(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = dir.join(format!("replay-{stamp}.{REPLAY_EXTENSION}"));
+
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(&path, json)?;
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        let replay: Replay = serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "replay version {} is not supported, expected {}",
+                    replay.version, REPLAY_VERSION
+                ),
+            ));
+        }
+        Ok(replay)
+    }
+
+    ///File names of the replays in `dir`, newest first.
+    pub fn list(dir: &Path) -> io::Result<Vec<String>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == REPLAY_EXTENSION))
+            .filter_map(|path| path.file_name()?.to_str().map(String::from))
+            .collect();
+        names.sort_unstable_by(|a, b| b.cmp(a));
+        Ok(names)
+    }
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ReplayStatus {
+    pub position: u64,
+    pub length: u64,
+    pub paused: bool,
+    pub speed: f32,
+}
+
+///Walks through a replay one tick at a time.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    /// dt to go back to once playback stops
+    pub live_dt: f32,
+    /// Map and teams to go back to once playback stops
+    pub live_arena: Arena,
+    pub live_teams: TeamManager,
+    position: u64,
+    next_request: usize,
+    paused: bool,
+    speed: f32,
+    accumulator: f32,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay, live_dt: f32, live_arena: Arena, live_teams: TeamManager) -> Self {
+        Self {
+            replay,
+            live_dt,
+            live_arena,
+            live_teams,
+            position: 0,
+            next_request: 0,
+            paused: false,
+            speed: 1.0,
+            accumulator: 0.0,
+        }
+    }
+
+    ///Back to the first tick, the caller restores the starting world.
+    pub fn rewind(&mut self) {
+        self.position = 0;
+        self.next_request = 0;
+        self.accumulator = 0.0;
+    }
+
+    ///How many ticks to play for one tick of the game loop.
+    pub fn ticks_due(&mut self) -> u32 {
+        if self.paused {
+            return 0;
+        }
+        self.accumulator += self.speed;
+        let due = self.accumulator.floor();
+        self.accumulator -= due;
+        due as u32
+    }
+
+    ///Moves forward a tick and returns the requests applied on it, None at the end.
+    pub fn advance(&mut self) -> Option<Vec<(ClientRequest, ClientId)>> {
+        if self.is_finished() {
+            return None;
+        }
+        self.position += 1;
+
+        let mut requests = Vec::new();
+        while let Some(recorded) = self.replay.requests.get(self.next_request) {
+            if recorded.tick > self.position {
+                break;
+            }
+            requests.push((recorded.request.clone(), recorded.client_id));
+            self.next_request += 1;
+        }
+        Some(requests)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.replay.length
+    }
+
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            position: self.position,
+            length: self.replay.length,
+            paused: self.paused,
+            speed: self.speed,
+        }
+    }
+}
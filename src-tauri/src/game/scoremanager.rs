@@ -1,11 +1,28 @@
-use tauri::window::Color;
+use crate::game::{arena::TeamLayout, matchrules::MatchRules, state::entityid::EntityId};
 
-#[derive(Default, Clone, serde::Serialize, serde::Deserialize, Debug)]
+/// RGBA colour, serialized as `[r, g, b, a]` the same way `tauri::window::Color` is.
+#[derive(Default, Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+pub struct Color(pub u8, pub u8, pub u8, pub u8);
+
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
 pub struct Team {
     pub id: u8,
     pub name: String,
     pub color: Color,
     pub score: u8,
+    pub rounds_won: u8,
+}
+
+impl From<&TeamLayout> for Team {
+    fn from(layout: &TeamLayout) -> Self {
+        Team {
+            id: layout.team_id,
+            name: layout.name.clone(),
+            color: layout.color,
+            score: 0,
+            rounds_won: 0,
+        }
+    }
 }
 
 impl Team {
@@ -18,50 +35,422 @@ impl Team {
     }
 }
 
-#[derive(Default, Clone, serde::Serialize, serde::Deserialize, Debug)]
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct Touch {
+    pub player_id: EntityId,
+    pub team_id: u8,
+}
+
+///One player's tally for the match.
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct PlayerStats {
+    pub player_id: EntityId,
+    pub team_id: u8,
+    pub goals: u8,
+    pub assists: u8,
+    pub own_goals: u8,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub struct GoalRecord {
+    /// Whose goal the ball went into
+    pub goal_team: u8,
+    /// Teams that got the point
+    pub scoring_teams: Vec<u8>,
+    /// Last to touch the ball, on the conceding team for an own goal
+    pub scorer: Option<EntityId>,
+    pub assist: Option<EntityId>,
+    pub own_goal: bool,
+}
+
+///How a round ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchOutcome {
+    NextRound { winner: Option<u8> },
+    Over { winner: Option<u8> },
+}
+
+#[derive(Default, Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
 pub struct ScoreManager {
     pub teams: Vec<Team>,
-    can_score: bool,
-    target_score: u8,
+    pub can_score: bool,
+    pub rules: MatchRules,
+    /// Seconds left in the round, None without a time limit or once in overtime
+    pub clock: Option<f32>,
+    /// Tied when the clock ran out, the next goal takes the round
+    pub overtime: bool,
+    /// Counts from 1, 0 outside a match
+    pub round: u8,
+    pub players: Vec<PlayerStats>,
+    /// This match's goals, oldest first
+    pub goals: Vec<GoalRecord>,
+    /// The last two players on the ball, most recent last
+    touches: Vec<Touch>,
 }
 
 impl ScoreManager {
-    pub fn new(team1: Team, team2: Team) -> Self {
+    pub fn new(teams: Vec<Team>) -> Self {
         Self {
-            teams: vec![team1, team2],
+            teams,
             can_score: true,
-            target_score: 1,
+            rules: MatchRules::default(),
+            clock: None,
+            overtime: false,
+            round: 0,
+            players: Vec::new(),
+            goals: Vec::new(),
+            touches: Vec::new(),
         }
     }
 
-    pub fn add_point(&mut self, team_id: u8) -> bool {
-        if let Some(team) = self.teams.iter_mut().find(|t| t.id == team_id) {
-            team.add_point();
+    ///Someone grabbed, shot or bumped the ball.
+    pub fn touch(&mut self, player_id: EntityId, team_id: u8) {
+        if self.touches.last().map(|t| t.player_id) == Some(player_id) {
+            return;
+        }
+        if self.touches.len() == 2 {
+            self.touches.remove(0);
+        }
+        self.touches.push(Touch { player_id, team_id });
+    }
+
+    ///New lineup between matches, scores start over.
+    pub fn set_teams(&mut self, teams: Vec<Team>) {
+        self.teams = teams;
+        self.reset();
+    }
+
+    ///The ball went into `goal_team`'s goal. The last team on the ball gets the point, or
+    ///every other team when nobody touched it or it was an own goal.
+    pub fn concede(&mut self, goal_team: u8) -> GoalRecord {
+        let last = self.touches.last().copied();
+        let before = match self.touches.as_slice() {
+            [before, _] => Some(*before),
+            _ => None,
+        };
+
+        let record = match last {
+            Some(scorer) if scorer.team_id != goal_team => GoalRecord {
+                goal_team,
+                scoring_teams: vec![scorer.team_id],
+                scorer: Some(scorer.player_id),
+                assist: before
+                    .filter(|t| t.team_id == scorer.team_id)
+                    .map(|t| t.player_id),
+                own_goal: false,
+            },
+            _ => GoalRecord {
+                goal_team,
+                scoring_teams: self
+                    .teams
+                    .iter()
+                    .map(|team| team.id)
+                    .filter(|id| *id != goal_team)
+                    .collect(),
+                scorer: last.map(|t| t.player_id),
+                assist: None,
+                own_goal: last.is_some(),
+            },
+        };
+
+        for team_id in &record.scoring_teams {
+            self.add_point(*team_id);
+        }
+        if let Some(scorer) = last {
+            let stats = self.stats_mut(scorer);
+            if record.own_goal {
+                stats.own_goals += 1;
+            } else {
+                stats.goals += 1;
+            }
+        }
+        if let Some(assist) = before.filter(|t| Some(t.player_id) == record.assist) {
+            self.stats_mut(assist).assists += 1;
+        }
+
+        self.touches.clear();
+        self.goals.push(record.clone());
+        record
+    }
 
-            //game over check
-            if team.score >= self.target_score {
-                return true;
+    ///Fresh scores and the first round's clock.
+    pub fn start_match(&mut self) {
+        self.reset();
+        self.round = 1;
+        self.clock = self.rules.clock();
+    }
+
+    ///Runs the round clock down. At zero the leader takes the round, a tie goes to
+    ///overtime with sudden death or ends the round drawn without it.
+    pub fn tick_clock(&mut self, dt: f32) -> Option<MatchOutcome> {
+        let clock = self.clock.as_mut()?;
+        *clock = (*clock - dt).max(0.0);
+        if *clock > 0.0 {
+            return None;
+        }
+
+        self.clock = None;
+        match self.leader() {
+            Some(team_id) => Some(self.finish_round(Some(team_id))),
+            None if self.rules.sudden_death => {
+                println!("Overtime!");
+                self.overtime = true;
+                None
             }
+            None => Some(self.finish_round(None)),
+        }
+    }
 
+    ///Checked after every goal, a team alone at the score limit or ahead in overtime takes the round.
+    pub fn check_goal(&mut self) -> Option<MatchOutcome> {
+        let team_id = self.leader()?;
+        let score = self.teams.iter().find(|t| t.id == team_id)?.score;
+        let limit = self.rules.score_limit;
+
+        if self.overtime || (limit > 0 && score >= limit) {
+            Some(self.finish_round(Some(team_id)))
+        } else {
+            None
+        }
+    }
+
+    ///The team strictly ahead on goals, None on a tie.
+    pub fn leader(&self) -> Option<u8> {
+        Self::strictly_ahead(&self.teams, |t| t.score)
+    }
+
+    fn finish_round(&mut self, winner: Option<u8>) -> MatchOutcome {
+        if let Some(team) = winner.and_then(|id| self.teams.iter_mut().find(|t| t.id == id)) {
+            team.rounds_won += 1;
+        }
+        println!("Round {} to {:?}", self.round, winner);
+
+        let to_win = self.rules.rounds_to_win();
+        if self.round >= self.rules.best_of || self.teams.iter().any(|t| t.rounds_won >= to_win) {
+            return MatchOutcome::Over {
+                winner: Self::strictly_ahead(&self.teams, |t| t.rounds_won),
+            };
+        }
+
+        self.round += 1;
+        for team in &mut self.teams {
+            team.reset();
+        }
+        self.touches.clear();
+        self.clock = self.rules.clock();
+        self.overtime = false;
+        self.can_score = true;
+        MatchOutcome::NextRound { winner }
+    }
+
+    fn strictly_ahead(teams: &[Team], key: impl Fn(&Team) -> u8) -> Option<u8> {
+        let best = teams.iter().map(&key).max()?;
+        let mut leaders = teams.iter().filter(|t| key(t) == best);
+        match (leaders.next(), leaders.next()) {
+            (Some(team), None) => Some(team.id),
+            _ => None,
+        }
+    }
+
+    pub fn add_point(&mut self, team_id: u8) {
+        if let Some(team) = self.teams.iter_mut().find(|t| t.id == team_id) {
+            team.add_point();
             self.can_score = false;
             println!("Gooooooooooooal");
         }
-
-        return false;
     }
 
     pub fn enable_score(&mut self) {
         self.can_score = true;
     }
 
-    pub fn set_target_score(&mut self, target: u8) {
-        self.target_score = target;
+    pub fn set_rules(&mut self, rules: MatchRules) {
+        self.rules = rules;
     }
 
     pub fn reset(&mut self) {
         for team in &mut self.teams {
             team.reset();
+            team.rounds_won = 0;
         }
+        self.players.clear();
+        self.goals.clear();
+        self.touches.clear();
         self.can_score = true;
+        self.clock = None;
+        self.overtime = false;
+        self.round = 0;
+    }
+
+    fn stats_mut(&mut self, touch: Touch) -> &mut PlayerStats {
+        let index = match self
+            .players
+            .iter()
+            .position(|p| p.player_id == touch.player_id)
+        {
+            Some(index) => index,
+            None => {
+                self.players.push(PlayerStats {
+                    player_id: touch.player_id,
+                    team_id: touch.team_id,
+                    goals: 0,
+                    assists: 0,
+                    own_goals: 0,
+                });
+                self.players.len() - 1
+            }
+        };
+        &mut self.players[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLUE: u8 = 0;
+    const RED: u8 = 1;
+    const GREEN: u8 = 2;
+
+    fn score_manager(team_count: u8) -> ScoreManager {
+        let teams = (0..team_count)
+            .map(|id| Team {
+                id,
+                ..Team::default()
+            })
+            .collect();
+        let mut score_manager = ScoreManager::new(teams);
+        score_manager.set_rules(MatchRules {
+            score_limit: 5,
+            ..MatchRules::default()
+        });
+        score_manager.start_match();
+        score_manager
+    }
+
+    fn scores(score_manager: &ScoreManager) -> Vec<u8> {
+        score_manager.teams.iter().map(|t| t.score).collect()
+    }
+
+    #[test]
+    fn attacking_team_gets_the_goal_and_the_passer_the_assist() {
+        let mut sm = score_manager(2);
+        sm.touch(EntityId(1), BLUE);
+        sm.touch(EntityId(2), BLUE);
+
+        let goal = sm.concede(RED);
+
+        assert_eq!(scores(&sm), [1, 0]);
+        assert_eq!(goal.scoring_teams, [BLUE]);
+        assert_eq!(goal.scorer, Some(EntityId(2)));
+        assert_eq!(goal.assist, Some(EntityId(1)));
+        assert!(!goal.own_goal);
+        assert_eq!(sm.goals, [goal]);
+        assert_eq!(
+            (sm.players[0].player_id, sm.players[0].goals),
+            (EntityId(2), 1)
+        );
+        assert_eq!(
+            (sm.players[1].player_id, sm.players[1].assists),
+            (EntityId(1), 1)
+        );
+    }
+
+    #[test]
+    fn no_assist_from_an_opponent() {
+        let mut sm = score_manager(2);
+        sm.touch(EntityId(5), RED);
+        sm.touch(EntityId(1), BLUE);
+
+        let goal = sm.concede(RED);
+
+        assert_eq!(goal.scorer, Some(EntityId(1)));
+        assert_eq!(goal.assist, None);
+        assert!(sm.players.iter().all(|p| p.assists == 0));
+    }
+
+    #[test]
+    fn own_goal_goes_to_everyone_else() {
+        let mut sm = score_manager(3);
+        sm.touch(EntityId(1), BLUE);
+        sm.touch(EntityId(5), RED);
+
+        let goal = sm.concede(RED);
+
+        assert_eq!(scores(&sm), [1, 0, 1]);
+        assert_eq!(goal.scoring_teams, [BLUE, GREEN]);
+        assert_eq!(goal.scorer, Some(EntityId(5)));
+        assert!(goal.own_goal);
+        assert_eq!(sm.players[0].own_goals, 1);
+        assert_eq!(sm.players[0].goals, 0);
+    }
+
+    #[test]
+    fn touches_start_over_after_a_goal() {
+        let mut sm = score_manager(2);
+        sm.touch(EntityId(1), BLUE);
+        sm.concede(RED);
+
+        let goal = sm.concede(BLUE);
+        assert_eq!(goal.scorer, None);
+        assert_eq!(goal.scoring_teams, [RED]);
+        assert!(!goal.own_goal);
+        assert_eq!(scores(&sm), [1, 1]);
+    }
+
+    #[test]
+    fn tie_at_the_horn_goes_to_sudden_death() {
+        let mut sm = score_manager(2);
+        sm.set_rules(MatchRules {
+            score_limit: 0,
+            time_limit: 1.0,
+            ..MatchRules::default()
+        });
+        sm.start_match();
+        sm.concede(RED);
+        sm.concede(BLUE);
+
+        assert_eq!(sm.tick_clock(0.5), None);
+        assert_eq!(sm.tick_clock(0.5), None);
+        assert!(sm.overtime);
+        assert_eq!(sm.clock, None);
+
+        sm.touch(EntityId(5), RED);
+        sm.concede(BLUE);
+        assert_eq!(
+            sm.check_goal(),
+            Some(MatchOutcome::Over { winner: Some(RED) })
+        );
+    }
+
+    #[test]
+    fn best_of_three_plays_on_until_a_team_takes_two_rounds() {
+        let mut sm = score_manager(2);
+        sm.set_rules(MatchRules {
+            score_limit: 1,
+            best_of: 3,
+            ..MatchRules::default()
+        });
+        sm.start_match();
+
+        sm.concede(RED);
+        assert_eq!(
+            sm.check_goal(),
+            Some(MatchOutcome::NextRound { winner: Some(BLUE) })
+        );
+        assert_eq!((sm.round, scores(&sm)), (2, vec![0, 0]));
+
+        sm.concede(BLUE);
+        assert_eq!(
+            sm.check_goal(),
+            Some(MatchOutcome::NextRound { winner: Some(RED) })
+        );
+
+        sm.concede(RED);
+        assert_eq!(
+            sm.check_goal(),
+            Some(MatchOutcome::Over { winner: Some(BLUE) })
+        );
+        assert_eq!(sm.teams[0].rounds_won, 2);
     }
 }
@@ -1,52 +1,131 @@
+use crate::game::arena::{Arena, SpawnPoint};
+use crate::game::physics::HOLD_DISTANCE;
 use crate::game::state::entityid::EntityId;
 use crate::game::state::{enums::Kind, State};
+use crate::game::team::TeamManager;
 use crate::game::world::World;
-pub const PLAYER_POSITIONS: [(f32, f32, f32); 8] = [
-    (50.0, 50.0, 0.0),
-    (270.0, 50.0, 3.142),
-    (50.0, 130.0, 0.0),
-    (270.0, 130.0, 3.142),
-    (160.0, 50.0, 0.0),
-    (160.0, 130.0, 3.142),
-    (90.0, 90.0, 0.0),
-    (230.0, 90.0, 3.142),
-];
 
 pub struct SpawnManager {
     ball_start: Option<(f32, f32)>,
     ball_id: Option<EntityId>,
-    pub width: f32,
-    pub height: f32,
+    arena: Arena,
 }
 
 impl SpawnManager {
     //Constructor
-    pub fn new(width: f32, height: f32) -> Self {
+    pub fn new(arena: Arena) -> Self {
         Self {
             ball_start: None,
             ball_id: None,
-            width,
-            height,
+            arena,
         }
     }
 
-    pub fn try_add_player(&mut self, world: &mut World, team_id: u8) -> Option<EntityId> {
+    pub fn arena(&self) -> &Arena {
+        &self.arena
+    }
+
+    ///Takes effect the next time the states are spawned.
+    pub fn set_arena(&mut self, arena: Arena) {
+        println!("Selected map {}", arena.name);
+        self.arena = arena;
+    }
+
+    pub fn try_add_player(
+        &mut self,
+        world: &mut World,
+        teams: &mut TeamManager,
+        team_id: u8,
+    ) -> Option<EntityId> {
         if world.reached_expected_player_count() {
             println!("Max players reached!");
             return None;
         }
+        match teams.get(team_id) {
+            Some(team) if team.is_full() => {
+                println!("Team {} is full!", team_id);
+                return None;
+            }
+            Some(_) => {}
+            None => {
+                println!("No team {} on {}", team_id, self.arena.name);
+                return None;
+            }
+        }
 
-        let (x, y, angle) = PLAYER_POSITIONS[world.curr_player_count()];
-        let player_id = self.add_player(world, team_id, x, y, angle);
+        // Parked on the team spawn, the formation goes around it once it's joined
+        let spawn = self.arena.spawn_for(team_id)?;
+        let player_id = self.add_player(world, team_id, spawn.x, spawn.y, spawn.angle);
+        teams.join(team_id, player_id).ok()?;
+
+        if let Some(spot) = self.formation_spot(teams, player_id) {
+            let state = world.grab_entity(player_id)?;
+            state.physics_state.pos.x = spot.x;
+            state.physics_state.pos.y = spot.y;
+        }
         Some(player_id)
     }
 
-    pub fn remove_player(&mut self, world: &mut World, player_id: EntityId) {
+    pub fn remove_player(
+        &mut self,
+        world: &mut World,
+        teams: &mut TeamManager,
+        player_id: EntityId,
+    ) {
         world.remove_player(player_id);
+        teams.leave(player_id);
+
+        // Bricks go with whoever placed them
+        let bricks: Vec<EntityId> = world
+            .entities
+            .iter()
+            .filter(|s| s.kind == Kind::Brick && s.owner_id == Some(player_id))
+            .map(|s| s.entity_id)
+            .collect();
+        for brick_id in bricks {
+            world.remove_entity(brick_id);
+        }
     }
 
-    pub fn remove_all(&mut self, world: &mut World) {
+    ///Moves a lobby player over to `team_id`, parked on that team's spawn.
+    pub fn switch_team(
+        &self,
+        world: &mut World,
+        teams: &mut TeamManager,
+        player_id: EntityId,
+        team_id: u8,
+    ) -> Result<(), String> {
+        if teams
+            .slot_of(player_id)
+            .is_some_and(|(team, _)| team.team_id == team_id)
+        {
+            return Ok(());
+        }
+        let spawn = self
+            .arena
+            .spawn_for(team_id)
+            .ok_or_else(|| format!("no team {} on {}", team_id, self.arena.name))?;
+        if teams.get(team_id).is_some_and(|team| team.is_full()) {
+            return Err(format!("team {} is full", team_id));
+        }
+
+        teams.leave(player_id);
+        teams.join(team_id, player_id)?;
+        if let Some(state) = world.grab_entity(player_id) {
+            state.team_id = Some(team_id);
+            state.physics_state.pos.x = spawn.x;
+            state.physics_state.pos.y = spawn.y;
+            state.physics_state.angle = spawn.angle;
+        }
+        if let Some(player) = world.lobby_player_mut(player_id) {
+            player.team_id = Some(team_id);
+        }
+        Ok(())
+    }
+
+    pub fn remove_all(&mut self, world: &mut World, teams: &mut TeamManager) {
         world.remove_all();
+        teams.clear_players();
         self.ball_id = None;
     }
 
@@ -59,30 +138,52 @@ impl SpawnManager {
     pub fn spawn_states(&mut self, world: &mut World) {
         //Borders
         self.create_borders(world);
+        //Walls
+        for wall in &self.arena.walls {
+            let mut state = State::new_wall(wall.x, wall.y, 0.0, 0.0);
+            state.physics_state.shape = wall.shape;
+            state.physics_state.angle = wall.angle;
+            world.add_entity(state);
+        }
         // Ball
-        self.add_ball(world, 160.0, 90.0); // center
-                                           // Goals
-        self.add_goal(world, 0.0, 60.0, 0);
-        self.add_goal(world, 290.0, 60.0, 1);
+        let kickoff = self.arena.ball_spawns[0];
+        self.add_ball(world, kickoff.x, kickoff.y);
+        // Goals
+        for goal in self.arena.goals.clone() {
+            self.add_goal(world, goal.x, goal.y, goal.w, goal.h, goal.team_id);
+        }
+    }
+
+    ///Moves the kickoff to the map's next ball spawn, used from the next reset on.
+    pub fn next_ball_spawn(&mut self) {
+        let spawns = &self.arena.ball_spawns;
+        let current = spawns
+            .iter()
+            .position(|spawn| Some((spawn.x, spawn.y)) == self.ball_start);
+        let next = spawns[current.map_or(0, |i| (i + 1) % spawns.len())];
+        self.ball_start = Some((next.x, next.y));
     }
 
     fn create_borders(&mut self, world: &mut World) {
-        let thickness = 10.0; // wall thickness
+        let thickness = self.arena.border; // wall thickness
+        if thickness <= 0.0 {
+            return;
+        }
+        let (width, height) = (self.arena.width, self.arena.height);
 
         // Top wall
-        world.add_entity(State::new_wall(0.0, -thickness, self.width, thickness));
+        world.add_entity(State::new_wall(0.0, -thickness, width, thickness));
         // Bottom wall
-        world.add_entity(State::new_wall(0.0, self.height, self.width, thickness));
+        world.add_entity(State::new_wall(0.0, height, width, thickness));
 
         // Left wall
-        world.add_entity(State::new_wall(-thickness, 0.0, thickness, self.height));
+        world.add_entity(State::new_wall(-thickness, 0.0, thickness, height));
 
         // Right wall
-        world.add_entity(State::new_wall(self.width, 0.0, thickness, self.height));
+        world.add_entity(State::new_wall(width, 0.0, thickness, height));
     }
 
-    pub fn reset_states(&self, world: &mut World) {
-        let mut player_index = 0;
+    pub fn reset_states(&self, world: &mut World, teams: &TeamManager) {
         for state in world.entities.iter_mut() {
             if state.is_static {
                 continue;
@@ -100,9 +201,12 @@ impl SpawnManager {
                     }
                 }
                 Kind::Player => {
-                    let (px, py, angle) = PLAYER_POSITIONS[player_index];
-                    state.physics_state.pos.x = px;
-                    state.physics_state.pos.y = py;
+                    let Some(spawn) = self.formation_spot(teams, state.entity_id) else {
+                        continue;
+                    };
+                    let angle = spawn.angle;
+                    state.physics_state.pos.x = spawn.x;
+                    state.physics_state.pos.y = spawn.y;
                     state.physics_state.angle = angle;
                     state.held_by = None;
                     state.physics_state.vel.x = 0.0;
@@ -112,29 +216,82 @@ impl SpawnManager {
                         .as_mut()
                         .unwrap()
                         .reset_player(angle);
-                    player_index += 1;
                 }
                 Kind::Brick => {
-                    state.time_to_live = Some(0);
+                    state.time_to_live = Some(0.0);
                 }
                 _ => {}
             }
         }
     }
 
+    ///Hands the ball to `team_id`'s player closest to the kickoff spot, returns who got it.
+    pub fn give_possession(
+        &self,
+        world: &mut World,
+        teams: &TeamManager,
+        team_id: u8,
+    ) -> Option<EntityId> {
+        let (bx, by) = self.ball_start?;
+        let team = teams.get(team_id)?;
+        let to_kickoff = |s: &State| {
+            let (dx, dy) = (s.physics_state.pos.x - bx, s.physics_state.pos.y - by);
+            dx * dx + dy * dy
+        };
+        let player_id = world
+            .entities
+            .iter()
+            .filter(|s| team.player_ids.contains(&s.entity_id))
+            .min_by(|a, b| to_kickoff(a).total_cmp(&to_kickoff(b)))?
+            .entity_id;
+
+        let (ball, player) = world.grab_two_entities(self.ball_id?, player_id)?;
+        let angle = player.physics_state.angle;
+        ball.physics_state.pos.x = player.physics_state.pos.x + angle.cos() * HOLD_DISTANCE;
+        ball.physics_state.pos.y = player.physics_state.pos.y + angle.sin() * HOLD_DISTANCE;
+        ball.held_by = Some(player_id);
+        player.player_controller.as_mut()?.give_ball();
+        Some(player_id)
+    }
+
+    ///Where the player stands in their team's formation, mirrored to the side the team starts on.
+    fn formation_spot(&self, teams: &TeamManager, player_id: EntityId) -> Option<SpawnPoint> {
+        let (team, slot) = teams.slot_of(player_id)?;
+        let spawn = self.arena.spawn_for(team.team_id)?;
+        team.spawn_pattern
+            .place(team.player_ids.len(), spawn)
+            .get(slot)
+            .copied()
+    }
+
     ///Private
     //Add Functions:
-    pub fn add_player(&mut self, world: &mut World, team_id: u8, x: f32, y: f32, angle: f32) -> EntityId {
+    pub fn add_player(
+        &mut self,
+        world: &mut World,
+        team_id: u8,
+        x: f32,
+        y: f32,
+        angle: f32,
+    ) -> EntityId {
         let player = State::new_player(team_id, x, y, angle);
 
-        let id = player.entity_id;
+        let id = world.add_player(player);
         println!("Added player with ID: {}", id.0);
-        world.add_player(player);
         id
     }
 
-    pub fn add_brick(&mut self, world: &mut World, pos: (f32, f32), player_id: EntityId) {
-        let brick = State::new_brick(pos.0, pos.1, 8.0, player_id);
+    ///Brick centred on `pos`, turned to face the same way as the player
+    pub fn add_brick(
+        &mut self,
+        world: &mut World,
+        pos: (f32, f32),
+        angle: f32,
+        player_id: EntityId,
+    ) {
+        let size = 8.0;
+        let mut brick = State::new_brick(pos.0 - size / 2.0, pos.1 - size / 2.0, size, player_id);
+        brick.physics_state.angle = angle;
         world.add_entity(brick);
     }
 
@@ -144,17 +301,26 @@ impl SpawnManager {
 
     fn add_ball(&mut self, world: &mut World, x: f32, y: f32) {
         let ball = State::new_ball(x, y);
-        self.ball_id = Some(ball.entity_id);
-        world.add_entity(ball);
+        self.ball_id = Some(world.add_entity(ball));
 
         self.ball_start = Some((x, y));
     }
 
-    fn add_goal(&mut self, world: &mut World, x: f32, y: f32, team_id: u8) {
-        world.add_entity(State::new_goal(x, y, 30.0, 60.0, team_id));
+    fn add_goal(&mut self, world: &mut World, x: f32, y: f32, w: f32, h: f32, team_id: u8) {
+        world.add_entity(State::new_goal(x, y, w, h, team_id));
     }
 
     pub fn get_ball_id(&self) -> Option<EntityId> {
         return self.ball_id;
     }
+
+    pub fn get_ball_start(&self) -> Option<(f32, f32)> {
+        self.ball_start
+    }
+
+    ///Puts back the ball bookkeeping from a saved world.
+    pub fn restore_ball(&mut self, ball_id: Option<EntityId>, ball_start: Option<(f32, f32)>) {
+        self.ball_id = ball_id;
+        self.ball_start = ball_start;
+    }
 }
@@ -1,14 +1,150 @@
-use std::sync::atomic::{AtomicU32, Ordering};
-
 use serde::{Deserialize, Serialize};
 
+const INDEX_BITS: u32 = 20;
+const INDEX_MASK: u32 = (1 << INDEX_BITS) - 1;
+const GENERATION_MASK: u32 = u32::MAX >> INDEX_BITS;
+
+/// Low 20 bits are the slot, high 12 bits count how often the slot was reused.
+/// Still one number on the wire and in the frontend. The generation wraps after
+/// 4096 reuses, so an id held on to for that long can match its slot again.
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct EntityId(pub u32);
 
-static ENTITY_ID_COUNTER: AtomicU32 = AtomicU32::new(1);
-
 impl EntityId {
+    /// Placeholder until World::add_entity hands out a real id
+    pub const UNASSIGNED: EntityId = EntityId(0);
+
+    pub fn from_parts(index: u32, generation: u32) -> Self {
+        EntityId((generation & GENERATION_MASK) << INDEX_BITS | (index & INDEX_MASK))
+    }
+
+    pub fn index(self) -> u32 {
+        self.0 & INDEX_MASK
+    }
+
+    pub fn generation(self) -> u32 {
+        self.0 >> INDEX_BITS
+    }
+}
+
+///Hands out entity ids for one World. Freed slots are reused with a bumped
+///generation, so an id kept around after its entity died never matches a new one.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EntityIdAllocator {
+    generations: Vec<u32>, // per slot, slot 0 is UNASSIGNED
+    free: Vec<u32>,
+}
+
+impl EntityIdAllocator {
     pub fn new() -> Self {
-        EntityId(ENTITY_ID_COUNTER.fetch_add(1, Ordering::Relaxed))
+        Self {
+            generations: vec![0],
+            free: Vec::new(),
+        }
+    }
+
+    pub fn allocate(&mut self) -> EntityId {
+        if let Some(index) = self.free.pop() {
+            return EntityId::from_parts(index, self.generations[index as usize]);
+        }
+
+        let index = self.generations.len() as u32;
+        assert!(index <= INDEX_MASK, "ran out of entity slots");
+        self.generations.push(0);
+        EntityId::from_parts(index, 0)
+    }
+
+    ///Frees the slot. Ids that are already stale are ignored.
+    pub fn release(&mut self, id: EntityId) {
+        if !self.is_current(id) {
+            return;
+        }
+        let index = id.index();
+        let generation = &mut self.generations[index as usize];
+        *generation = (*generation + 1) & GENERATION_MASK;
+        self.free.push(index);
+    }
+
+    pub fn is_current(&self, id: EntityId) -> bool {
+        id != EntityId::UNASSIGNED
+            && self
+                .generations
+                .get(id.index() as usize)
+                .is_some_and(|generation| *generation == id.generation())
+    }
+
+    ///Frees every slot still in use. Unlike starting over, ids handed out before
+    ///stay stale instead of matching whatever gets their slot next.
+    pub fn release_all(&mut self) {
+        let mut in_use = vec![true; self.generations.len()];
+        in_use[0] = false;
+        for index in &self.free {
+            in_use[*index as usize] = false;
+        }
+
+        for (index, used) in in_use.into_iter().enumerate() {
+            if used {
+                let index = index as u32;
+                self.release(EntityId::from_parts(
+                    index,
+                    self.generations[index as usize],
+                ));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reused_slot_gets_the_next_generation() {
+        let mut ids = EntityIdAllocator::new();
+        let first = ids.allocate();
+        ids.release(first);
+        let second = ids.allocate();
+
+        assert_eq!(second.index(), first.index());
+        assert_eq!(second.generation(), first.generation() + 1);
+        assert!(!ids.is_current(first));
+        assert!(ids.is_current(second));
+
+        // Releasing the stale id again doesn't free the slot out from under the new one
+        ids.release(first);
+        assert!(ids.is_current(second));
+        assert_ne!(ids.allocate().index(), second.index());
+    }
+
+    #[test]
+    fn generation_wraps_after_twelve_bits() {
+        let mut ids = EntityIdAllocator::new();
+        let first = ids.allocate();
+        let mut id = first;
+        for _ in 0..GENERATION_MASK {
+            ids.release(id);
+            id = ids.allocate();
+        }
+        assert_eq!(id.generation(), GENERATION_MASK);
+
+        ids.release(id);
+        let wrapped = ids.allocate();
+        assert_eq!(wrapped.generation(), 0);
+        assert_eq!(wrapped, first);
+    }
+
+    #[test]
+    fn release_all_leaves_no_old_id_current() {
+        let mut ids = EntityIdAllocator::new();
+        let old: Vec<EntityId> = (0..3).map(|_| ids.allocate()).collect();
+        ids.release(old[1]);
+
+        ids.release_all();
+        let new: Vec<EntityId> = (0..3).map(|_| ids.allocate()).collect();
+
+        for id in &old {
+            assert!(!ids.is_current(*id));
+            assert!(!new.contains(id));
+        }
     }
 }
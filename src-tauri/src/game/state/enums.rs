@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Kind {
     Player,
     Brick,
@@ -16,9 +16,10 @@ pub enum ActionState {
     Dashing,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Shape {
     Circle { radius: f32 },
     Rectangle { w: f32, h: f32 },
+    Capsule { radius: f32, length: f32 }, // segment of `length` along the angle, grown by `radius`
 }
@@ -1,17 +1,64 @@
-use crate::game::state::entityid::EntityId;
+use crate::{
+    game::{bot::BotDifficulty, state::entityid::EntityId},
+    network::clientid::ClientId,
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+pub const MAX_NAME_LENGTH: usize = 16;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct LobbyPlayer {
     pub player_id: EntityId,
+    /// Picked in the lobby, the team they play for
     pub team_id: Option<u8>,
+    #[serde(default)]
+    pub name: String,
+    /// Who controls them, None for bots
+    #[serde(default)]
+    pub client_id: Option<ClientId>,
+    /// The match only starts once everyone is
+    #[serde(default)]
+    pub ready: bool,
+    /// Played by the server when set
+    #[serde(default)]
+    pub bot: Option<BotDifficulty>,
+    /// Dropped off the network, kept around for a while in case they come back
+    #[serde(default)]
+    pub disconnected: bool,
     //pub spawn_id: Option<u8>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+impl LobbyPlayer {
+    ///Trimmed, without control characters and not too long.
+    pub fn clean_name(name: &str) -> Result<String, String> {
+        let name: String = name.trim().chars().filter(|c| !c.is_control()).collect();
+        if name.is_empty() {
+            return Err("a name can't be blank".into());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!("names are at most {MAX_NAME_LENGTH} characters"));
+        }
+        Ok(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct LobbyState {
     pub expected_players: usize,
     pub players: Vec<LobbyPlayer>,
 }
 
+impl LobbyState {
+    pub fn all_ready(&self) -> bool {
+        self.players.iter().all(|player| player.ready)
+    }
+
+    ///Everyone but the bots has to ready up again.
+    pub fn unready(&mut self) {
+        for player in &mut self.players {
+            player.ready = player.bot.is_some();
+        }
+    }
+}
+
 //I'm good with these in same file for now. if they get too big or have functions added might split );
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
 
-use crate::game::state::{entityid::EntityId, enums::ActionState, Kind, Shape, State};
+use crate::game::{
+    input::inputframe::Vec2,
+    state::{entityid::EntityId, enums::ActionState, Kind, Shape, State},
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct RenderState {
@@ -21,6 +24,8 @@ pub struct RenderState {
     pub kind: Kind,
     pub player_id: EntityId,
     pub team_id: Option<u8>,
+    /// Sequence of the last input the host applied to this player, 0 for everything else
+    pub input_sequence: u32,
 }
 impl From<&State> for RenderState {
     fn from(state: &State) -> Self {
@@ -42,6 +47,48 @@ impl From<&State> for RenderState {
             kind: state.kind,
             player_id: state.entity_id,
             team_id: state.team_id,
+            input_sequence: state
+                .player_controller
+                .as_ref()
+                .map_or(0, |pc| pc.input.sequence),
         }
     }
 }
+
+///Rebuilds a simulated State from what the host sent, so clients can run
+///Physics on it. Mass, friction etc. come from the matching constructor.
+impl From<&RenderState> for State {
+    fn from(rs: &RenderState) -> Self {
+        let (w, h) = match rs.shape {
+            Shape::Rectangle { w, h } => (w, h),
+            Shape::Circle { radius } => (radius * 2.0, radius * 2.0),
+            Shape::Capsule { radius, length } => (length + radius * 2.0, radius * 2.0),
+        };
+        let entity_id = EntityId(rs.id);
+
+        let mut s = match rs.kind {
+            Kind::Player => State::new_player(rs.team_id.unwrap_or(0), rs.x, rs.y, rs.angle),
+            Kind::Ball => State::new_ball(rs.x, rs.y),
+            Kind::Wall => State::new_wall(rs.x, rs.y, w, h),
+            Kind::Goal => State::new_goal(rs.x, rs.y, w, h, rs.team_id.unwrap_or(0)),
+            Kind::Brick => {
+                let mut brick = State::new_brick(rs.x, rs.y, w, entity_id);
+                brick.time_to_live = None; // the host decides when bricks die
+                brick
+            }
+        };
+
+        s.entity_id = entity_id;
+        s.physics_state.vel = Vec2 { x: rs.vx, y: rs.vy };
+        s.physics_state.angle = rs.angle;
+        s.physics_state.shape = rs.shape;
+        s.is_static = rs.is_static;
+        s.action_state = rs.action_state;
+        s.team_id = rs.team_id;
+        if let Some(pc) = &mut s.player_controller {
+            pc.player_id = entity_id;
+            pc.set_angle(rs.angle);
+        }
+        s
+    }
+}
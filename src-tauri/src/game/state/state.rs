@@ -5,8 +5,12 @@ pub mod physicsstate;
 pub mod renderstate;
 
 use crate::game::{
+    collision::Contact,
     eventqueue::{EventQueue, GameEvent},
-    input::{inputframe::Vec2, playercontroller::PlayerController},
+    input::{
+        inputframe::Vec2,
+        playercontroller::{PlayerController, DASH_KNOCKBACK},
+    },
     physics::Physics,
     state::{entityid::EntityId, enums::*, physicsstate::PhysicsState},
     util::Util,
@@ -21,7 +25,7 @@ pub struct State {
     pub is_alive: bool,
     pub kind: Kind,
     pub entity_id: EntityId,
-    pub time_to_live: Option<u16>,
+    pub time_to_live: Option<f32>, // seconds
     pub team_id: Option<u8>,
     pub held_by: Option<EntityId>,
     pub owner_id: Option<EntityId>,
@@ -41,11 +45,12 @@ impl State {
         if let Some(ttl) = self.time_to_live {
             //println!("dying counter: {}", ttl);
 
-            if ttl <= 1 {
+            let remaining = ttl - dt;
+            if remaining <= 0.0 {
                 self.time_to_live = None;
                 self.die(events);
             } else {
-                self.time_to_live = Some(ttl - 1);
+                self.time_to_live = Some(remaining);
             }
         }
 
@@ -69,131 +74,72 @@ impl State {
     }
 
     fn determine_action_state(&mut self) {
-        self.action_state =
-            if self.physics_state.vel.x.abs() > 0.1 || self.physics_state.vel.y.abs() > 0.1 {
-                ActionState::Moving
-            } else {
-                ActionState::Idle
-            };
-    }
-
-    pub fn handle_collision(states: &mut Vec<State>, i: usize, j: usize, events: &mut EventQueue) {
-        let (a, b) = Util::two_mut(states, i, j);
-
-        // Declare variables here
-        let (nx, ny, overlap) = match (&a.physics_state.shape, &b.physics_state.shape) {
-            (Shape::Circle { .. }, Shape::Circle { .. }) => {
-                // For circle-circle, use full overlap
-                if let Some((nx, ny, overlap)) =
-                    PhysicsState::find_overlap(&a.physics_state, &b.physics_state)
-                {
-                    (nx, ny, overlap)
-                } else {
-                    // No collision; early return
-                    return;
-                }
-            }
-            _ => {
-                // Everything else: simple arcade bounce
-                a.compute_min_axis_overlap(b)
-            }
-        };
+        let dashing = self
+            .player_controller
+            .as_ref()
+            .is_some_and(|pc| pc.is_dashing());
 
-        // Trigger handling
-        if a.is_trigger {
-            a.handle_trigger_collision(b, events);
-        } else if b.is_trigger {
-            b.handle_trigger_collision(a, events);
+        self.action_state = if dashing {
+            ActionState::Dashing
+        } else if self.physics_state.vel.x.abs() > 0.1 || self.physics_state.vel.y.abs() > 0.1 {
+            ActionState::Moving
         } else {
-            // Resolve physics
-            Physics::resolve_pair(&mut a.physics_state, &mut b.physics_state, nx, ny, overlap);
-        }
+            ActionState::Idle
+        };
     }
 
-    pub fn handle_pure_trigger(
-        states: &mut Vec<State>,
+    ///Resolves a contact found by the narrow phase, normal pointing from i to j
+    pub fn handle_collision(
+        states: &mut [State],
         i: usize,
         j: usize,
+        contact: Contact,
         events: &mut EventQueue,
     ) {
         let (a, b) = Util::two_mut(states, i, j);
+        let Contact { nx, ny, depth } = contact;
 
-        match (&a.physics_state.shape, &b.physics_state.shape) {
-            (Shape::Circle { .. }, Shape::Circle { .. }) => {
-                if let Some((nx, ny, overlap)) =
-                    PhysicsState::find_overlap(&a.physics_state, &b.physics_state)
-                {
-                    (nx, ny, overlap)
-                } else {
-                    return; // no overlap
-                }
-            }
-            _ => a.compute_min_axis_overlap(b),
-        };
-
+        // Trigger handling
         if a.is_trigger {
             a.handle_trigger_collision(b, events);
-        }
-        if b.is_trigger {
+        } else if b.is_trigger {
             b.handle_trigger_collision(a, events);
-        }
-    }
+        } else {
+            // Only knock back on the hit itself, not while they're already moving apart
+            let closing = (b.physics_state.vel.x - a.physics_state.vel.x) * nx
+                + (b.physics_state.vel.y - a.physics_state.vel.y) * ny
+                < 0.0;
 
-    // Compute the minimum-penetration axis for simple arcade collisions
-    fn compute_min_axis_overlap(&self, other: &State) -> (f32, f32, f32) {
-        // Extract sizes from shapes
-        let (aw, ah, ax_center, ay_center) = match &self.physics_state.shape {
-            Shape::Rectangle { w, h } => (
-                w,
-                h,
-                self.physics_state.pos.x + w / 2.0,
-                self.physics_state.pos.y + h / 2.0,
-            ),
-            Shape::Circle { radius } => {
-                let d = radius * 2.0;
-                (
-                    &d.clone(),
-                    &d.clone(),
-                    self.physics_state.pos.x,
-                    self.physics_state.pos.y,
-                ) // x,y = center
-            }
-        };
-        let (bw, bh, bx_center, by_center) = match &other.physics_state.shape {
-            Shape::Rectangle { w, h } => (
-                w,
-                h,
-                other.physics_state.pos.x + w / 2.0,
-                other.physics_state.pos.y + h / 2.0,
-            ),
-            Shape::Circle { radius } => {
-                let d = radius * 2.0;
-                (
-                    &d.clone(),
-                    &d.clone(),
-                    other.physics_state.pos.x,
-                    other.physics_state.pos.y,
-                ) // x,y = center
-            }
-        };
+            // Resolve physics
+            Physics::resolve_pair(&mut a.physics_state, &mut b.physics_state, nx, ny, depth);
 
-        // Delta between centers
-        let dx = bx_center - ax_center;
-        let dy = by_center - ay_center;
+            if closing && a.is_dashing_into(b) {
+                Physics::apply_impulse(&mut b.physics_state, ny.atan2(nx), DASH_KNOCKBACK);
+            } else if closing && b.is_dashing_into(a) {
+                Physics::apply_impulse(&mut a.physics_state, (-ny).atan2(-nx), DASH_KNOCKBACK);
+            }
 
-        // Combined half sizes
-        let combined_half_width = (aw + bw) / 2.0;
-        let combined_half_height = (ah + bh) / 2.0;
+            if let Some(player_id) = a.bumping_ball(b).or_else(|| b.bumping_ball(a)) {
+                events.push(GameEvent::BallTouched { player_id });
+            }
+        }
+    }
 
-        // Overlap along each axis
-        let overlap_x = combined_half_width - dx.abs();
-        let overlap_y = combined_half_height - dy.abs();
+    ///A player knocking the loose ball about, for who gets the goal
+    fn bumping_ball(&self, other: &State) -> Option<EntityId> {
+        let loose_ball = other.kind == Kind::Ball && other.held_by.is_none();
+        (self.kind == Kind::Player && loose_ball).then_some(self.entity_id)
+    }
 
-        // Pick axis of minimum penetration
-        if overlap_x < overlap_y {
-            (dx.signum(), 0.0, overlap_x)
-        } else {
-            (0.0, dy.signum(), overlap_y)
+    ///Dashing players hit the ball and enemy players harder
+    fn is_dashing_into(&self, other: &State) -> bool {
+        if self.action_state != ActionState::Dashing {
+            return false;
+        }
+        match other.kind {
+            Kind::Ball => true,
+            Kind::Player => other.team_id != self.team_id,
+            _ => false,
         }
     }
 
@@ -218,6 +164,11 @@ impl State {
         }
     }
 
+    ///Walls, goals and dead bricks, nothing moves these
+    pub fn is_fixed(&self) -> bool {
+        self.is_static || self.physics_state.is_static
+    }
+
     pub fn is_holding(&self) -> bool {
         if self.player_controller.is_none() {
             return false;
@@ -238,7 +189,7 @@ impl State {
             is_alive: true,
             kind: Kind::Ball,
             time_to_live: None,
-            entity_id: EntityId::new(),
+            entity_id: EntityId::UNASSIGNED,
             owner_id: None,
             team_id: None,
             held_by: None,
@@ -268,7 +219,7 @@ impl State {
         s.physics_state.restitution = 0.6;
         s.team_id = Some(team_id);
         s.kind = Kind::Player;
-        s.player_controller = Some(PlayerController::new(75.0, 400.0, s.entity_id));
+        s.player_controller = Some(PlayerController::new(4500.0, 400.0, s.entity_id));
         s
     }
 
@@ -289,7 +240,7 @@ impl State {
         s.physics_state.shape = Shape::Rectangle { w, h: w };
         s.kind = Kind::Brick;
         s.physics_state.mass = 20.0;
-        s.time_to_live = Some(60 * 5); // 5 seconds
+        s.time_to_live = Some(5.0);
         s.is_static = false;
         s.owner_id = Some(entity_id);
         s
@@ -307,3 +258,59 @@ impl State {
         s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    ///A player moving right at 200 running into `other` just in front of it.
+    fn bump(dashing: bool, other: State) -> (State, State) {
+        let mut player = State::new_player(0, 0.0, 0.0, 0.0);
+        player.physics_state.vel = Vec2 { x: 200.0, y: 0.0 };
+        if dashing {
+            player.action_state = ActionState::Dashing;
+        }
+        let mut states = vec![player, other];
+        let contact = Contact {
+            nx: 1.0,
+            ny: 0.0,
+            depth: 1.0,
+        };
+        State::handle_collision(&mut states, 0, 1, contact, &mut EventQueue::new());
+        let other = states.pop().unwrap();
+        (states.pop().unwrap(), other)
+    }
+
+    #[test]
+    fn dashing_into_the_ball_knocks_it_further() {
+        let (_, walked) = bump(false, State::new_ball(7.0, 0.0));
+        let (_, dashed) = bump(true, State::new_ball(7.0, 0.0));
+
+        let extra = dashed.physics_state.vel.x - walked.physics_state.vel.x;
+        assert!((extra - DASH_KNOCKBACK).abs() < 1e-3, "extra {extra}");
+        assert_eq!(dashed.physics_state.vel.y, 0.0);
+    }
+
+    #[test]
+    fn dash_knockback_hits_enemies_but_not_teammates() {
+        let enemy = || State::new_player(1, 9.0, 0.0, 0.0);
+        let teammate = || State::new_player(0, 9.0, 0.0, 0.0);
+
+        let (_, walked) = bump(false, enemy());
+        let (_, dashed) = bump(true, enemy());
+        let extra = dashed.physics_state.vel.x - walked.physics_state.vel.x;
+        assert!((extra - DASH_KNOCKBACK).abs() < 1e-3, "extra {extra}");
+
+        let (_, walked) = bump(false, teammate());
+        let (_, dashed) = bump(true, teammate());
+        assert_eq!(dashed.physics_state.vel.x, walked.physics_state.vel.x);
+    }
+
+    #[test]
+    fn no_knockback_once_they_are_moving_apart() {
+        let mut ball = State::new_ball(7.0, 0.0);
+        ball.physics_state.vel = Vec2 { x: 500.0, y: 0.0 };
+        let (_, dashed) = bump(true, ball);
+        assert!(dashed.physics_state.vel.x <= 500.0);
+    }
+}
@@ -1,11 +1,17 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+use crate::game::arena::SpawnPoint;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum SpawnPattern {
     Line,
+    #[default]
     Diamond,
     Spread,
 }
 
 impl SpawnPattern {
+    ///Offsets from the team's spawn, +x is towards the other side.
     pub fn positions(&self, player_count: usize) -> Vec<(f32, f32)> {
         match self {
             SpawnPattern::Line => line_positions(player_count),
@@ -13,6 +19,25 @@ impl SpawnPattern {
             SpawnPattern::Spread => spread_positions(player_count),
         }
     }
+
+    ///Where each of `player_count` players goes around `spawn`, all facing the same way it does.
+    pub fn place(&self, player_count: usize, spawn: SpawnPoint) -> Vec<SpawnPoint> {
+        let (sin, cos) = spawn.angle.sin_cos();
+        // A team facing back the other way is the mirror image, not the formation turned upside down
+        let flip = if cos < -0.001 { -1.0 } else { 1.0 };
+
+        self.positions(player_count)
+            .into_iter()
+            .map(|(dx, dy)| {
+                let dy = dy * flip;
+                SpawnPoint {
+                    x: spawn.x + dx * cos - dy * sin,
+                    y: spawn.y + dx * sin + dy * cos,
+                    angle: spawn.angle,
+                }
+            })
+            .collect()
+    }
 }
 
 fn line_positions(player_count: usize) -> Vec<(f32, f32)> {
@@ -44,3 +69,42 @@ fn spread_positions(player_count: usize) -> Vec<(f32, f32)> {
         _ => Vec::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn opposing_side_is_the_mirror_image() {
+        let left = SpawnPoint {
+            x: 70.0,
+            y: 90.0,
+            angle: 0.0,
+        };
+        let right = SpawnPoint {
+            x: 250.0,
+            y: 90.0,
+            angle: PI,
+        };
+
+        for pattern in [
+            SpawnPattern::Line,
+            SpawnPattern::Diamond,
+            SpawnPattern::Spread,
+        ] {
+            for count in 1..=4 {
+                let ours = pattern.place(count, left);
+                let theirs = pattern.place(count, right);
+                assert_eq!(ours.len(), count);
+
+                for (a, b) in ours.iter().zip(&theirs) {
+                    assert!((a.x - (320.0 - b.x)).abs() < 1e-3, "{pattern:?} {count}");
+                    assert!((a.y - b.y).abs() < 1e-3, "{pattern:?} {count}");
+                    assert_eq!(b.angle, PI);
+                }
+            }
+        }
+    }
+}
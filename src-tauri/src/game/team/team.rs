@@ -1,12 +1,173 @@
 pub mod spawnpattern;
 
+use serde::{Deserialize, Serialize};
+
+use crate::game::{state::entityid::EntityId, team::spawnpattern::SpawnPattern};
+
+/// Formations only go up to four
+pub const MAX_TEAM_SIZE: usize = 4;
+pub const MIN_TEAMS: usize = 2;
+pub const MAX_TEAMS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Team {
     pub team_id: u8,
+    /// In join order, which is also the order of the formation slots
     pub player_ids: Vec<EntityId>,
-    color: (f32, f32, f32),
-    spawn_pattern: SpawnPattern,
+    pub max_players: usize,
+    pub spawn_pattern: SpawnPattern,
+}
+
+impl Team {
+    pub fn new(team_id: u8) -> Self {
+        Self {
+            team_id,
+            player_ids: Vec::new(),
+            max_players: MAX_TEAM_SIZE,
+            spawn_pattern: SpawnPattern::default(),
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.player_ids.len() >= self.max_players
+    }
 }
 
-struct TeamManager {
+///Who is on which team. The lobby only mirrors this for the frontend.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct TeamManager {
     teams: Vec<Team>,
 }
+
+impl TeamManager {
+    pub fn new(team_ids: impl IntoIterator<Item = u8>) -> Self {
+        let mut tm = Self::default();
+        tm.set_teams(team_ids);
+        tm
+    }
+
+    ///Makes these the teams, ones that stay keep their players and settings.
+    pub fn set_teams(&mut self, team_ids: impl IntoIterator<Item = u8>) {
+        let mut old = std::mem::take(&mut self.teams);
+        for team_id in team_ids {
+            let team = match old.iter().position(|t| t.team_id == team_id) {
+                Some(index) => old.swap_remove(index),
+                None => Team::new(team_id),
+            };
+            self.teams.push(team);
+        }
+    }
+
+    pub fn get(&self, team_id: u8) -> Option<&Team> {
+        self.teams.iter().find(|t| t.team_id == team_id)
+    }
+
+    ///The team with the fewest players that still has room, lowest id on a tie.
+    pub fn open_team(&self) -> Option<u8> {
+        self.teams
+            .iter()
+            .filter(|t| !t.is_full())
+            .min_by_key(|t| (t.player_ids.len(), t.team_id))
+            .map(|t| t.team_id)
+    }
+
+    pub fn join(&mut self, team_id: u8, player_id: EntityId) -> Result<(), String> {
+        let Some(team) = self.teams.iter_mut().find(|t| t.team_id == team_id) else {
+            return Err(format!("there is no team {team_id}"));
+        };
+        if team.is_full() {
+            return Err(format!(
+                "team {team_id} is full ({} players)",
+                team.max_players
+            ));
+        }
+        team.player_ids.push(player_id);
+        Ok(())
+    }
+
+    pub fn leave(&mut self, player_id: EntityId) {
+        for team in &mut self.teams {
+            team.player_ids.retain(|id| *id != player_id);
+        }
+    }
+
+    pub fn clear_players(&mut self) {
+        for team in &mut self.teams {
+            team.player_ids.clear();
+        }
+    }
+
+    ///The next move that evens the teams out: the last to join the biggest team goes to
+    ///the smallest one with room. None once no two teams are more than one apart.
+    pub fn unbalanced_move(&self) -> Option<(EntityId, u8)> {
+        let biggest = self
+            .teams
+            .iter()
+            .max_by_key(|t| (t.player_ids.len(), std::cmp::Reverse(t.team_id)))?;
+        let smallest = self
+            .teams
+            .iter()
+            .filter(|t| !t.is_full())
+            .min_by_key(|t| (t.player_ids.len(), t.team_id))?;
+        if biggest.player_ids.len() <= smallest.player_ids.len() + 1 {
+            return None;
+        }
+        Some((*biggest.player_ids.last()?, smallest.team_id))
+    }
+
+    ///The player's team and their place in its formation.
+    pub fn slot_of(&self, player_id: EntityId) -> Option<(&Team, usize)> {
+        self.teams.iter().find_map(|team| {
+            let slot = team.player_ids.iter().position(|id| *id == player_id)?;
+            Some((team, slot))
+        })
+    }
+
+    pub fn set_formation(
+        &mut self,
+        team_id: u8,
+        spawn_pattern: SpawnPattern,
+        max_players: usize,
+    ) -> Result<(), String> {
+        let Some(team) = self.teams.iter_mut().find(|t| t.team_id == team_id) else {
+            return Err(format!("there is no team {team_id}"));
+        };
+        if !(1..=MAX_TEAM_SIZE).contains(&max_players) {
+            return Err(format!(
+                "teams hold 1 to {MAX_TEAM_SIZE} players, not {max_players}"
+            ));
+        }
+        if max_players < team.player_ids.len() {
+            return Err(format!(
+                "team {team_id} already has {} players",
+                team.player_ids.len()
+            ));
+        }
+        team.spawn_pattern = spawn_pattern;
+        team.max_players = max_players;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balancing_moves_the_latest_joiners_to_the_smallest_team() {
+        let mut teams = TeamManager::new([0, 1, 2]);
+        for id in 1..=4 {
+            teams.join(0, EntityId(id)).unwrap();
+        }
+
+        let mut moves = Vec::new();
+        while let Some((player_id, team_id)) = teams.unbalanced_move() {
+            teams.leave(player_id);
+            teams.join(team_id, player_id).unwrap();
+            moves.push((player_id.0, team_id));
+        }
+
+        assert_eq!(moves, [(4, 1), (3, 2)]);
+        assert_eq!(teams.get(0).unwrap().player_ids.len(), 2);
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::HashMap;
 
 use crate::game::{
+    broadphase::{SpatialGrid, DEFAULT_CELL_SIZE},
+    collision::Collision,
+    input::inputframe::Vec2,
     state::{
-        entityid::EntityId,
+        entityid::{EntityId, EntityIdAllocator},
         lobbyplayer::{LobbyPlayer, LobbyState},
         State,
     },
@@ -12,6 +15,8 @@ use crate::game::{
 pub struct World {
     pub entities: Vec<State>,
     entity_map: HashMap<EntityId, usize>,
+    entity_ids: EntityIdAllocator,
+    broad_phase: SpatialGrid,
 
     lobby_state: LobbyState,
 }
@@ -21,6 +26,8 @@ impl World {
         return World {
             entities: Vec::new(),
             entity_map: HashMap::new(),
+            entity_ids: EntityIdAllocator::new(),
+            broad_phase: SpatialGrid::new(DEFAULT_CELL_SIZE),
             lobby_state: LobbyState {
                 expected_players: 0,
                 players: Vec::new(),
@@ -28,7 +35,20 @@ impl World {
         };
     }
 
-    pub fn add_entity(&mut self, state: State) {
+    ///Gives the state the next id from this world and adds it.
+    pub fn add_entity(&mut self, mut state: State) -> EntityId {
+        let id = self.entity_ids.allocate();
+
+        state.entity_id = id;
+        if let Some(pc) = &mut state.player_controller {
+            pc.player_id = id;
+        }
+        self.insert_entity(state);
+        id
+    }
+
+    ///Adds a state that already has an id, e.g. one mirrored from the host.
+    pub fn insert_entity(&mut self, state: State) {
         self.entity_map.entry(state.entity_id).or_insert_with(|| {
             let idx = self.entities.len();
             self.entities.push(state);
@@ -36,12 +56,63 @@ impl World {
         });
     }
 
-    pub fn add_player(&mut self, state: State) {
+    pub fn add_player(&mut self, state: State) -> EntityId {
+        let team_id = state.team_id;
+        let player_id = self.add_entity(state);
         self.lobby_state.players.push(LobbyPlayer {
-            player_id: state.entity_id,
-            team_id: state.team_id,
+            player_id,
+            team_id,
+            name: format!("Player {}", player_id.0),
+            client_id: None,
+            ready: false,
+            bot: None,
+            disconnected: false,
+        });
+        player_id
+    }
+
+    pub fn lobby_player_mut(&mut self, player_id: EntityId) -> Option<&mut LobbyPlayer> {
+        self.lobby_state
+            .players
+            .iter_mut()
+            .find(|player| player.player_id == player_id)
+    }
+
+    ///Swaps in a saved world wholesale, ids included.
+    pub fn restore(
+        &mut self,
+        entities: Vec<State>,
+        lobby_state: LobbyState,
+        entity_ids: EntityIdAllocator,
+    ) {
+        self.entities.clear();
+        self.entity_map.clear();
+        for state in entities {
+            self.insert_entity(state);
+        }
+        self.lobby_state = lobby_state;
+        self.entity_ids = entity_ids;
+    }
+
+    ///Rebuilds the grid with every entity covering the whole way from where it
+    ///is to where it's headed this tick.
+    pub fn rebuild_broad_phase(&mut self, positions: &[(f32, f32)]) {
+        let bounds = self.entities.iter().zip(positions).map(|(s, &(x, y))| {
+            let ps = &s.physics_state;
+            let (ax1, ay1, ax2, ay2) = Collision::bounds(&ps.shape, ps.pos, ps.angle);
+            let (bx1, by1, bx2, by2) = Collision::bounds(&ps.shape, Vec2 { x, y }, ps.angle);
+            (ax1.min(bx1), ay1.min(by1), ax2.max(bx2), ay2.max(by2))
         });
-        self.add_entity(state);
+        self.broad_phase.rebuild(bounds);
+    }
+
+    ///Index pairs whose bounds overlapped at the last rebuild, each once.
+    pub fn candidate_pairs(&self) -> Vec<(usize, usize)> {
+        self.broad_phase.pairs().to_vec()
+    }
+
+    pub fn entity_ids(&self) -> &EntityIdAllocator {
+        &self.entity_ids
     }
 
     pub fn remove_entity(&mut self, id: EntityId) {
@@ -50,6 +121,7 @@ impl World {
             self.entities.swap_remove(index);
 
             self.entity_map.remove(&id);
+            self.entity_ids.release(id);
 
             if index != last {
                 let moved_id = self.entities[index].entity_id;
@@ -74,11 +146,16 @@ impl World {
         self.entities.clear();
         self.entity_map.clear();
         self.lobby_state.players.clear();
+        // Ids from before keep going stale instead of starting over and aliasing new ones
+        self.entity_ids.release_all();
         println!("Removed all entities");
     }
 
     pub fn remove_all_non_players(&mut self) {
-        for id in self.entity_map.keys().cloned().collect::<Vec<_>>() {
+        // Sorted so freed slots get reused in the same order every run
+        let mut ids: Vec<EntityId> = self.entity_map.keys().cloned().collect();
+        ids.sort_unstable_by_key(|id| id.0);
+        for id in ids {
             if !self
                 .lobby_state
                 .players
@@ -95,6 +172,10 @@ impl World {
     }
 
     //Entity Accessors
+    pub fn index_of(&self, id: EntityId) -> Option<usize> {
+        self.entity_map.get(&id).copied()
+    }
+
     pub fn grab_entity(&mut self, id: EntityId) -> Option<&mut State> {
         let index = self.entity_map.get(&id).copied()?; // Option<usize>
         Some(&mut self.entities[index])
@@ -114,6 +195,10 @@ impl World {
     pub fn get_lobby_state(&self) -> &LobbyState {
         &self.lobby_state
     }
+
+    pub fn lobby_state_mut(&mut self) -> &mut LobbyState {
+        &mut self.lobby_state
+    }
     //Player Counts
     pub fn curr_player_count(&self) -> usize {
         return self.lobby_state.players.len();
@@ -1,14 +1,21 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
 use tokio::{
     sync::mpsc::{UnboundedReceiver, UnboundedSender},
     time,
 };
 
+use crate::game::frontend::gamepayload::GamePayload;
 use crate::network::{
     clientid::ClientId,
     clientrequest::{ClientMessage, ClientRequest},
+    codec::WireMessage,
+    reliability::ReliabilityLayer,
     serverevent::ServerEvent,
+    snapshotdelta::SnapshotHistory,
     socketmanager::SocketData,
 };
 
@@ -21,6 +28,11 @@ pub struct ClientNetworkHandler {
     incoming_socket_data: UnboundedReceiver<SocketData>,
     outgoing_socket_data: UnboundedSender<SocketData>,
     host_addr: Option<SocketAddr>,
+    client_id: Option<ClientId>, // handed out by the host in the join handshake
+    reliability: ReliabilityLayer,
+
+    //Snapshots rebuilt from the host's deltas
+    snapshot_history: SnapshotHistory,
 }
 
 impl ClientNetworkHandler {
@@ -36,6 +48,9 @@ impl ClientNetworkHandler {
             incoming_socket_data,
             outgoing_socket_data,
             host_addr: None,
+            client_id: None,
+            reliability: ReliabilityLayer::new(),
+            snapshot_history: SnapshotHistory::new(),
         }
     }
 
@@ -45,6 +60,7 @@ impl ClientNetworkHandler {
 
     pub async fn start_listening(&mut self) {
         let mut heartbeat = time::interval(Duration::from_secs(5)); // ping every 5s
+        let mut resend = time::interval(Duration::from_millis(50));
         loop {
             tokio::select! {
                 Some(dta) = self.incoming_socket_data.recv() => {
@@ -56,22 +72,38 @@ impl ClientNetworkHandler {
                 _ = heartbeat.tick() => {
                     self.send_request(ClientRequest::Idle).await;
                 }
+                _ = resend.tick() => {
+                    for data in self.reliability.resend_due(Instant::now()) {
+                        let _ = self.outgoing_socket_data.send(data);
+                    }
+                }
                 else => break, // all channels closed, shutdown
             }
         }
     }
 
     async fn handle_socket_data(&mut self, data: SocketData) {
-        let (peer_addr, bytes) = data;
+        let (peer_addr, frame) = data;
+
+        let Some(incoming) = self.reliability.receive(peer_addr, &frame) else {
+            eprintln!("Dropping unframed packet from {}", peer_addr);
+            return;
+        };
+        if let Some(ack) = incoming.ack {
+            let _ = self.outgoing_socket_data.send((peer_addr, ack));
+        }
 
+        for bytes in incoming.payloads {
+            self.handle_server_bytes(peer_addr, bytes).await;
+        }
+    }
+
+    async fn handle_server_bytes(&mut self, peer_addr: SocketAddr, bytes: Vec<u8>) {
         //deserialize
-        let msg: ServerEvent = match serde_json::from_slice(&bytes) {
+        let msg = match ServerEvent::from_bytes(&bytes) {
             Ok(m) => m,
             Err(e) => {
-                eprintln!(
-                    "Failed to deserialize client message from {}: {}",
-                    peer_addr, e
-                );
+                eprintln!("Failed to decode server event from {}: {}", peer_addr, e);
                 return;
             }
         };
@@ -84,22 +116,55 @@ impl ClientNetworkHandler {
 
         // --- Authority enforcement ---
         match self.host_addr {
-            Some(host) if host == peer_addr => {
-                let _ = self.outgoing_server_event.send(msg);
+            Some(host) if host == peer_addr => {}
+            _ => return, // Drop anything not from the host
+        }
+
+        match msg {
+            ServerEvent::WorldSnapshot { snapshot } => self.accept_snapshot(snapshot).await,
+            ServerEvent::WorldDelta { delta } => {
+                let Some(base) = self.snapshot_history.get(delta.base_tick) else {
+                    eprintln!("Dropping delta against unknown tick {}", delta.base_tick);
+                    return;
+                };
+                let snapshot = delta.apply(base);
+                self.accept_snapshot(snapshot).await;
             }
             _ => {
-                // Drop anything not from the host
+                let _ = self.outgoing_server_event.send(msg);
+            }
+        }
+    }
+
+    async fn accept_snapshot(&mut self, snapshot: GamePayload) {
+        // Late packets are useless once something newer has been shown
+        if let Some(latest) = self.snapshot_history.latest_tick() {
+            if snapshot.tick <= latest {
+                return;
             }
         }
+
+        let tick = snapshot.tick;
+        self.snapshot_history.push(snapshot.clone());
+        self.send_request(ClientRequest::Ack { tick }).await;
+
+        let _ = self
+            .outgoing_server_event
+            .send(ServerEvent::WorldSnapshot { snapshot });
     }
 
     async fn handle_joined(&mut self, peer_addr: SocketAddr, msg: ServerEvent) {
         println!("Recieving Join data");
         match msg {
-            ServerEvent::Joined { client_id } => {
+            ServerEvent::Joined {
+                client_id,
+                tick_rate,
+            } => {
                 // First contact: establish host
                 if self.host_addr.is_none() {
                     self.host_addr = Some(peer_addr);
+                    // The host keeps our sequences through a dropout, so we keep its
+                    self.reliability.hold_peer(peer_addr);
                     self.send_request(ClientRequest::Joined).await;
 
                     return;
@@ -108,8 +173,10 @@ impl ClientNetworkHandler {
                 // Second contact: finalize identity
                 if let Some(id) = client_id {
                     println!("Sending data 2");
+                    self.client_id = Some(id);
                     let _ = self.outgoing_server_event.send(ServerEvent::Joined {
                         client_id: Some(id),
+                        tick_rate,
                     });
                 }
             }
@@ -117,35 +184,32 @@ impl ClientNetworkHandler {
         }
     }
 
-    async fn send_request(&self, request: ClientRequest) {
-        let msg = ClientMessage::new(ClientId::new(), request);
+    async fn send_request(&mut self, request: ClientRequest) {
+        let msg = ClientMessage {
+            client_id: self.client_id,
+            request,
+        };
         self.handle_client_request(msg).await;
     }
 
     //just from client
-    async fn handle_client_request(&self, msg: ClientMessage) {
-        if self.host_addr.is_none() {
+    async fn handle_client_request(&mut self, msg: ClientMessage) {
+        let Some(host_addr) = self.host_addr else {
             eprintln!("Host address does not exist");
             return;
-        }
-        match serde_json::to_vec(&msg) {
-            Ok(bytes) => {
-                match self
-                    .outgoing_socket_data
-                    .send((self.host_addr.unwrap(), bytes))
-                {
-                    Ok(_) => {
-                        println!("Enqueued outgoing data for sending");
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to enqueue outgoing data: {e}");
-                    }
-                }
-
-                println!("Sending data 1");
+        };
+        let frame = self.reliability.wrap(
+            host_addr,
+            msg.request.delivery(),
+            &msg.to_bytes(),
+            Instant::now(),
+        );
+        match self.outgoing_socket_data.send((host_addr, frame)) {
+            Ok(_) => {
+                println!("Enqueued outgoing data for sending");
             }
             Err(e) => {
-                eprintln!("Failed to serialize ClientMessage: {}", e);
+                eprintln!("Failed to enqueue outgoing data: {e}");
             }
         }
     }
@@ -1,9 +1,12 @@
 use crate::{
     game::{input::InputFrame, state::entityid::EntityId},
-    network::clientid::ClientId,
+    network::{
+        clientid::ClientId,
+        reliability::{Delivery, ReliableChannel},
+    },
 };
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum ClientRequest {
     Add {
@@ -17,20 +20,53 @@ pub enum ClientRequest {
     Input {
         entity_id: EntityId,
         frame: InputFrame,
+        /// Frames sent just before this one, oldest first, in case those packets got lost
+        #[serde(default)]
+        recent: Vec<InputFrame>,
+    },
+    Ack {
+        tick: u64,
+    },
+    /// Lobby only, like SetReady and SwitchTeam
+    SetName {
+        entity_id: EntityId,
+        name: String,
+    },
+    SetReady {
+        entity_id: EntityId,
+        ready: bool,
+    },
+    SwitchTeam {
+        entity_id: EntityId,
+        team_id: u8,
     },
+    /// Only made by the host's network handler, when the TTLManager gives up on a client
+    Disconnected,
+    /// Only made by the host's network handler, when a dropped client shows up again
+    Reconnected,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+impl ClientRequest {
+    ///Inputs and acks are sent every tick, so a lost one is replaced by the next
+    pub fn delivery(&self) -> Delivery {
+        match self {
+            ClientRequest::Joined => Delivery::Reliable(ReliableChannel::Session),
+            ClientRequest::Add { .. }
+            | ClientRequest::Remove { .. }
+            | ClientRequest::SetName { .. }
+            | ClientRequest::SetReady { .. }
+            | ClientRequest::SwitchTeam { .. } => Delivery::Reliable(ReliableChannel::Players),
+            ClientRequest::Idle
+            | ClientRequest::Input { .. }
+            | ClientRequest::Ack { .. }
+            | ClientRequest::Disconnected
+            | ClientRequest::Reconnected => Delivery::Unreliable,
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct ClientMessage {
     pub client_id: Option<ClientId>,
     pub request: ClientRequest,
 }
-
-impl ClientMessage {
-    pub fn new(client_id: ClientId, request: ClientRequest) -> Self {
-        Self {
-            client_id: Some(client_id),
-            request,
-        }
-    }
-}
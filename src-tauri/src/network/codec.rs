@@ -0,0 +1,1301 @@
+//! Binary wire format for everything that goes over the socket.
+//!
+//! Every packet is `[version][message type][body]`. Numbers are little endian,
+//! lists and strings are prefixed with a u32 length, options with a 0/1 byte.
+//! The serde derives on the payload types stay for the frontend, they just
+//! aren't used on the wire.
+
+use std::fmt;
+
+use crate::{
+    game::{
+        bot::BotDifficulty,
+        frontend::{fxevent::FxEvent, gamepayload::GamePayload},
+        gamemanager::GamePhase,
+        input::inputframe::{Buttons, InputFrame, Vec2},
+        matchrules::MatchRules,
+        scoremanager::{Color, GoalRecord, PlayerStats, ScoreManager, Team},
+        state::{
+            entityid::EntityId,
+            enums::{ActionState, Kind, Shape},
+            lobbyplayer::{LobbyPlayer, LobbyState},
+            renderstate::RenderState,
+        },
+    },
+    network::{
+        clientid::ClientId,
+        clientrequest::{ClientMessage, ClientRequest},
+        serverevent::ServerEvent,
+        snapshotdelta::{RenderStateDelta, SnapshotDelta},
+    },
+};
+
+pub const PROTOCOL_VERSION: u8 = 12;
+
+//Message types, client -> host
+const MSG_ADD: u8 = 0x01;
+const MSG_JOINED: u8 = 0x02;
+const MSG_IDLE: u8 = 0x03;
+const MSG_REMOVE: u8 = 0x04;
+const MSG_INPUT: u8 = 0x05;
+const MSG_ACK: u8 = 0x06;
+const MSG_DISCONNECTED: u8 = 0x07;
+const MSG_RECONNECTED: u8 = 0x08;
+const MSG_SET_NAME: u8 = 0x09;
+const MSG_SET_READY: u8 = 0x0A;
+const MSG_SWITCH_TEAM: u8 = 0x0B;
+
+//Message types, host -> client
+const EVT_JOINED: u8 = 0x81;
+const EVT_WORLD_SNAPSHOT: u8 = 0x82;
+const EVT_ADDED_PLAYER: u8 = 0x83;
+const EVT_WORLD_DELTA: u8 = 0x84;
+const EVT_LOBBY_UPDATED: u8 = 0x85;
+const EVT_LOBBY_SETTINGS: u8 = 0x86;
+const EVT_KICKED: u8 = 0x87;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CodecError {
+    UnsupportedVersion(u8),
+    UnknownMessageType(u8),
+    UnexpectedEnd,
+    InvalidTag { what: &'static str, tag: u8 },
+    InvalidUtf8,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::UnsupportedVersion(v) => {
+                write!(
+                    f,
+                    "unsupported protocol version {v}, expected {PROTOCOL_VERSION}"
+                )
+            }
+            CodecError::UnknownMessageType(t) => write!(f, "unknown message type {t:#04x}"),
+            CodecError::UnexpectedEnd => write!(f, "packet ended early"),
+            CodecError::InvalidTag { what, tag } => write!(f, "invalid {what} tag {tag}"),
+            CodecError::InvalidUtf8 => write!(f, "string is not valid utf-8"),
+            CodecError::TrailingBytes(n) => write!(f, "{n} unread bytes at end of packet"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+///Top level packets, the only things that get framed with a header.
+pub trait WireMessage: Sized {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CodecError>;
+}
+
+impl WireMessage for ClientMessage {
+    fn to_bytes(&self) -> Vec<u8> {
+        let msg_type = match &self.request {
+            ClientRequest::Add { .. } => MSG_ADD,
+            ClientRequest::Joined => MSG_JOINED,
+            ClientRequest::Idle => MSG_IDLE,
+            ClientRequest::Remove { .. } => MSG_REMOVE,
+            ClientRequest::Input { .. } => MSG_INPUT,
+            ClientRequest::Ack { .. } => MSG_ACK,
+            ClientRequest::Disconnected => MSG_DISCONNECTED,
+            ClientRequest::Reconnected => MSG_RECONNECTED,
+            ClientRequest::SetName { .. } => MSG_SET_NAME,
+            ClientRequest::SetReady { .. } => MSG_SET_READY,
+            ClientRequest::SwitchTeam { .. } => MSG_SWITCH_TEAM,
+        };
+
+        let mut w = WireWriter::with_header(msg_type);
+        self.client_id.encode(&mut w);
+        match &self.request {
+            ClientRequest::Add { team_id } => team_id.encode(&mut w),
+            ClientRequest::Joined
+            | ClientRequest::Idle
+            | ClientRequest::Disconnected
+            | ClientRequest::Reconnected => {}
+            ClientRequest::Remove { id } => id.encode(&mut w),
+            ClientRequest::Input {
+                entity_id,
+                frame,
+                recent,
+            } => {
+                entity_id.encode(&mut w);
+                frame.encode(&mut w);
+                recent.encode(&mut w);
+            }
+            ClientRequest::Ack { tick } => tick.encode(&mut w),
+            ClientRequest::SetName { entity_id, name } => {
+                entity_id.encode(&mut w);
+                name.encode(&mut w);
+            }
+            ClientRequest::SetReady { entity_id, ready } => {
+                entity_id.encode(&mut w);
+                ready.encode(&mut w);
+            }
+            ClientRequest::SwitchTeam { entity_id, team_id } => {
+                entity_id.encode(&mut w);
+                team_id.encode(&mut w);
+            }
+        }
+        w.finish()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        let (msg_type, mut r) = WireReader::with_header(bytes)?;
+        let client_id = Option::<ClientId>::decode(&mut r)?;
+        let request = match msg_type {
+            MSG_ADD => ClientRequest::Add {
+                team_id: u8::decode(&mut r)?,
+            },
+            MSG_JOINED => ClientRequest::Joined,
+            MSG_IDLE => ClientRequest::Idle,
+            MSG_REMOVE => ClientRequest::Remove {
+                id: EntityId::decode(&mut r)?,
+            },
+            MSG_INPUT => ClientRequest::Input {
+                entity_id: EntityId::decode(&mut r)?,
+                frame: InputFrame::decode(&mut r)?,
+                recent: Vec::<InputFrame>::decode(&mut r)?,
+            },
+            MSG_ACK => ClientRequest::Ack {
+                tick: u64::decode(&mut r)?,
+            },
+            MSG_DISCONNECTED => ClientRequest::Disconnected,
+            MSG_RECONNECTED => ClientRequest::Reconnected,
+            MSG_SET_NAME => ClientRequest::SetName {
+                entity_id: EntityId::decode(&mut r)?,
+                name: String::decode(&mut r)?,
+            },
+            MSG_SET_READY => ClientRequest::SetReady {
+                entity_id: EntityId::decode(&mut r)?,
+                ready: bool::decode(&mut r)?,
+            },
+            MSG_SWITCH_TEAM => ClientRequest::SwitchTeam {
+                entity_id: EntityId::decode(&mut r)?,
+                team_id: u8::decode(&mut r)?,
+            },
+            other => return Err(CodecError::UnknownMessageType(other)),
+        };
+        r.finish()?;
+
+        Ok(ClientMessage { client_id, request })
+    }
+}
+
+impl WireMessage for ServerEvent {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut w;
+        match self {
+            ServerEvent::Joined {
+                client_id,
+                tick_rate,
+            } => {
+                w = WireWriter::with_header(EVT_JOINED);
+                client_id.encode(&mut w);
+                tick_rate.encode(&mut w);
+            }
+            ServerEvent::WorldSnapshot { snapshot } => {
+                w = WireWriter::with_header(EVT_WORLD_SNAPSHOT);
+                snapshot.encode(&mut w);
+            }
+            ServerEvent::WorldDelta { delta } => {
+                w = WireWriter::with_header(EVT_WORLD_DELTA);
+                delta.encode(&mut w);
+            }
+            ServerEvent::AddedPlayer { entity, client } => {
+                w = WireWriter::with_header(EVT_ADDED_PLAYER);
+                entity.encode(&mut w);
+                client.encode(&mut w);
+            }
+            ServerEvent::LobbyUpdated { lobby } => {
+                w = WireWriter::with_header(EVT_LOBBY_UPDATED);
+                lobby.encode(&mut w);
+            }
+            ServerEvent::LobbySettings {
+                player_count,
+                rules,
+            } => {
+                w = WireWriter::with_header(EVT_LOBBY_SETTINGS);
+                player_count.encode(&mut w);
+                rules.encode(&mut w);
+            }
+            ServerEvent::Kicked { entity, client } => {
+                w = WireWriter::with_header(EVT_KICKED);
+                entity.encode(&mut w);
+                client.encode(&mut w);
+            }
+        }
+        w.finish()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CodecError> {
+        let (msg_type, mut r) = WireReader::with_header(bytes)?;
+        let event = match msg_type {
+            EVT_JOINED => ServerEvent::Joined {
+                client_id: Option::<ClientId>::decode(&mut r)?,
+                tick_rate: f32::decode(&mut r)?,
+            },
+            EVT_WORLD_SNAPSHOT => ServerEvent::WorldSnapshot {
+                snapshot: GamePayload::decode(&mut r)?,
+            },
+            EVT_WORLD_DELTA => ServerEvent::WorldDelta {
+                delta: SnapshotDelta::decode(&mut r)?,
+            },
+            EVT_ADDED_PLAYER => ServerEvent::AddedPlayer {
+                entity: EntityId::decode(&mut r)?,
+                client: ClientId::decode(&mut r)?,
+            },
+            EVT_LOBBY_UPDATED => ServerEvent::LobbyUpdated {
+                lobby: LobbyState::decode(&mut r)?,
+            },
+            EVT_LOBBY_SETTINGS => ServerEvent::LobbySettings {
+                player_count: u8::decode(&mut r)?,
+                rules: MatchRules::decode(&mut r)?,
+            },
+            EVT_KICKED => ServerEvent::Kicked {
+                entity: EntityId::decode(&mut r)?,
+                client: ClientId::decode(&mut r)?,
+            },
+            other => return Err(CodecError::UnknownMessageType(other)),
+        };
+        r.finish()?;
+
+        Ok(event)
+    }
+}
+
+pub struct WireWriter {
+    buf: Vec<u8>,
+}
+
+impl WireWriter {
+    fn with_header(msg_type: u8) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.push(PROTOCOL_VERSION);
+        buf.push(msg_type);
+        Self { buf }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn put_len(&mut self, len: usize) {
+        self.put(&(len as u32).to_le_bytes());
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct WireReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WireReader<'a> {
+    fn with_header(bytes: &'a [u8]) -> Result<(u8, Self), CodecError> {
+        let mut r = Self { bytes, pos: 0 };
+        let version = u8::decode(&mut r)?;
+        if version != PROTOCOL_VERSION {
+            return Err(CodecError::UnsupportedVersion(version));
+        }
+        let msg_type = u8::decode(&mut r)?;
+        Ok((msg_type, r))
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], CodecError> {
+        let end = self.pos + N;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(CodecError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(slice.try_into().unwrap())
+    }
+
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], CodecError> {
+        let end = self.pos.checked_add(len).ok_or(CodecError::UnexpectedEnd)?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(CodecError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn take_len(&mut self) -> Result<usize, CodecError> {
+        Ok(u32::decode(self)? as usize)
+    }
+
+    fn tag(&mut self, what: &'static str, max: u8) -> Result<u8, CodecError> {
+        let tag = u8::decode(self)?;
+        if tag > max {
+            return Err(CodecError::InvalidTag { what, tag });
+        }
+        Ok(tag)
+    }
+
+    fn finish(self) -> Result<(), CodecError> {
+        match self.bytes.len() - self.pos {
+            0 => Ok(()),
+            n => Err(CodecError::TrailingBytes(n)),
+        }
+    }
+}
+
+///Body encoding for anything that can sit inside a packet.
+pub trait Wire: Sized {
+    fn encode(&self, w: &mut WireWriter);
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError>;
+}
+
+macro_rules! wire_number {
+    ($($t:ty),*) => {$(
+        impl Wire for $t {
+            fn encode(&self, w: &mut WireWriter) {
+                w.put(&self.to_le_bytes());
+            }
+            fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+                Ok(<$t>::from_le_bytes(r.take()?))
+            }
+        }
+    )*};
+}
+
+wire_number!(u8, u16, u32, u64, f32);
+
+impl Wire for bool {
+    fn encode(&self, w: &mut WireWriter) {
+        (*self as u8).encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(r.tag("bool", 1)? == 1)
+    }
+}
+
+impl Wire for String {
+    fn encode(&self, w: &mut WireWriter) {
+        w.put_len(self.len());
+        w.put(self.as_bytes());
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let len = r.take_len()?;
+        let bytes = r.take_slice(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| CodecError::InvalidUtf8)
+    }
+}
+
+impl<T: Wire> Wire for Option<T> {
+    fn encode(&self, w: &mut WireWriter) {
+        match self {
+            Some(v) => {
+                1u8.encode(w);
+                v.encode(w);
+            }
+            None => 0u8.encode(w),
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        match r.tag("option", 1)? {
+            1 => Ok(Some(T::decode(r)?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<T: Wire> Wire for Vec<T> {
+    fn encode(&self, w: &mut WireWriter) {
+        w.put_len(self.len());
+        for item in self {
+            item.encode(w);
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let len = r.take_len()?;
+        // Don't trust the length for the allocation, a bad packet could claim billions
+        let mut items = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            items.push(T::decode(r)?);
+        }
+        Ok(items)
+    }
+}
+
+//Ids
+
+impl Wire for ClientId {
+    fn encode(&self, w: &mut WireWriter) {
+        self.0.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(ClientId(u32::decode(r)?))
+    }
+}
+
+impl Wire for EntityId {
+    fn encode(&self, w: &mut WireWriter) {
+        self.0.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(EntityId(u32::decode(r)?))
+    }
+}
+
+//Input
+
+impl Wire for Vec2 {
+    fn encode(&self, w: &mut WireWriter) {
+        self.x.encode(w);
+        self.y.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(Vec2 {
+            x: f32::decode(r)?,
+            y: f32::decode(r)?,
+        })
+    }
+}
+
+impl Wire for Buttons {
+    fn encode(&self, w: &mut WireWriter) {
+        let bits = self.grab as u8 | (self.place as u8) << 1 | (self.dash as u8) << 2;
+        bits.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let bits = u8::decode(r)?;
+        Ok(Buttons {
+            grab: bits & 1 != 0,
+            place: bits & 2 != 0,
+            dash: bits & 4 != 0,
+        })
+    }
+}
+
+impl Wire for InputFrame {
+    fn encode(&self, w: &mut WireWriter) {
+        self.move_axis.encode(w);
+        self.look.encode(w);
+        self.buttons.encode(w);
+        self.sequence.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(InputFrame {
+            move_axis: Vec2::decode(r)?,
+            look: Vec2::decode(r)?,
+            buttons: Buttons::decode(r)?,
+            sequence: u32::decode(r)?,
+        })
+    }
+}
+
+//State enums
+
+impl Wire for Kind {
+    fn encode(&self, w: &mut WireWriter) {
+        (*self as u8).encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("kind", 4)? {
+            0 => Kind::Player,
+            1 => Kind::Brick,
+            2 => Kind::Wall,
+            3 => Kind::Ball,
+            _ => Kind::Goal,
+        })
+    }
+}
+
+impl Wire for BotDifficulty {
+    fn encode(&self, w: &mut WireWriter) {
+        (*self as u8).encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("bot difficulty", 2)? {
+            0 => BotDifficulty::Easy,
+            1 => BotDifficulty::Normal,
+            _ => BotDifficulty::Hard,
+        })
+    }
+}
+
+impl Wire for ActionState {
+    fn encode(&self, w: &mut WireWriter) {
+        (*self as u8).encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("action state", 2)? {
+            0 => ActionState::Idle,
+            1 => ActionState::Moving,
+            _ => ActionState::Dashing,
+        })
+    }
+}
+
+impl Wire for Shape {
+    fn encode(&self, w: &mut WireWriter) {
+        match self {
+            Shape::Circle { radius } => {
+                0u8.encode(w);
+                radius.encode(w);
+            }
+            Shape::Rectangle { w: width, h } => {
+                1u8.encode(w);
+                width.encode(w);
+                h.encode(w);
+            }
+            Shape::Capsule { radius, length } => {
+                2u8.encode(w);
+                radius.encode(w);
+                length.encode(w);
+            }
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("shape", 2)? {
+            0 => Shape::Circle {
+                radius: f32::decode(r)?,
+            },
+            1 => Shape::Rectangle {
+                w: f32::decode(r)?,
+                h: f32::decode(r)?,
+            },
+            _ => Shape::Capsule {
+                radius: f32::decode(r)?,
+                length: f32::decode(r)?,
+            },
+        })
+    }
+}
+
+impl Wire for GamePhase {
+    fn encode(&self, w: &mut WireWriter) {
+        match self {
+            GamePhase::Waiting => 0u8.encode(w),
+            GamePhase::Countdown { time_left } => {
+                1u8.encode(w);
+                time_left.encode(w);
+            }
+            GamePhase::Playing => 2u8.encode(w),
+            GamePhase::GameOver { winner, time_left } => {
+                3u8.encode(w);
+                winner.encode(w);
+                time_left.encode(w);
+            }
+            GamePhase::Celebrating {
+                conceded,
+                time_left,
+            } => {
+                4u8.encode(w);
+                conceded.encode(w);
+                time_left.encode(w);
+            }
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("game phase", 4)? {
+            0 => GamePhase::Waiting,
+            1 => GamePhase::Countdown {
+                time_left: f32::decode(r)?,
+            },
+            2 => GamePhase::Playing,
+            3 => GamePhase::GameOver {
+                winner: Option::<u8>::decode(r)?,
+                time_left: f32::decode(r)?,
+            },
+            _ => GamePhase::Celebrating {
+                conceded: u8::decode(r)?,
+                time_left: f32::decode(r)?,
+            },
+        })
+    }
+}
+
+//Payload
+
+impl Wire for RenderState {
+    fn encode(&self, w: &mut WireWriter) {
+        self.id.encode(w);
+        self.x.encode(w);
+        self.y.encode(w);
+        self.vx.encode(w);
+        self.vy.encode(w);
+        self.angle.encode(w);
+        self.shape.encode(w);
+        self.action_state.encode(w);
+        let flags = self.is_holding as u8 | (self.is_held as u8) << 1 | (self.is_static as u8) << 2;
+        flags.encode(w);
+        self.kind.encode(w);
+        self.player_id.encode(w);
+        self.team_id.encode(w);
+        self.input_sequence.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let id = u32::decode(r)?;
+        let x = f32::decode(r)?;
+        let y = f32::decode(r)?;
+        let vx = f32::decode(r)?;
+        let vy = f32::decode(r)?;
+        let angle = f32::decode(r)?;
+        let shape = Shape::decode(r)?;
+        let action_state = ActionState::decode(r)?;
+        let flags = u8::decode(r)?;
+
+        Ok(RenderState {
+            id,
+            x,
+            y,
+            vx,
+            vy,
+            angle,
+            shape,
+            action_state,
+            is_holding: flags & 1 != 0,
+            is_held: flags & 2 != 0,
+            is_static: flags & 4 != 0,
+            kind: Kind::decode(r)?,
+            player_id: EntityId::decode(r)?,
+            team_id: Option::<u8>::decode(r)?,
+            input_sequence: u32::decode(r)?,
+        })
+    }
+}
+
+impl Wire for Color {
+    fn encode(&self, w: &mut WireWriter) {
+        w.put(&[self.0, self.1, self.2, self.3]);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let [red, green, blue, alpha] = r.take()?;
+        Ok(Color(red, green, blue, alpha))
+    }
+}
+
+impl Wire for Team {
+    fn encode(&self, w: &mut WireWriter) {
+        self.id.encode(w);
+        self.name.encode(w);
+        self.color.encode(w);
+        self.score.encode(w);
+        self.rounds_won.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(Team {
+            id: u8::decode(r)?,
+            name: String::decode(r)?,
+            color: Color::decode(r)?,
+            score: u8::decode(r)?,
+            rounds_won: u8::decode(r)?,
+        })
+    }
+}
+
+impl Wire for MatchRules {
+    fn encode(&self, w: &mut WireWriter) {
+        self.score_limit.encode(w);
+        self.time_limit.encode(w);
+        self.sudden_death.encode(w);
+        self.best_of.encode(w);
+        self.game_over_delay.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(MatchRules {
+            score_limit: u8::decode(r)?,
+            time_limit: f32::decode(r)?,
+            sudden_death: bool::decode(r)?,
+            best_of: u8::decode(r)?,
+            game_over_delay: f32::decode(r)?,
+        })
+    }
+}
+
+impl Wire for PlayerStats {
+    fn encode(&self, w: &mut WireWriter) {
+        self.player_id.encode(w);
+        self.team_id.encode(w);
+        self.goals.encode(w);
+        self.assists.encode(w);
+        self.own_goals.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(PlayerStats {
+            player_id: EntityId::decode(r)?,
+            team_id: u8::decode(r)?,
+            goals: u8::decode(r)?,
+            assists: u8::decode(r)?,
+            own_goals: u8::decode(r)?,
+        })
+    }
+}
+
+impl Wire for GoalRecord {
+    fn encode(&self, w: &mut WireWriter) {
+        self.goal_team.encode(w);
+        self.scoring_teams.encode(w);
+        self.scorer.encode(w);
+        self.assist.encode(w);
+        self.own_goal.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(GoalRecord {
+            goal_team: u8::decode(r)?,
+            scoring_teams: Vec::<u8>::decode(r)?,
+            scorer: Option::<EntityId>::decode(r)?,
+            assist: Option::<EntityId>::decode(r)?,
+            own_goal: bool::decode(r)?,
+        })
+    }
+}
+
+// Who last touched the ball stays on the host
+impl Wire for ScoreManager {
+    fn encode(&self, w: &mut WireWriter) {
+        self.teams.encode(w);
+        self.can_score.encode(w);
+        self.rules.encode(w);
+        self.clock.encode(w);
+        self.overtime.encode(w);
+        self.round.encode(w);
+        self.players.encode(w);
+        self.goals.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let mut score_manager = ScoreManager::new(Vec::<Team>::decode(r)?);
+        score_manager.can_score = bool::decode(r)?;
+        score_manager.rules = MatchRules::decode(r)?;
+        score_manager.clock = Option::<f32>::decode(r)?;
+        score_manager.overtime = bool::decode(r)?;
+        score_manager.round = u8::decode(r)?;
+        score_manager.players = Vec::<PlayerStats>::decode(r)?;
+        score_manager.goals = Vec::<GoalRecord>::decode(r)?;
+        Ok(score_manager)
+    }
+}
+
+impl Wire for FxEvent {
+    fn encode(&self, w: &mut WireWriter) {
+        match self {
+            FxEvent::BallWallHit { pos, intensity } => {
+                0u8.encode(w);
+                pos.0.encode(w);
+                pos.1.encode(w);
+                intensity.encode(w);
+            }
+            FxEvent::GoalScored { goal } => {
+                1u8.encode(w);
+                goal.encode(w);
+            }
+            FxEvent::PlayerDashed {
+                player_id,
+                pos,
+                angle,
+            } => {
+                2u8.encode(w);
+                player_id.encode(w);
+                pos.0.encode(w);
+                pos.1.encode(w);
+                angle.encode(w);
+            }
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(match r.tag("fx event", 2)? {
+            0 => FxEvent::BallWallHit {
+                pos: (f32::decode(r)?, f32::decode(r)?),
+                intensity: f32::decode(r)?,
+            },
+            1 => FxEvent::GoalScored {
+                goal: GoalRecord::decode(r)?,
+            },
+            _ => FxEvent::PlayerDashed {
+                player_id: EntityId::decode(r)?,
+                pos: (f32::decode(r)?, f32::decode(r)?),
+                angle: f32::decode(r)?,
+            },
+        })
+    }
+}
+
+impl Wire for LobbyPlayer {
+    fn encode(&self, w: &mut WireWriter) {
+        self.player_id.encode(w);
+        self.team_id.encode(w);
+        self.name.encode(w);
+        self.client_id.encode(w);
+        self.ready.encode(w);
+        self.bot.encode(w);
+        self.disconnected.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(LobbyPlayer {
+            player_id: EntityId::decode(r)?,
+            team_id: Option::<u8>::decode(r)?,
+            name: String::decode(r)?,
+            client_id: Option::<ClientId>::decode(r)?,
+            ready: bool::decode(r)?,
+            bot: Option::<BotDifficulty>::decode(r)?,
+            disconnected: bool::decode(r)?,
+        })
+    }
+}
+
+impl Wire for LobbyState {
+    fn encode(&self, w: &mut WireWriter) {
+        (self.expected_players as u32).encode(w);
+        self.players.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(LobbyState {
+            expected_players: u32::decode(r)? as usize,
+            players: Vec::<LobbyPlayer>::decode(r)?,
+        })
+    }
+}
+
+impl Wire for GamePayload {
+    fn encode(&self, w: &mut WireWriter) {
+        self.tick.encode(w);
+        self.render_states.encode(w);
+        self.score_manager.encode(w);
+        self.game_phase.encode(w);
+        self.fx_events.encode(w);
+        self.lobby_state.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(GamePayload {
+            tick: u64::decode(r)?,
+            render_states: Vec::<RenderState>::decode(r)?,
+            score_manager: ScoreManager::decode(r)?,
+            game_phase: GamePhase::decode(r)?,
+            fx_events: Vec::<FxEvent>::decode(r)?,
+            lobby_state: LobbyState::decode(r)?,
+        })
+    }
+}
+
+//Deltas
+
+impl Wire for RenderStateDelta {
+    // A bitmask up front says which fields follow, in declaration order
+    fn encode(&self, w: &mut WireWriter) {
+        let mut mask = 0u16;
+        let present = [
+            self.x.is_some(),
+            self.y.is_some(),
+            self.vx.is_some(),
+            self.vy.is_some(),
+            self.angle.is_some(),
+            self.shape.is_some(),
+            self.action_state.is_some(),
+            self.is_holding.is_some(),
+            self.is_held.is_some(),
+            self.is_static.is_some(),
+            self.kind.is_some(),
+            self.player_id.is_some(),
+            self.team_id.is_some(),
+            self.input_sequence.is_some(),
+        ];
+        for (bit, is_set) in present.iter().enumerate() {
+            if *is_set {
+                mask |= 1 << bit;
+            }
+        }
+
+        self.id.encode(w);
+        mask.encode(w);
+        let floats = [self.x, self.y, self.vx, self.vy, self.angle];
+        for value in floats.iter().flatten() {
+            value.encode(w);
+        }
+        if let Some(shape) = &self.shape {
+            shape.encode(w);
+        }
+        if let Some(action_state) = &self.action_state {
+            action_state.encode(w);
+        }
+        let flags = [self.is_holding, self.is_held, self.is_static];
+        for value in flags.iter().flatten() {
+            value.encode(w);
+        }
+        if let Some(kind) = &self.kind {
+            kind.encode(w);
+        }
+        if let Some(player_id) = &self.player_id {
+            player_id.encode(w);
+        }
+        if let Some(team_id) = &self.team_id {
+            team_id.encode(w);
+        }
+        if let Some(input_sequence) = &self.input_sequence {
+            input_sequence.encode(w);
+        }
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        let id = u32::decode(r)?;
+        let mask = u16::decode(r)?;
+        let has = |bit: u16| mask & (1 << bit) != 0;
+
+        fn field<T: Wire>(r: &mut WireReader, present: bool) -> Result<Option<T>, CodecError> {
+            if present {
+                Ok(Some(T::decode(r)?))
+            } else {
+                Ok(None)
+            }
+        }
+
+        Ok(RenderStateDelta {
+            id,
+            x: field(r, has(0))?,
+            y: field(r, has(1))?,
+            vx: field(r, has(2))?,
+            vy: field(r, has(3))?,
+            angle: field(r, has(4))?,
+            shape: field(r, has(5))?,
+            action_state: field(r, has(6))?,
+            is_holding: field(r, has(7))?,
+            is_held: field(r, has(8))?,
+            is_static: field(r, has(9))?,
+            kind: field(r, has(10))?,
+            player_id: field(r, has(11))?,
+            team_id: field(r, has(12))?,
+            input_sequence: field(r, has(13))?,
+        })
+    }
+}
+
+impl Wire for SnapshotDelta {
+    fn encode(&self, w: &mut WireWriter) {
+        self.base_tick.encode(w);
+        self.tick.encode(w);
+        self.removed.encode(w);
+        self.added.encode(w);
+        self.changed.encode(w);
+        self.score_manager.encode(w);
+        self.game_phase.encode(w);
+        self.lobby_state.encode(w);
+        self.fx_events.encode(w);
+    }
+    fn decode(r: &mut WireReader) -> Result<Self, CodecError> {
+        Ok(SnapshotDelta {
+            base_tick: u64::decode(r)?,
+            tick: u64::decode(r)?,
+            removed: Vec::<u32>::decode(r)?,
+            added: Vec::<RenderState>::decode(r)?,
+            changed: Vec::<RenderStateDelta>::decode(r)?,
+            score_manager: Option::<ScoreManager>::decode(r)?,
+            game_phase: Option::<GamePhase>::decode(r)?,
+            lobby_state: Option::<LobbyState>::decode(r)?,
+            fx_events: Vec::<FxEvent>::decode(r)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{state::State, world::World};
+
+    fn round_trip_client(msg: ClientMessage) {
+        let bytes = msg.to_bytes();
+        assert_eq!(bytes[0], PROTOCOL_VERSION);
+
+        let decoded = ClientMessage::from_bytes(&bytes).expect("decode failed");
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", msg));
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    fn round_trip_server(evt: ServerEvent) {
+        let bytes = evt.to_bytes();
+        assert_eq!(bytes[0], PROTOCOL_VERSION);
+
+        let decoded = ServerEvent::from_bytes(&bytes).expect("decode failed");
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", evt));
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    fn sample_payload() -> GamePayload {
+        let states = vec![
+            State::new_player(0, 50.0, 50.0, 0.0),
+            State::new_player(1, 270.0, 130.0, 3.0),
+            State::new_ball(160.0, 90.0),
+            State::new_wall(0.0, -10.0, 320.0, 10.0),
+            State::new_goal(0.0, 60.0, 30.0, 60.0, 0),
+            State::new_brick(100.0, 100.0, 8.0, EntityId(1)),
+        ];
+        let mut diagonal = State::new_wall(40.0, 40.0, 0.0, 0.0);
+        diagonal.physics_state.shape = Shape::Capsule {
+            radius: 4.0,
+            length: 60.0,
+        };
+        diagonal.physics_state.angle = 0.75;
+        let states = states.into_iter().chain([diagonal]);
+        let mut world = World::new();
+        for state in states {
+            world.add_entity(state);
+        }
+        let states = world.entities;
+        let mut score_manager = ScoreManager::new(vec![
+            Team {
+                id: 0,
+                name: "Blue".into(),
+                color: Color(0, 0, 255, 255),
+                score: 2,
+                rounds_won: 1,
+            },
+            Team {
+                id: 1,
+                name: "Red".into(),
+                color: Color(255, 0, 0, 255),
+                score: 1,
+                rounds_won: 0,
+            },
+            Team {
+                id: 2,
+                name: "Green".into(),
+                color: Color(0, 200, 0, 255),
+                score: 0,
+                rounds_won: 0,
+            },
+        ]);
+        score_manager.set_rules(MatchRules {
+            time_limit: 90.0,
+            best_of: 3,
+            ..MatchRules::default()
+        });
+        score_manager.start_match();
+        score_manager.touch(states[1].entity_id, 0);
+        score_manager.touch(states[0].entity_id, 0);
+        let goal = score_manager.concede(1);
+        let lobby_state = LobbyState {
+            expected_players: 2,
+            players: vec![
+                LobbyPlayer {
+                    player_id: states[0].entity_id,
+                    team_id: Some(0),
+                    name: "Ünïcode ok".into(),
+                    client_id: Some(ClientId(3)),
+                    ready: true,
+                    bot: None,
+                    disconnected: true,
+                },
+                LobbyPlayer {
+                    player_id: states[1].entity_id,
+                    team_id: None,
+                    name: "Hard Bot 2".into(),
+                    client_id: None,
+                    ready: false,
+                    bot: Some(BotDifficulty::Hard),
+                    disconnected: false,
+                },
+            ],
+        };
+
+        GamePayload::new(
+            42,
+            &states,
+            &score_manager,
+            &GamePhase::Countdown { time_left: 1.5 },
+            vec![
+                FxEvent::BallWallHit {
+                    pos: (10.0, 20.0),
+                    intensity: 0.75,
+                },
+                FxEvent::GoalScored { goal },
+                FxEvent::PlayerDashed {
+                    player_id: EntityId(4),
+                    pos: (12.0, -3.5),
+                    angle: 1.25,
+                },
+            ],
+            &lobby_state,
+        )
+    }
+
+    #[test]
+    fn client_requests_round_trip() {
+        let frame = InputFrame {
+            move_axis: Vec2 { x: 0.5, y: -1.0 },
+            look: Vec2 { x: 1.0, y: 0.0 },
+            buttons: Buttons {
+                grab: true,
+                place: false,
+                dash: true,
+            },
+            sequence: 17,
+        };
+
+        let requests = vec![
+            ClientRequest::Add { team_id: 1 },
+            ClientRequest::Joined,
+            ClientRequest::Idle,
+            ClientRequest::Remove { id: EntityId(7) },
+            ClientRequest::Input {
+                entity_id: EntityId(3),
+                frame: frame.clone(),
+                recent: Vec::new(),
+            },
+            ClientRequest::Input {
+                entity_id: EntityId(3),
+                frame: InputFrame {
+                    sequence: 18,
+                    ..frame.clone()
+                },
+                recent: vec![frame],
+            },
+            ClientRequest::Ack { tick: 1234 },
+            ClientRequest::Disconnected,
+            ClientRequest::Reconnected,
+            ClientRequest::SetName {
+                entity_id: EntityId(3),
+                name: "Wren".into(),
+            },
+            ClientRequest::SetReady {
+                entity_id: EntityId(3),
+                ready: true,
+            },
+            ClientRequest::SwitchTeam {
+                entity_id: EntityId(3),
+                team_id: 2,
+            },
+        ];
+
+        for request in requests {
+            round_trip_client(ClientMessage {
+                client_id: Some(ClientId(9)),
+                request,
+            });
+        }
+        round_trip_client(ClientMessage {
+            client_id: None,
+            request: ClientRequest::Joined,
+        });
+    }
+
+    #[test]
+    fn server_events_round_trip() {
+        round_trip_server(ServerEvent::Joined {
+            client_id: None,
+            tick_rate: 60.0,
+        });
+        round_trip_server(ServerEvent::Joined {
+            client_id: Some(ClientId(4)),
+            tick_rate: 30.0,
+        });
+        round_trip_server(ServerEvent::AddedPlayer {
+            entity: EntityId(12),
+            client: ClientId(4),
+        });
+        round_trip_server(ServerEvent::WorldSnapshot {
+            snapshot: sample_payload(),
+        });
+        round_trip_server(ServerEvent::LobbyUpdated {
+            lobby: sample_payload().lobby_state,
+        });
+        round_trip_server(ServerEvent::LobbySettings {
+            player_count: 4,
+            rules: MatchRules {
+                time_limit: 120.0,
+                best_of: 3,
+                ..MatchRules::default()
+            },
+        });
+        round_trip_server(ServerEvent::Kicked {
+            entity: EntityId(12),
+            client: ClientId(4),
+        });
+
+        let base = sample_payload();
+        let mut next = base.clone();
+        next.tick = 50;
+        next.render_states[0].x += 4.0;
+        next.render_states[0].team_id = None;
+        next.render_states[2].is_held = true;
+        next.render_states[1].input_sequence = 9;
+        next.render_states.remove(5);
+        next.game_phase = GamePhase::Playing;
+        round_trip_server(ServerEvent::WorldDelta {
+            delta: SnapshotDelta::between(&base, &next),
+        });
+    }
+
+    #[test]
+    fn every_game_phase_round_trips() {
+        for phase in [
+            GamePhase::Waiting,
+            GamePhase::Countdown { time_left: 3.0 },
+            GamePhase::Playing,
+            GamePhase::GameOver {
+                winner: Some(1),
+                time_left: 4.5,
+            },
+            GamePhase::GameOver {
+                winner: None,
+                time_left: 0.0,
+            },
+            GamePhase::Celebrating {
+                conceded: 2,
+                time_left: 1.25,
+            },
+        ] {
+            let mut payload = sample_payload();
+            payload.game_phase = phase.clone();
+            let bytes = ServerEvent::WorldSnapshot { snapshot: payload }.to_bytes();
+            match ServerEvent::from_bytes(&bytes).unwrap() {
+                ServerEvent::WorldSnapshot { snapshot } => assert_eq!(snapshot.game_phase, phase),
+                _ => panic!("wrong event type"),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_packets() {
+        let bytes = ServerEvent::Joined {
+            client_id: None,
+            tick_rate: 60.0,
+        }
+        .to_bytes();
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            ServerEvent::from_bytes(&wrong_version).unwrap_err(),
+            CodecError::UnsupportedVersion(PROTOCOL_VERSION + 1)
+        );
+
+        // A server event is not a client message
+        assert_eq!(
+            ClientMessage::from_bytes(&bytes).unwrap_err(),
+            CodecError::UnknownMessageType(EVT_JOINED)
+        );
+
+        // The bare join packet from SocketManager::join
+        assert!(ClientMessage::from_bytes(b"JOIN").is_err());
+
+        let snapshot = ServerEvent::WorldSnapshot {
+            snapshot: sample_payload(),
+        }
+        .to_bytes();
+        assert_eq!(
+            ServerEvent::from_bytes(&snapshot[..snapshot.len() - 1]).unwrap_err(),
+            CodecError::UnexpectedEnd
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            ServerEvent::from_bytes(&trailing).unwrap_err(),
+            CodecError::TrailingBytes(1)
+        );
+    }
+
+    #[test]
+    fn snapshot_is_smaller_than_json() {
+        let evt = ServerEvent::WorldSnapshot {
+            snapshot: sample_payload(),
+        };
+        let json = serde_json::to_vec(&evt).unwrap();
+        assert!(evt.to_bytes().len() * 3 < json.len());
+    }
+}
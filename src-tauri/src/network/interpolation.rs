@@ -0,0 +1,265 @@
+//! Smooths snapshots out on joining clients.
+//!
+//! Snapshots are stamped with the host tick, so each one has a host time. The
+//! buffer tracks the offset between that and the local clock, and on every
+//! local frame renders the world as it was `INTERPOLATION_DELAY` ago by
+//! blending the two snapshots around that moment. When nothing new has arrived
+//! it carries entities forward on their velocity for a little while.
+//!
+//! Times are kept as f64 seconds, a dedicated server can be up for days and an
+//! f32 runs out of sub-tick precision after a few hours.
+
+use std::{collections::VecDeque, time::Instant};
+
+use crate::game::{
+    frontend::{fxevent::FxEvent, gamepayload::GamePayload},
+    physics::Physics,
+    state::renderstate::RenderState,
+};
+
+/// How far behind the newest snapshot we render, in seconds
+pub const INTERPOLATION_DELAY: f64 = 0.1;
+/// Longest we keep moving entities on their velocity without a new snapshot
+pub const MAX_EXTRAPOLATION: f32 = 0.25;
+/// Offset jumps bigger than this are taken as-is instead of being smoothed in
+const CLOCK_SNAP: f64 = 0.25;
+const CLOCK_SMOOTHING: f64 = 0.05;
+const BUFFER_SIZE: usize = 32;
+
+struct Buffered {
+    time: f64, // host seconds
+    payload: GamePayload,
+}
+
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<Buffered>,
+    tick_rate: f32,
+    clock_start: Instant,
+    clock_offset: Option<f64>, // host time - local time
+    pending_fx: Vec<FxEvent>,
+}
+
+impl SnapshotBuffer {
+    pub fn new(tick_rate: f32, now: Instant) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(BUFFER_SIZE),
+            tick_rate,
+            clock_start: now,
+            clock_offset: None,
+            pending_fx: Vec::new(),
+        }
+    }
+
+    ///The host's tick rate, which turns snapshot ticks into host time.
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        if tick_rate != self.tick_rate {
+            self.tick_rate = tick_rate;
+            // Times from the old rate don't line up with the new ones
+            self.snapshots.clear();
+            self.clock_offset = None;
+        }
+    }
+
+    pub fn push(&mut self, mut snapshot: GamePayload, now: Instant) {
+        let time = snapshot.tick as f64 / self.tick_rate as f64;
+        if let Some(newest) = self.snapshots.back() {
+            if time <= newest.time {
+                return;
+            }
+        }
+
+        let sample = time - self.local_time(now);
+        self.clock_offset = Some(match self.clock_offset {
+            Some(offset) if (sample - offset).abs() < CLOCK_SNAP => {
+                offset + (sample - offset) * CLOCK_SMOOTHING
+            }
+            _ => sample,
+        });
+
+        // Fx only need to play once, they go out with the next emitted frame
+        self.pending_fx.append(&mut snapshot.fx_events);
+
+        if self.snapshots.len() == BUFFER_SIZE {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Buffered {
+            time,
+            payload: snapshot,
+        });
+    }
+
+    ///The world as of INTERPOLATION_DELAY ago on the host clock.
+    pub fn sample(&mut self, now: Instant) -> Option<GamePayload> {
+        let offset = self.clock_offset?;
+        let render_time = self.local_time(now) + offset - INTERPOLATION_DELAY;
+
+        // Drop snapshots we'll never blend from again, keeping one before render_time
+        while self.snapshots.len() > 2 && self.snapshots[1].time <= render_time {
+            self.snapshots.pop_front();
+        }
+
+        let from = self.snapshots.front()?;
+        let mut payload = match self.snapshots.get(1) {
+            Some(to) if render_time > from.time => {
+                let t = ((render_time - from.time) / (to.time - from.time)).min(1.0);
+                if render_time <= to.time {
+                    Self::interpolate(&from.payload, &to.payload, t as f32)
+                } else {
+                    Self::extrapolate(&to.payload, (render_time - to.time) as f32)
+                }
+            }
+            None if render_time > from.time => {
+                Self::extrapolate(&from.payload, (render_time - from.time) as f32)
+            }
+            _ => from.payload.clone(),
+        };
+
+        payload.fx_events = std::mem::take(&mut self.pending_fx);
+        Some(payload)
+    }
+
+    //Helpers
+
+    fn local_time(&self, now: Instant) -> f64 {
+        now.duration_since(self.clock_start).as_secs_f64()
+    }
+
+    ///Entities follow `from`, positions blend toward `to` where it has them.
+    fn interpolate(from: &GamePayload, to: &GamePayload, t: f32) -> GamePayload {
+        let mut payload = from.clone();
+        payload.fx_events.clear();
+
+        for state in payload.render_states.iter_mut() {
+            if let Some(next) = to.render_states.iter().find(|s| s.id == state.id) {
+                Self::blend(state, next, t);
+            }
+        }
+        payload
+    }
+
+    fn extrapolate(from: &GamePayload, ahead: f32) -> GamePayload {
+        let ahead = ahead.min(MAX_EXTRAPOLATION);
+        let mut payload = from.clone();
+        payload.fx_events.clear();
+
+        for state in payload.render_states.iter_mut() {
+            state.x += state.vx * ahead;
+            state.y += state.vy * ahead;
+        }
+        payload
+    }
+
+    fn blend(state: &mut RenderState, next: &RenderState, t: f32) {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        state.x = lerp(state.x, next.x);
+        state.y = lerp(state.y, next.y);
+        state.vx = lerp(state.vx, next.vx);
+        state.vy = lerp(state.vy, next.vy);
+        // Turn the short way round
+        state.angle += Physics::normalize_angle(next.angle - state.angle) * t;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::game::{
+        gamemanager::GamePhase,
+        scoremanager::ScoreManager,
+        state::{lobbyplayer::LobbyState, State},
+    };
+
+    ///A ball at `x` moving at `vx`.
+    fn snapshot(tick: u64, x: f32, vx: f32) -> GamePayload {
+        let mut ball = RenderState::from(&State::new_ball(x, 0.0));
+        ball.vx = vx;
+        GamePayload {
+            tick,
+            render_states: vec![ball],
+            score_manager: ScoreManager::default(),
+            game_phase: GamePhase::Playing,
+            fx_events: Vec::new(),
+            lobby_state: LobbyState {
+                expected_players: 0,
+                players: Vec::new(),
+            },
+        }
+    }
+
+    fn at(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    fn ball_x(buffer: &mut SnapshotBuffer, now: Instant) -> f32 {
+        buffer.sample(now).unwrap().render_states[0].x
+    }
+
+    #[test]
+    fn blends_between_the_snapshots_around_render_time() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::new(60.0, start);
+        buffer.push(snapshot(0, 0.0, 0.0), start);
+        buffer.push(snapshot(6, 60.0, 0.0), at(start, 100));
+
+        // Rendering 100ms behind, halfway between the two
+        let x = ball_x(&mut buffer, at(start, 150));
+        assert!((x - 30.0).abs() < 0.5, "x = {x}");
+    }
+
+    #[test]
+    fn extrapolation_stops_at_its_cap() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::new(60.0, start);
+        buffer.push(snapshot(0, 0.0, 100.0), start);
+
+        let x = ball_x(&mut buffer, at(start, 2000));
+        assert!((x - 100.0 * MAX_EXTRAPOLATION).abs() < 0.01, "x = {x}");
+    }
+
+    #[test]
+    fn late_and_out_of_order_snapshots_are_dropped() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::new(60.0, start);
+        buffer.push(snapshot(0, 0.0, 0.0), start);
+        buffer.push(snapshot(12, 120.0, 0.0), at(start, 200));
+        buffer.push(snapshot(6, 1000.0, 0.0), at(start, 210));
+        buffer.push(snapshot(12, 1000.0, 0.0), at(start, 220));
+
+        let x = ball_x(&mut buffer, at(start, 200));
+        assert!((x - 60.0).abs() < 0.5, "x = {x}");
+    }
+
+    #[test]
+    fn ticks_are_timed_at_the_hosts_rate() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::new(60.0, start);
+        buffer.set_tick_rate(30.0);
+        // Three ticks at 30 Hz is 100ms, same as six at 60 Hz
+        buffer.push(snapshot(0, 0.0, 0.0), start);
+        buffer.push(snapshot(3, 60.0, 0.0), at(start, 100));
+
+        let x = ball_x(&mut buffer, at(start, 150));
+        assert!((x - 30.0).abs() < 0.5, "x = {x}");
+    }
+
+    #[test]
+    fn stays_smooth_after_a_day_of_uptime() {
+        let start = Instant::now();
+        let mut buffer = SnapshotBuffer::new(60.0, start);
+        let day = 60 * 60 * 60 * 24;
+        buffer.push(snapshot(day, 0.0, 0.0), start);
+        buffer.push(snapshot(day + 6, 60.0, 0.0), at(start, 100));
+
+        // Every millisecond moves it along, nothing gets rounded to the same spot
+        let mut last = ball_x(&mut buffer, at(start, 100));
+        for millis in 101..=200 {
+            let x = ball_x(&mut buffer, at(start, millis));
+            assert!(x > last, "stuck at {x} on {millis}ms");
+            last = x;
+        }
+        let x = ball_x(&mut buffer, at(start, 150));
+        assert!((x - 30.0).abs() < 0.5, "x = {x}");
+    }
+}
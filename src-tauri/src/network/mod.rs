@@ -2,9 +2,14 @@ pub mod channels;
 pub mod clientid;
 pub mod clientnetworkhandler;
 pub mod clientrequest;
+pub mod codec;
+pub mod interpolation;
+#[cfg(feature = "desktop")]
 pub mod networkclient;
 pub mod networkhandler;
 pub mod networkinfo;
+pub mod reliability;
 pub mod serverevent;
+pub mod snapshotdelta;
 pub mod socketmanager;
 pub mod ttlmanager;
@@ -1,9 +1,18 @@
+use std::time::{Duration, Instant};
+
 use tauri::Emitter;
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    time,
+};
 
+use crate::game::{
+    frontend::gamepayload::GamePayload, gamemanager::DEFAULT_TICK_RATE, prediction::Prediction,
+};
 use crate::network::{
     clientid::{self, ClientId},
     clientrequest::{ClientMessage, ClientRequest},
+    interpolation::SnapshotBuffer,
     serverevent::ServerEvent,
 };
 
@@ -14,6 +23,12 @@ pub struct NetworkClient {
     pub server_event_rx: UnboundedReceiver<ServerEvent>,
     app: tauri::AppHandle,
     id: Option<ClientId>,
+    // The host's, local ticks have to line up with the ones inputs are used on
+    tick_rate: f32,
+    // Only joining clients predict, the host client has no round trip to hide
+    prediction: Option<Prediction>,
+    // Joining clients render from a buffer to hide packet jitter
+    snapshot_buffer: Option<SnapshotBuffer>,
 }
 
 impl NetworkClient {
@@ -29,21 +44,40 @@ impl NetworkClient {
             server_event_rx,
             app,
             id: None,
+            tick_rate: DEFAULT_TICK_RATE,
+            prediction: None,
+            snapshot_buffer: None,
         }
     }
 
     //init id
     pub fn init_id(&mut self, is_host: bool, id: Option<ClientId>) {
         if is_host {
-            self.id = Some(ClientId::new());
+            self.id = Some(ClientId::HOST);
         } else {
-            self.id = id
+            self.id = id;
+            self.prediction = Some(Prediction::new(self.tick_rate));
+            self.snapshot_buffer = Some(SnapshotBuffer::new(self.tick_rate, Instant::now()));
         }
     }
 
     //To Network
 
-    pub async fn send_request(&self, req: ClientRequest) {
+    pub async fn send_request(&mut self, req: ClientRequest) {
+        if let Some(prediction) = &mut self.prediction {
+            match &req {
+                // Predicted players send their input every local tick instead
+                ClientRequest::Input {
+                    entity_id, frame, ..
+                } if prediction.is_tracking(*entity_id) => {
+                    prediction.set_input(*entity_id, frame.clone());
+                    return;
+                }
+                ClientRequest::Remove { id } => prediction.untrack(*id),
+                _ => {}
+            }
+        }
+
         // Wrap the request in a ClientMessage
         let msg = ClientMessage {
             client_id: self.id,
@@ -56,12 +90,21 @@ impl NetworkClient {
 
     //Listen for network and frontend
     pub async fn start_listening(&mut self) {
+        let mut tick_rate = self.tick_rate;
+        let mut local_tick = time::interval(Duration::from_secs_f32(1.0 / tick_rate));
         loop {
             tokio::select! {
                 Some(req) = self.frontend_requend_rx.recv() => self.send_request(req).await,
                 Some(evt) = self.server_event_rx.recv() => self.handle_server_event(evt).await,
+                _ = local_tick.tick(), if self.snapshot_buffer.is_some() => self.local_tick().await,
                 else => break, // both channels closed, shutdown
             }
+
+            // The host told us its rate on joining
+            if self.tick_rate != tick_rate {
+                tick_rate = self.tick_rate;
+                local_tick = time::interval(Duration::from_secs_f32(1.0 / tick_rate));
+            }
         }
     }
 
@@ -69,29 +112,115 @@ impl NetworkClient {
     async fn handle_server_event(&mut self, event: ServerEvent) {
         match event {
             ServerEvent::WorldSnapshot { snapshot } => {
-                if let Err(err) = self.app.emit("game-state", snapshot.clone()) {
-                    eprintln!("Failed to emit game-state: {}", err);
+                if let Some(prediction) = &mut self.prediction {
+                    prediction.reconcile(&snapshot);
                 }
+                match &mut self.snapshot_buffer {
+                    Some(buffer) => buffer.push(snapshot, Instant::now()),
+                    None => self.emit_game_state(&snapshot),
+                }
+            }
+            ServerEvent::WorldDelta { .. } => {
+                // Deltas are rebuilt into full snapshots before they get here
             }
             ServerEvent::AddedPlayer { entity, client } => {
                 if client.0 != self.id.unwrap().0 {
                     return;
                 }
                 println!("Added Player");
+                if let Some(prediction) = &mut self.prediction {
+                    prediction.track(entity);
+                }
                 if let Err(err) = self.app.emit("added_player", entity.0) {
                     eprintln!("Failed to add a player to client: {}", err);
                 }
             }
-            ServerEvent::Joined { client_id } => {
+            ServerEvent::LobbyUpdated { lobby } => {
+                if let Err(err) = self.app.emit("lobby_updated", lobby) {
+                    eprintln!("Failed to send the lobby to client: {}", err);
+                }
+            }
+            ServerEvent::LobbySettings {
+                player_count,
+                rules,
+            } => {
+                let settings = serde_json::json!({
+                    "player_count": player_count,
+                    "rules": rules,
+                });
+                if let Err(err) = self.app.emit("lobby_settings", settings) {
+                    eprintln!("Failed to send lobby settings to client: {}", err);
+                }
+            }
+            ServerEvent::Kicked { entity, client } => {
+                if Some(client) != self.id {
+                    return;
+                }
+                println!("Kicked player {:?}", entity);
+                if let Some(prediction) = &mut self.prediction {
+                    prediction.untrack(entity);
+                }
+                if let Err(err) = self.app.emit("kicked", entity.0) {
+                    eprintln!("Failed to tell client about a kick: {}", err);
+                }
+            }
+            ServerEvent::Joined {
+                client_id,
+                tick_rate,
+            } => {
                 if client_id.is_none() {
                     eprintln!("Client is handling initial join request");
                 }
                 println!("Client Id Added");
                 self.id = client_id;
+                self.set_tick_rate(tick_rate);
                 if let Err(err) = self.app.emit("joined", client_id.unwrap()) {
                     eprintln!("Failed to to send join to client: {}", err);
                 }
             }
         }
     }
+
+    fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.tick_rate = tick_rate;
+        if let Some(prediction) = &mut self.prediction {
+            prediction.set_tick_rate(tick_rate);
+        }
+        if let Some(buffer) = &mut self.snapshot_buffer {
+            buffer.set_tick_rate(tick_rate);
+        }
+    }
+
+    //Local clock
+
+    ///Predicts owned players, then emits the buffered world with them on top.
+    async fn local_tick(&mut self) {
+        if let Some(prediction) = &mut self.prediction {
+            for request in prediction.tick() {
+                let msg = ClientMessage {
+                    client_id: self.id,
+                    request,
+                };
+                let _ = self.client_request_tx.send(msg);
+            }
+        }
+
+        let Some(mut payload) = self
+            .snapshot_buffer
+            .as_mut()
+            .and_then(|buffer| buffer.sample(Instant::now()))
+        else {
+            return;
+        };
+        if let Some(prediction) = &self.prediction {
+            prediction.apply_to(&mut payload);
+        }
+        self.emit_game_state(&payload);
+    }
+
+    fn emit_game_state(&self, payload: &GamePayload) {
+        if let Err(err) = self.app.emit("game-state", payload.clone()) {
+            eprintln!("Failed to emit game-state: {}", err);
+        }
+    }
 }
@@ -1,22 +1,45 @@
-use std::{collections::HashMap, net::SocketAddr};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    time,
+};
 
+use crate::game::gamemanager::{DEFAULT_RECONNECT_GRACE, DEFAULT_TICK_RATE};
 use crate::network::{
     clientid::ClientId,
     clientrequest::{ClientMessage, ClientRequest},
+    codec::WireMessage,
+    reliability::{Delivery, ReliabilityLayer},
     serverevent::ServerEvent,
+    snapshotdelta::{SnapshotDelta, SnapshotHistory},
     socketmanager::SocketData,
 };
 
 pub struct NetworkHandler {
     clients_by_addr: HashMap<SocketAddr, ClientId>,
     clients_by_id: HashMap<ClientId, SocketAddr>,
+    // Clients the TTLManager gave up on, by the address they may come back from, and when
+    departed: HashMap<SocketAddr, (ClientId, Instant)>,
+    reconnect_grace: Duration, // matches GameManager's, after it their players are gone
+    next_client_id: u32,       // 0 is ClientId::HOST
+    tick_rate: f32,            // the host's, handed to clients as they join
     game_tx: UnboundedSender<(ClientRequest, ClientId)>,
     client_message: UnboundedReceiver<ClientMessage>,
     server_events: UnboundedReceiver<ServerEvent>,
     client_events: UnboundedSender<ServerEvent>,
 
+    //Snapshots
+    snapshot_history: SnapshotHistory,
+    acked_ticks: HashMap<ClientId, u64>,
+
+    //Acks and resends for Joined/AddedPlayer/Add/Remove
+    reliability: ReliabilityLayer,
+
     //TTL
     pub reset_client_tx: UnboundedSender<ClientId>,
     pub client_died_rx: UnboundedReceiver<ClientId>,
@@ -40,10 +63,17 @@ impl NetworkHandler {
         Self {
             clients_by_addr: HashMap::new(),
             clients_by_id: HashMap::new(),
+            departed: HashMap::new(),
+            reconnect_grace: Duration::from_secs_f32(DEFAULT_RECONNECT_GRACE),
+            next_client_id: 1,
+            tick_rate: DEFAULT_TICK_RATE,
             game_tx,
             client_message,
             server_events,
             client_events,
+            snapshot_history: SnapshotHistory::new(),
+            acked_ticks: HashMap::new(),
+            reliability: ReliabilityLayer::new(),
             client_died_rx,
             reset_client_tx,
             incoming_socket_data,
@@ -51,13 +81,23 @@ impl NetworkHandler {
         }
     }
 
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.tick_rate = tick_rate;
+    }
+
+    pub fn set_reconnect_grace(&mut self, seconds: f32) {
+        self.reconnect_grace = Duration::from_secs_f32(seconds);
+    }
+
     pub async fn start_listening(&mut self) {
+        let mut resend = time::interval(Duration::from_millis(50));
         loop {
             tokio::select! {
                 Some(dta) = self.incoming_socket_data.recv() => self.handle_socket_data(dta).await,
                 Some(req) = self.client_message.recv() => self.handle_client_request(req).await,
                 Some(evt) = self.server_events.recv() => self.handle_server_event(evt).await,
                 Some(clt) = self.client_died_rx.recv() => self.handle_client_died(clt).await,
+                _ = resend.tick() => self.resend_reliable(),
                 else => break, // all channels closed, shutdown
             }
         }
@@ -65,14 +105,31 @@ impl NetworkHandler {
 
     async fn handle_socket_data(&mut self, data: SocketData) {
         println!("Handling");
-        let (peer_addr, bytes) = data;
+        let (peer_addr, frame) = data;
+
+        let Some(incoming) = self.reliability.receive(peer_addr, &frame) else {
+            eprintln!("Dropping unframed packet from {}: {:?}", peer_addr, frame);
+            if !self.clients_by_addr.contains_key(&peer_addr) {
+                self.send_blank_join(peer_addr);
+            }
+            return;
+        };
+        if let Some(ack) = incoming.ack {
+            let _ = self.outgoing_socket_data.send((peer_addr, ack));
+        }
+
+        for bytes in incoming.payloads {
+            self.handle_client_bytes(peer_addr, bytes);
+        }
+    }
 
+    fn handle_client_bytes(&mut self, peer_addr: SocketAddr, bytes: Vec<u8>) {
         //deserialize
-        let msg: ClientMessage = match serde_json::from_slice(&bytes) {
+        let msg = match ClientMessage::from_bytes(&bytes) {
             Ok(m) => m,
             Err(e) => {
                 eprintln!(
-                    "Failed to deserialize client message from {}: {:?} {}",
+                    "Failed to decode client message from {}: {:?} {}",
                     peer_addr, bytes, e
                 );
                 if !self.clients_by_addr.contains_key(&peer_addr) {
@@ -81,31 +138,55 @@ impl NetworkHandler {
                 return;
             }
         };
+        if !self.clients_by_addr.contains_key(&peer_addr) {
+            self.try_reconnect(peer_addr, msg.client_id, Instant::now());
+        }
+
         let request = msg.request;
         match request {
+            ClientRequest::Disconnected | ClientRequest::Reconnected => {
+                eprintln!(
+                    "Rejected {:?} from {}, only the host says who left",
+                    request, peer_addr
+                );
+                return;
+            }
             ClientRequest::Joined => {
                 self.handle_client_join(peer_addr);
                 return;
             }
             ClientRequest::Idle => {
-                let clientid = self.clients_by_addr.get(&data.0);
+                let clientid = self.clients_by_addr.get(&peer_addr);
                 if clientid.is_none() {
                     return;
                 }
                 self.reset_client_tx.send(*clientid.unwrap());
                 return;
             }
+            ClientRequest::Ack { tick } => {
+                self.handle_ack(peer_addr, tick);
+                return;
+            }
             _ => {}
         }
 
-        if msg.client_id.is_none() {
-            eprintln!("Recieved message from unknown client",);
+        // The address is who sent it, the id in the body is only a claim
+        let Some(client_id) = self.clients_by_addr.get(&peer_addr).copied() else {
+            eprintln!("Recieved message from unknown client at {}", peer_addr);
+            self.send_blank_join(peer_addr);
+            return;
+        };
+        if msg.client_id != Some(client_id) {
+            eprintln!(
+                "Rejected message from {} claiming to be {:?}, it's {:?}",
+                peer_addr, msg.client_id, client_id
+            );
             return;
         }
 
         // send to game manager
 
-        let _ = self.game_tx.send((request, msg.client_id.unwrap()));
+        let _ = self.game_tx.send((request, client_id));
     }
 
     fn handle_client_join(&mut self, peer_addr: SocketAddr) {
@@ -115,16 +196,18 @@ impl NetworkHandler {
         }
         println!("Recieving join req");
 
-        let client_id = ClientId::new();
+        let client_id = ClientId(self.next_client_id);
+        self.next_client_id += 1;
         self.clients_by_addr.insert(peer_addr, client_id);
         self.clients_by_id.insert(client_id, peer_addr);
+        self.reliability.hold_peer(peer_addr);
 
         let event = ServerEvent::Joined {
             client_id: Some(client_id),
+            tick_rate: self.tick_rate,
         };
 
-        let bytes = serde_json::to_vec(&event).unwrap();
-        self.send_over_network(peer_addr, bytes);
+        self.send_event(peer_addr, &event);
     }
 
     async fn handle_client_died(&mut self, id: ClientId) {
@@ -135,15 +218,67 @@ impl NetworkHandler {
         }
         self.clients_by_id.remove(&id);
         self.clients_by_addr.remove(&addr.unwrap());
+        self.acked_ticks.remove(&id);
+
+        // Their players stay for the grace window, GameManager counts it down too.
+        // So do their reliable sequences, the client still has its side of them.
+        let now = Instant::now();
+        self.prune_departed(now);
+        self.departed.insert(addr.unwrap(), (id, now));
+        let _ = self.game_tx.send((ClientRequest::Disconnected, id));
+    }
+
+    ///Takes a client back if it's coming from where a departed one was and still has its id.
+    fn try_reconnect(&mut self, peer_addr: SocketAddr, claimed: Option<ClientId>, now: Instant) {
+        self.prune_departed(now);
+        let Some((client_id, _)) = self.departed.get(&peer_addr).copied() else {
+            return;
+        };
+        if claimed != Some(client_id) {
+            return;
+        }
+
+        println!("Client {:?} is back from {}", client_id, peer_addr);
+        self.departed.remove(&peer_addr);
+        self.clients_by_addr.insert(peer_addr, client_id);
+        self.clients_by_id.insert(client_id, peer_addr);
+        let _ = self.game_tx.send((ClientRequest::Reconnected, client_id));
+    }
+
+    ///Forgets clients whose grace window ran out, their players have been removed by now.
+    fn prune_departed(&mut self, now: Instant) {
+        let grace = self.reconnect_grace;
+        let expired: Vec<SocketAddr> = self
+            .departed
+            .iter()
+            .filter(|(_, (_, left))| now.duration_since(*left) >= grace)
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        for addr in expired {
+            self.departed.remove(&addr);
+            if !self.clients_by_addr.contains_key(&addr) {
+                self.reliability.forget_peer(addr);
+            }
+        }
+    }
+
+    fn handle_ack(&mut self, peer_addr: SocketAddr, tick: u64) {
+        let Some(client_id) = self.clients_by_addr.get(&peer_addr) else {
+            return;
+        };
+
+        // Acks can arrive out of order, only ever move forward
+        let acked = self.acked_ticks.entry(*client_id).or_insert(tick);
+        *acked = (*acked).max(tick);
     }
 
-    fn send_blank_join(&self, addr: SocketAddr) {
-        let blank_join = ServerEvent::Joined { client_id: None };
-        let bytes = match serde_json::to_vec(&blank_join) {
-            Ok(b) => b,
-            Err(_) => return,
+    fn send_blank_join(&mut self, addr: SocketAddr) {
+        let blank_join = ServerEvent::Joined {
+            client_id: None,
+            tick_rate: self.tick_rate,
         };
-        self.send_over_network(addr, bytes);
+        self.send_event(addr, &blank_join);
         println!("Sending blank");
     }
 
@@ -160,10 +295,11 @@ impl NetworkHandler {
         let _ = self.game_tx.send((request, client_id.unwrap()));
     }
 
-    async fn handle_server_event(&self, event: ServerEvent) {
+    async fn handle_server_event(&mut self, event: ServerEvent) {
         match &event {
             ServerEvent::Joined {
                 client_id: Some(id),
+                ..
             } => {
                 self.compose_client_send(*id, event);
             }
@@ -173,42 +309,280 @@ impl NetworkHandler {
             }
 
             ServerEvent::WorldSnapshot { .. } => {
-                self.send_to_all(event);
+                self.broadcast_snapshot(event);
+            }
+
+            ServerEvent::LobbyUpdated { .. } | ServerEvent::LobbySettings { .. } => {
+                self.broadcast_event(event);
+            }
+
+            ServerEvent::Kicked { client, .. } => {
+                self.compose_client_send(*client, event);
             }
 
             _ => {}
         }
     }
 
-    fn compose_client_send(&self, client_id: ClientId, event: ServerEvent) {
-        if let Some(addr) = self.clients_by_id.get(&client_id) {
-            let bytes = match serde_json::to_vec(&event) {
-                Ok(b) => b,
-                Err(_) => return,
-            };
-            self.send_over_network(*addr, bytes);
+    fn compose_client_send(&mut self, client_id: ClientId, event: ServerEvent) {
+        if let Some(addr) = self.clients_by_id.get(&client_id).copied() {
+            self.send_event(addr, &event);
         } else {
             self.send_to_host_client(event); //host client isn't stored
         }
     }
 
-    fn send_to_all(&self, event: ServerEvent) {
-        let bytes = match serde_json::to_vec(&event) {
-            Ok(b) => b,
-            Err(_) => return,
+    ///Sends each client only what changed since the last snapshot it acked,
+    ///or the whole thing if that snapshot is no longer in the history.
+    fn broadcast_snapshot(&mut self, event: ServerEvent) {
+        let ServerEvent::WorldSnapshot { snapshot } = &event else {
+            return;
         };
 
-        for addr in self.clients_by_id.values() {
-            self.send_over_network(*addr, bytes.clone());
+        let now = Instant::now();
+        let mut full_bytes = None;
+        for (client_id, addr) in self.clients_by_id.iter() {
+            let base = self
+                .acked_ticks
+                .get(client_id)
+                .and_then(|tick| self.snapshot_history.get(*tick));
+
+            let bytes = match base {
+                Some(base) => ServerEvent::WorldDelta {
+                    delta: SnapshotDelta::between(base, snapshot),
+                }
+                .to_bytes(),
+                None => full_bytes.get_or_insert_with(|| event.to_bytes()).clone(),
+            };
+            let frame = self
+                .reliability
+                .wrap(*addr, Delivery::Unreliable, &bytes, now);
+            let _ = self.outgoing_socket_data.send((*addr, frame));
+        }
+
+        self.snapshot_history.push(snapshot.clone());
+        self.send_to_host_client(event);
+    }
+
+    fn broadcast_event(&mut self, event: ServerEvent) {
+        let addrs: Vec<SocketAddr> = self.clients_by_id.values().copied().collect();
+        for addr in addrs {
+            self.send_event(addr, &event);
         }
         self.send_to_host_client(event);
     }
 
-    fn send_over_network(&self, addr: SocketAddr, bytes: Vec<u8>) {
-        let _ = self.outgoing_socket_data.send((addr, bytes));
+    fn send_event(&mut self, addr: SocketAddr, event: &ServerEvent) {
+        let frame =
+            self.reliability
+                .wrap(addr, event.delivery(), &event.to_bytes(), Instant::now());
+        let _ = self.outgoing_socket_data.send((addr, frame));
+    }
+
+    fn resend_reliable(&mut self) {
+        // Stop resending to clients that aren't coming back
+        self.prune_departed(Instant::now());
+        for data in self.reliability.resend_due(Instant::now()) {
+            let _ = self.outgoing_socket_data.send(data);
+        }
     }
 
     fn send_to_host_client(&self, event: ServerEvent) {
         let _ = self.client_events.send(event); //host client
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+    use crate::{
+        game::{matchrules::MatchRules, state::entityid::EntityId},
+        network::{codec::WireMessage, reliability::ReliabilityLayer},
+    };
+
+    type GameRx = UnboundedReceiver<(ClientRequest, ClientId)>;
+
+    ///A handler with nothing on the other end but the game's request queue and the socket.
+    fn handler() -> (NetworkHandler, GameRx, UnboundedReceiver<SocketData>) {
+        let (game_tx, game_rx) = unbounded_channel();
+        let (_, client_message) = unbounded_channel();
+        let (_, server_events) = unbounded_channel();
+        let (client_events, _) = unbounded_channel();
+        let (reset_client_tx, _) = unbounded_channel();
+        let (_, client_died_rx) = unbounded_channel();
+        let (_, incoming_socket_data) = unbounded_channel();
+        let (outgoing_socket_data, outgoing) = unbounded_channel();
+        let nm = NetworkHandler::new(
+            game_tx,
+            client_message,
+            server_events,
+            client_events,
+            reset_client_tx,
+            client_died_rx,
+            incoming_socket_data,
+            outgoing_socket_data,
+        );
+        (nm, game_rx, outgoing)
+    }
+
+    fn joined_client(nm: &mut NetworkHandler) -> (SocketAddr, ClientId) {
+        let addr: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        nm.handle_client_join(addr);
+        (addr, nm.clients_by_addr[&addr])
+    }
+
+    #[tokio::test]
+    async fn client_back_within_the_grace_window_keeps_its_id() {
+        let (mut nm, mut game_rx, _) = handler();
+        let (addr, id) = joined_client(&mut nm);
+        nm.handle_client_died(id).await;
+        assert!(matches!(
+            game_rx.try_recv(),
+            Ok((ClientRequest::Disconnected, dropped)) if dropped == id
+        ));
+
+        // Someone else's id from that address doesn't count
+        nm.try_reconnect(addr, Some(ClientId(id.0 + 1)), Instant::now());
+        assert!(!nm.clients_by_addr.contains_key(&addr));
+
+        nm.try_reconnect(addr, Some(id), Instant::now());
+        assert_eq!(nm.clients_by_addr.get(&addr), Some(&id));
+        assert!(nm.departed.is_empty());
+        assert!(matches!(
+            game_rx.try_recv(),
+            Ok((ClientRequest::Reconnected, back)) if back == id
+        ));
+    }
+
+    #[tokio::test]
+    async fn departed_clients_are_forgotten_once_the_grace_runs_out() {
+        let (mut nm, mut game_rx, _) = handler();
+        nm.set_reconnect_grace(1.0);
+        let (addr, id) = joined_client(&mut nm);
+        nm.handle_client_died(id).await;
+        let _ = game_rx.try_recv();
+        // The unacked join is kept for it while it might come back
+        assert_eq!(nm.reliability.in_flight_count(addr), 1);
+
+        nm.try_reconnect(addr, Some(id), Instant::now() + Duration::from_secs(2));
+        assert!(!nm.clients_by_addr.contains_key(&addr));
+        assert!(nm.departed.is_empty());
+        assert_eq!(nm.reliability.in_flight_count(addr), 0);
+        assert!(game_rx.try_recv().is_err());
+    }
+
+    ///The client's end of the link, framing and unframing through its own reliability layer.
+    struct Client {
+        addr: SocketAddr,
+        host: SocketAddr,
+        id: Option<ClientId>,
+        reliability: ReliabilityLayer,
+    }
+
+    impl Client {
+        async fn send(&mut self, nm: &mut NetworkHandler, request: ClientRequest) {
+            let msg = ClientMessage {
+                client_id: self.id,
+                request,
+            };
+            let frame = self.reliability.wrap(
+                self.host,
+                msg.request.delivery(),
+                &msg.to_bytes(),
+                Instant::now(),
+            );
+            nm.handle_socket_data((self.addr, frame)).await;
+        }
+
+        ///Everything the host sent that made it through, acks and resends aside.
+        fn receive(&mut self, outgoing: &mut UnboundedReceiver<SocketData>) -> Vec<ServerEvent> {
+            let mut events = Vec::new();
+            while let Ok((to, frame)) = outgoing.try_recv() {
+                if to != self.addr {
+                    continue;
+                }
+                let Some(incoming) = self.reliability.receive(self.host, &frame) else {
+                    continue;
+                };
+                for bytes in incoming.payloads {
+                    events.push(ServerEvent::from_bytes(&bytes).unwrap());
+                }
+            }
+            events
+        }
+    }
+
+    fn settings(player_count: u8) -> ServerEvent {
+        ServerEvent::LobbySettings {
+            player_count,
+            rules: MatchRules::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn reliable_messages_still_flow_both_ways_after_a_reconnect() {
+        let (mut nm, mut game_rx, mut outgoing) = handler();
+        let mut client = Client {
+            addr: "127.0.0.1:9000".parse().unwrap(),
+            host: "127.0.0.1:7000".parse().unwrap(),
+            id: None,
+            reliability: ReliabilityLayer::new(),
+        };
+        client.reliability.hold_peer(client.host);
+
+        client.send(&mut nm, ClientRequest::Joined).await;
+        let joined = client.receive(&mut outgoing);
+        let Some(ServerEvent::Joined {
+            client_id: Some(id),
+            ..
+        }) = joined.first()
+        else {
+            panic!("no join handshake: {joined:?}");
+        };
+        client.id = Some(*id);
+        let id = *id;
+
+        // Get some sequences going on the players channel both ways
+        let ready = ClientRequest::SetReady {
+            entity_id: EntityId(1),
+            ready: true,
+        };
+        client.send(&mut nm, ready).await;
+        assert!(matches!(
+            game_rx.try_recv(),
+            Ok((ClientRequest::SetReady { .. }, from)) if from == id
+        ));
+        nm.handle_server_event(settings(2)).await;
+        assert_eq!(client.receive(&mut outgoing).len(), 1);
+
+        nm.handle_client_died(id).await;
+        let _ = game_rx.try_recv();
+
+        // Comes back with its next request, still numbered where it left off
+        let rename = ClientRequest::SetName {
+            entity_id: EntityId(1),
+            name: "back".into(),
+        };
+        client.send(&mut nm, rename).await;
+        assert!(matches!(
+            game_rx.try_recv(),
+            Ok((ClientRequest::Reconnected, back)) if back == id
+        ));
+        assert!(matches!(
+            game_rx.try_recv(),
+            Ok((ClientRequest::SetName { .. }, from)) if from == id
+        ));
+
+        nm.handle_server_event(settings(4)).await;
+        let events = client.receive(&mut outgoing);
+        assert!(matches!(
+            events.as_slice(),
+            [ServerEvent::LobbySettings {
+                player_count: 4,
+                ..
+            }]
+        ));
+    }
+}
@@ -0,0 +1,468 @@
+//! Optional reliable, ordered delivery on top of the plain UDP socket.
+//!
+//! Every datagram starts with a frame byte:
+//! `[0][payload]` unreliable, `[1][channel][seq u32][payload]` reliable,
+//! `[2][channel][seq u32]` ack. Reliable payloads are resent until acked and
+//! handed over strictly in order per channel. Snapshots and inputs stay
+//! unreliable, losing one just means the next one replaces it.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::network::socketmanager::SocketData;
+
+const FRAME_UNRELIABLE: u8 = 0;
+const FRAME_RELIABLE: u8 = 1;
+const FRAME_ACK: u8 = 2;
+
+pub const RESEND_AFTER: Duration = Duration::from_millis(150);
+/// After this many resends the peer is treated as gone and the message dropped
+pub const MAX_RESENDS: u32 = 40;
+/// How far ahead of the next expected sequence a frame may be and still get buffered
+const RECEIVE_WINDOW: u32 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReliableChannel {
+    /// Join handshake
+    Session = 0,
+    /// Adding and removing players
+    Players = 1,
+}
+
+impl ReliableChannel {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(ReliableChannel::Session),
+            1 => Some(ReliableChannel::Players),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Unreliable,
+    Reliable(ReliableChannel),
+}
+
+struct InFlight {
+    frame: Vec<u8>,
+    last_sent: Instant,
+    resends: u32,
+}
+
+#[derive(Default)]
+struct ChannelState {
+    //Sending
+    next_send_seq: u32,
+    in_flight: BTreeMap<u32, InFlight>,
+
+    //Receiving
+    next_recv_seq: u32,
+    out_of_order: BTreeMap<u32, Vec<u8>>,
+}
+
+/// What came out of one datagram.
+pub struct Incoming {
+    /// Payloads ready to decode, in order
+    pub payloads: Vec<Vec<u8>>,
+    /// Ack to send straight back to the peer
+    pub ack: Option<Vec<u8>>,
+}
+
+pub struct ReliabilityLayer {
+    peers: HashMap<SocketAddr, HashMap<ReliableChannel, ChannelState>>,
+    /// Peers that are never given up on, their sequences have to survive a silence
+    held: HashSet<SocketAddr>,
+}
+
+impl ReliabilityLayer {
+    pub fn new() -> Self {
+        Self {
+            peers: HashMap::new(),
+            held: HashSet::new(),
+        }
+    }
+
+    ///Frames a payload for sending. Reliable ones are kept until the peer acks them.
+    pub fn wrap(
+        &mut self,
+        addr: SocketAddr,
+        delivery: Delivery,
+        payload: &[u8],
+        now: Instant,
+    ) -> Vec<u8> {
+        match delivery {
+            Delivery::Unreliable => {
+                let mut frame = Vec::with_capacity(payload.len() + 1);
+                frame.push(FRAME_UNRELIABLE);
+                frame.extend_from_slice(payload);
+                frame
+            }
+            Delivery::Reliable(channel) => {
+                let state = self.channel(addr, channel);
+                let seq = state.next_send_seq;
+                state.next_send_seq += 1;
+
+                let mut frame = Vec::with_capacity(payload.len() + 6);
+                frame.push(FRAME_RELIABLE);
+                frame.push(channel as u8);
+                frame.extend_from_slice(&seq.to_le_bytes());
+                frame.extend_from_slice(payload);
+
+                state.in_flight.insert(
+                    seq,
+                    InFlight {
+                        frame: frame.clone(),
+                        last_sent: now,
+                        resends: 0,
+                    },
+                );
+                frame
+            }
+        }
+    }
+
+    ///Unwraps a datagram. Returns None if it isn't a frame at all.
+    pub fn receive(&mut self, addr: SocketAddr, frame: &[u8]) -> Option<Incoming> {
+        let (&kind, rest) = frame.split_first()?;
+        match kind {
+            FRAME_UNRELIABLE => Some(Incoming {
+                payloads: vec![rest.to_vec()],
+                ack: None,
+            }),
+            FRAME_RELIABLE => {
+                let (channel, seq, payload) = Self::read_header(rest)?;
+                let state = self.channel(addr, channel);
+
+                // Anything below next_recv_seq was already delivered, just ack it again
+                if seq >= state.next_recv_seq + RECEIVE_WINDOW {
+                    return None;
+                }
+                if seq >= state.next_recv_seq {
+                    state.out_of_order.insert(seq, payload.to_vec());
+                }
+
+                let mut payloads = Vec::new();
+                while let Some(payload) = state.out_of_order.remove(&state.next_recv_seq) {
+                    payloads.push(payload);
+                    state.next_recv_seq += 1;
+                }
+
+                let mut ack = Vec::with_capacity(6);
+                ack.push(FRAME_ACK);
+                ack.push(channel as u8);
+                ack.extend_from_slice(&seq.to_le_bytes());
+
+                Some(Incoming {
+                    payloads,
+                    ack: Some(ack),
+                })
+            }
+            FRAME_ACK => {
+                let (channel, seq, _) = Self::read_header(rest)?;
+                if let Some(state) = self
+                    .peers
+                    .get_mut(&addr)
+                    .and_then(|channels| channels.get_mut(&channel))
+                {
+                    state.in_flight.remove(&seq);
+                }
+                Some(Incoming {
+                    payloads: Vec::new(),
+                    ack: None,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    ///Frames whose ack is overdue. Peers that never answer get dropped.
+    pub fn resend_due(&mut self, now: Instant) -> Vec<SocketData> {
+        let mut resend = Vec::new();
+        let mut gone = Vec::new();
+
+        for (addr, channels) in self.peers.iter_mut() {
+            for state in channels.values_mut() {
+                for in_flight in state.in_flight.values_mut() {
+                    if now.duration_since(in_flight.last_sent) < RESEND_AFTER {
+                        continue;
+                    }
+                    if in_flight.resends >= MAX_RESENDS && !self.held.contains(addr) {
+                        gone.push(*addr);
+                        break;
+                    }
+                    in_flight.resends += 1;
+                    in_flight.last_sent = now;
+                    resend.push((*addr, in_flight.frame.clone()));
+                }
+            }
+        }
+
+        for addr in gone {
+            eprintln!("{} stopped acking reliable messages, dropping them", addr);
+            self.forget_peer(addr);
+            resend.retain(|(a, _)| *a != addr);
+        }
+
+        resend
+    }
+
+    pub fn forget_peer(&mut self, addr: SocketAddr) {
+        self.peers.remove(&addr);
+        self.held.remove(&addr);
+    }
+
+    ///Keeps resending to a peer however long it goes quiet, until it's forgotten.
+    ///Dropping its sequences while it might still come back would desync both sides.
+    pub fn hold_peer(&mut self, addr: SocketAddr) {
+        self.held.insert(addr);
+    }
+
+    pub fn in_flight_count(&self, addr: SocketAddr) -> usize {
+        self.peers.get(&addr).map_or(0, |channels| {
+            channels.values().map(|s| s.in_flight.len()).sum()
+        })
+    }
+
+    //Helpers
+
+    fn channel(&mut self, addr: SocketAddr, channel: ReliableChannel) -> &mut ChannelState {
+        self.peers
+            .entry(addr)
+            .or_default()
+            .entry(channel)
+            .or_default()
+    }
+
+    fn read_header(bytes: &[u8]) -> Option<(ReliableChannel, u32, &[u8])> {
+        let channel = ReliableChannel::from_u8(*bytes.first()?)?;
+        let seq = u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?);
+        Some((channel, seq, &bytes[5..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one-way link that drops, delays and reorders datagrams from a fixed seed.
+    struct LossySocket {
+        seed: u64,
+        drop_percent: u64,
+        in_transit: Vec<(Instant, Vec<u8>)>,
+    }
+
+    impl LossySocket {
+        fn new(seed: u64, drop_percent: u64) -> Self {
+            Self {
+                seed,
+                drop_percent,
+                in_transit: Vec::new(),
+            }
+        }
+
+        fn next_random(&mut self) -> u64 {
+            // xorshift, good enough to scramble packets reproducibly
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            self.seed
+        }
+
+        fn send(&mut self, frame: Vec<u8>, now: Instant) {
+            if self.next_random() % 100 < self.drop_percent {
+                return;
+            }
+            let delay = Duration::from_millis(5 + self.next_random() % 80);
+            self.in_transit.push((now + delay, frame));
+        }
+
+        fn arrived(&mut self, now: Instant) -> Vec<Vec<u8>> {
+            let (ready, waiting) = self.in_transit.drain(..).partition(|(at, _)| *at <= now);
+            self.in_transit = waiting;
+            let mut ready: Vec<(Instant, Vec<u8>)> = ready;
+            ready.sort_by_key(|(at, _)| *at);
+            ready.into_iter().map(|(_, frame)| frame).collect()
+        }
+    }
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    /// Pushes reliable messages from a to b over lossy links both ways until everything is acked.
+    fn run_lossy_exchange(seed: u64, drop_percent: u64, count: u32) -> Vec<Vec<u8>> {
+        let (a_addr, b_addr) = (addr(1000), addr(2000));
+        let mut a = ReliabilityLayer::new();
+        let mut b = ReliabilityLayer::new();
+        let mut a_to_b = LossySocket::new(seed, drop_percent);
+        let mut b_to_a = LossySocket::new(seed.wrapping_mul(31) | 1, drop_percent);
+
+        let start = Instant::now();
+        let mut now = start;
+        let mut delivered = Vec::new();
+
+        for i in 0..count {
+            let channel = if i % 3 == 0 {
+                ReliableChannel::Session
+            } else {
+                ReliableChannel::Players
+            };
+            let frame = a.wrap(b_addr, Delivery::Reliable(channel), &i.to_le_bytes(), now);
+            a_to_b.send(frame, now);
+            // Unreliable traffic mixed in, free to get lost
+            let noise = a.wrap(b_addr, Delivery::Unreliable, b"snapshot", now);
+            a_to_b.send(noise, now);
+        }
+
+        while a.in_flight_count(b_addr) > 0 {
+            now += Duration::from_millis(10);
+            assert!(now - start < Duration::from_secs(60), "never converged");
+
+            for frame in a_to_b.arrived(now) {
+                let incoming = b.receive(a_addr, &frame).expect("valid frame");
+                for payload in incoming.payloads {
+                    if payload != b"snapshot" {
+                        delivered.push(payload);
+                    }
+                }
+                if let Some(ack) = incoming.ack {
+                    b_to_a.send(ack, now);
+                }
+            }
+            for frame in b_to_a.arrived(now) {
+                a.receive(b_addr, &frame).expect("valid frame");
+            }
+            for (_, frame) in a.resend_due(now) {
+                a_to_b.send(frame, now);
+            }
+        }
+
+        delivered
+    }
+
+    fn per_channel(delivered: &[Vec<u8>]) -> (Vec<u32>, Vec<u32>) {
+        delivered
+            .iter()
+            .map(|p| u32::from_le_bytes(p.as_slice().try_into().unwrap()))
+            .partition(|i| i % 3 == 0)
+    }
+
+    #[test]
+    fn delivers_everything_in_order_over_a_lossy_link() {
+        for seed in [1, 7, 42, 1234] {
+            let delivered = run_lossy_exchange(seed, 30, 60);
+            assert_eq!(delivered.len(), 60, "lost or duplicated messages");
+
+            let (session, players) = per_channel(&delivered);
+            assert_eq!(session, (0..60).filter(|i| i % 3 == 0).collect::<Vec<_>>());
+            assert_eq!(players, (0..60).filter(|i| i % 3 != 0).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn duplicates_are_acked_but_delivered_once() {
+        let mut a = ReliabilityLayer::new();
+        let mut b = ReliabilityLayer::new();
+        let now = Instant::now();
+
+        let frame = a.wrap(
+            addr(2),
+            Delivery::Reliable(ReliableChannel::Players),
+            b"add",
+            now,
+        );
+        let first = b.receive(addr(1), &frame).unwrap();
+        let second = b.receive(addr(1), &frame).unwrap();
+
+        assert_eq!(first.payloads, vec![b"add".to_vec()]);
+        assert!(second.payloads.is_empty());
+        assert!(second.ack.is_some());
+    }
+
+    #[test]
+    fn resends_until_acked_then_stops() {
+        let mut a = ReliabilityLayer::new();
+        let mut b = ReliabilityLayer::new();
+        let start = Instant::now();
+
+        let frame = a.wrap(
+            addr(2),
+            Delivery::Reliable(ReliableChannel::Session),
+            b"joined",
+            start,
+        );
+        assert!(a.resend_due(start).is_empty());
+        assert_eq!(a.resend_due(start + RESEND_AFTER).len(), 1);
+
+        let ack = b.receive(addr(1), &frame).unwrap().ack.unwrap();
+        a.receive(addr(2), &ack).unwrap();
+        assert!(a.resend_due(start + RESEND_AFTER * 3).is_empty());
+        assert_eq!(a.in_flight_count(addr(2)), 0);
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_peer() {
+        let mut a = ReliabilityLayer::new();
+        let mut now = Instant::now();
+        a.wrap(
+            addr(2),
+            Delivery::Reliable(ReliableChannel::Session),
+            b"hello?",
+            now,
+        );
+
+        for _ in 0..MAX_RESENDS {
+            now += RESEND_AFTER;
+            assert_eq!(a.resend_due(now).len(), 1);
+        }
+        now += RESEND_AFTER;
+        assert!(a.resend_due(now).is_empty());
+        assert_eq!(a.in_flight_count(addr(2)), 0);
+    }
+
+    #[test]
+    fn held_peers_keep_their_sequences_through_a_silence() {
+        let mut a = ReliabilityLayer::new();
+        let mut b = ReliabilityLayer::new();
+        let mut now = Instant::now();
+        a.hold_peer(addr(2));
+        let lost = a.wrap(
+            addr(2),
+            Delivery::Reliable(ReliableChannel::Players),
+            b"first",
+            now,
+        );
+
+        for _ in 0..MAX_RESENDS * 2 {
+            now += RESEND_AFTER;
+            assert_eq!(a.resend_due(now).len(), 1);
+        }
+        assert_eq!(a.in_flight_count(addr(2)), 1);
+
+        // Back again, the next message follows on from the one it missed
+        let next = a.wrap(
+            addr(2),
+            Delivery::Reliable(ReliableChannel::Players),
+            b"second",
+            now,
+        );
+        assert!(b.receive(addr(1), &next).unwrap().payloads.is_empty());
+        let delivered = b.receive(addr(1), &lost).unwrap().payloads;
+        assert_eq!(delivered, vec![b"first".to_vec(), b"second".to_vec()]);
+    }
+
+    #[test]
+    fn rejects_non_frames() {
+        let mut layer = ReliabilityLayer::new();
+        assert!(layer.receive(addr(1), b"JOIN").is_none());
+        assert!(layer.receive(addr(1), &[]).is_none());
+        assert!(layer
+            .receive(addr(1), &[FRAME_RELIABLE, 9, 0, 0, 0, 0])
+            .is_none());
+    }
+}
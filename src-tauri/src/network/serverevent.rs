@@ -1,11 +1,61 @@
 use crate::{
-    game::{frontend::gamepayload::GamePayload, state::entityid::EntityId},
-    network::clientid::ClientId,
+    game::{
+        frontend::gamepayload::GamePayload,
+        matchrules::MatchRules,
+        state::{entityid::EntityId, lobbyplayer::LobbyState},
+    },
+    network::{
+        clientid::ClientId,
+        reliability::{Delivery, ReliableChannel},
+        snapshotdelta::SnapshotDelta,
+    },
 };
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub enum ServerEvent {
-    Joined { client_id: Option<ClientId> },
-    WorldSnapshot { snapshot: GamePayload },
-    AddedPlayer { entity: EntityId, client: ClientId },
+    /// `tick_rate` is the host's, clients predict and interpolate at it
+    Joined {
+        client_id: Option<ClientId>,
+        tick_rate: f32,
+    },
+    WorldSnapshot {
+        snapshot: GamePayload,
+    },
+    WorldDelta {
+        delta: SnapshotDelta,
+    },
+    AddedPlayer {
+        entity: EntityId,
+        client: ClientId,
+    },
+    /// Someone joined, left, renamed, switched teams or readied up
+    LobbyUpdated {
+        lobby: LobbyState,
+    },
+    /// The host changed the match settings, everyone has to ready up again
+    LobbySettings {
+        player_count: u8,
+        rules: MatchRules,
+    },
+    /// The host kicked one of this client's players
+    Kicked {
+        entity: EntityId,
+        client: ClientId,
+    },
+}
+
+impl ServerEvent {
+    ///Snapshots go out every tick, so a lost one is replaced by the next
+    pub fn delivery(&self) -> Delivery {
+        match self {
+            ServerEvent::Joined { .. } => Delivery::Reliable(ReliableChannel::Session),
+            ServerEvent::AddedPlayer { .. }
+            | ServerEvent::LobbyUpdated { .. }
+            | ServerEvent::LobbySettings { .. }
+            | ServerEvent::Kicked { .. } => Delivery::Reliable(ReliableChannel::Players),
+            ServerEvent::WorldSnapshot { .. } | ServerEvent::WorldDelta { .. } => {
+                Delivery::Unreliable
+            }
+        }
+    }
 }
@@ -0,0 +1,264 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::game::{
+    frontend::{fxevent::FxEvent, gamepayload::GamePayload},
+    gamemanager::GamePhase,
+    scoremanager::ScoreManager,
+    state::{
+        entityid::EntityId,
+        enums::{ActionState, Kind, Shape},
+        lobbyplayer::LobbyState,
+        renderstate::RenderState,
+    },
+};
+
+/// How many sent/received snapshots each side remembers, about a second at 60 Hz.
+/// The host only deltas against ticks still in here, so clients keep at least as many.
+pub const SNAPSHOT_HISTORY: usize = 64;
+
+/// The fields of one RenderState that changed since the base snapshot. `None` means unchanged.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+pub struct RenderStateDelta {
+    pub id: u32,
+    pub x: Option<f32>,
+    pub y: Option<f32>,
+    pub vx: Option<f32>,
+    pub vy: Option<f32>,
+    pub angle: Option<f32>,
+    pub shape: Option<Shape>,
+    pub action_state: Option<ActionState>,
+    pub is_holding: Option<bool>,
+    pub is_held: Option<bool>,
+    pub is_static: Option<bool>,
+    pub kind: Option<Kind>,
+    pub player_id: Option<EntityId>,
+    pub team_id: Option<Option<u8>>,
+    pub input_sequence: Option<u32>,
+}
+
+impl RenderStateDelta {
+    /// Returns None when nothing changed.
+    pub fn between(base: &RenderState, next: &RenderState) -> Option<Self> {
+        fn changed<T: PartialEq + Copy>(a: T, b: T) -> Option<T> {
+            if a == b {
+                None
+            } else {
+                Some(b)
+            }
+        }
+
+        let delta = Self {
+            id: next.id,
+            x: changed(base.x, next.x),
+            y: changed(base.y, next.y),
+            vx: changed(base.vx, next.vx),
+            vy: changed(base.vy, next.vy),
+            angle: changed(base.angle, next.angle),
+            shape: changed(base.shape, next.shape),
+            action_state: changed(base.action_state, next.action_state),
+            is_holding: changed(base.is_holding, next.is_holding),
+            is_held: changed(base.is_held, next.is_held),
+            is_static: changed(base.is_static, next.is_static),
+            kind: changed(base.kind, next.kind),
+            player_id: changed(base.player_id, next.player_id),
+            team_id: changed(base.team_id, next.team_id),
+            input_sequence: changed(base.input_sequence, next.input_sequence),
+        };
+
+        if delta.is_empty() {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.is_none()
+            && self.y.is_none()
+            && self.vx.is_none()
+            && self.vy.is_none()
+            && self.angle.is_none()
+            && self.shape.is_none()
+            && self.action_state.is_none()
+            && self.is_holding.is_none()
+            && self.is_held.is_none()
+            && self.is_static.is_none()
+            && self.kind.is_none()
+            && self.player_id.is_none()
+            && self.team_id.is_none()
+            && self.input_sequence.is_none()
+    }
+
+    pub fn apply(&self, state: &mut RenderState) {
+        if let Some(x) = self.x {
+            state.x = x;
+        }
+        if let Some(y) = self.y {
+            state.y = y;
+        }
+        if let Some(vx) = self.vx {
+            state.vx = vx;
+        }
+        if let Some(vy) = self.vy {
+            state.vy = vy;
+        }
+        if let Some(angle) = self.angle {
+            state.angle = angle;
+        }
+        if let Some(shape) = self.shape {
+            state.shape = shape;
+        }
+        if let Some(action_state) = self.action_state {
+            state.action_state = action_state;
+        }
+        if let Some(is_holding) = self.is_holding {
+            state.is_holding = is_holding;
+        }
+        if let Some(is_held) = self.is_held {
+            state.is_held = is_held;
+        }
+        if let Some(is_static) = self.is_static {
+            state.is_static = is_static;
+        }
+        if let Some(kind) = self.kind {
+            state.kind = kind;
+        }
+        if let Some(player_id) = self.player_id {
+            state.player_id = player_id;
+        }
+        if let Some(team_id) = self.team_id {
+            state.team_id = team_id;
+        }
+        if let Some(input_sequence) = self.input_sequence {
+            state.input_sequence = input_sequence;
+        }
+    }
+}
+
+/// Everything that changed between a snapshot the client acknowledged and the current one.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SnapshotDelta {
+    pub base_tick: u64,
+    pub tick: u64,
+    pub removed: Vec<u32>,
+    pub added: Vec<RenderState>,
+    pub changed: Vec<RenderStateDelta>,
+    pub score_manager: Option<ScoreManager>,
+    pub game_phase: Option<GamePhase>,
+    pub lobby_state: Option<LobbyState>,
+    pub fx_events: Vec<FxEvent>,
+}
+
+impl SnapshotDelta {
+    pub fn between(base: &GamePayload, next: &GamePayload) -> Self {
+        let base_states: HashMap<u32, &RenderState> =
+            base.render_states.iter().map(|s| (s.id, s)).collect();
+        let next_ids: HashSet<u32> = next.render_states.iter().map(|s| s.id).collect();
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for state in &next.render_states {
+            match base_states.get(&state.id) {
+                Some(old) => {
+                    if let Some(delta) = RenderStateDelta::between(old, state) {
+                        changed.push(delta);
+                    }
+                }
+                None => added.push(state.clone()),
+            }
+        }
+
+        let removed = base
+            .render_states
+            .iter()
+            .map(|s| s.id)
+            .filter(|id| !next_ids.contains(id))
+            .collect();
+
+        Self {
+            base_tick: base.tick,
+            tick: next.tick,
+            removed,
+            added,
+            changed,
+            score_manager: (base.score_manager != next.score_manager)
+                .then(|| next.score_manager.clone()),
+            game_phase: (base.game_phase != next.game_phase).then(|| next.game_phase.clone()),
+            lobby_state: (base.lobby_state != next.lobby_state).then(|| next.lobby_state.clone()),
+            fx_events: next.fx_events.clone(),
+        }
+    }
+
+    /// Rebuilds the full snapshot. Surviving entities keep the base order, new ones go last.
+    pub fn apply(&self, base: &GamePayload) -> GamePayload {
+        let removed: HashSet<u32> = self.removed.iter().copied().collect();
+        let mut render_states: Vec<RenderState> = base
+            .render_states
+            .iter()
+            .filter(|s| !removed.contains(&s.id))
+            .cloned()
+            .collect();
+
+        let index: HashMap<u32, usize> = render_states
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.id, i))
+            .collect();
+        for delta in &self.changed {
+            if let Some(&i) = index.get(&delta.id) {
+                delta.apply(&mut render_states[i]);
+            }
+        }
+        render_states.extend(self.added.iter().cloned());
+
+        GamePayload {
+            tick: self.tick,
+            render_states,
+            score_manager: self
+                .score_manager
+                .clone()
+                .unwrap_or_else(|| base.score_manager.clone()),
+            game_phase: self
+                .game_phase
+                .clone()
+                .unwrap_or_else(|| base.game_phase.clone()),
+            fx_events: self.fx_events.clone(),
+            lobby_state: self
+                .lobby_state
+                .clone()
+                .unwrap_or_else(|| base.lobby_state.clone()),
+        }
+    }
+}
+
+/// The last few snapshots by tick, oldest first.
+pub struct SnapshotHistory {
+    snapshots: VecDeque<GamePayload>,
+}
+
+impl SnapshotHistory {
+    pub fn new() -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(SNAPSHOT_HISTORY),
+        }
+    }
+
+    pub fn push(&mut self, snapshot: GamePayload) {
+        if self.snapshots.len() == SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&GamePayload> {
+        self.snapshots.iter().rev().find(|s| s.tick == tick)
+    }
+
+    pub fn latest_tick(&self) -> Option<u64> {
+        self.snapshots.back().map(|s| s.tick)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
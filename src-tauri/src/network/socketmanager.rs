@@ -3,8 +3,8 @@ use std::sync::Arc;
 use std::time::Duration;
 use std::{collections::HashSet, net::SocketAddr};
 
-use std::io::Result;
-use tokio::net::UdpSocket;
+use std::io::{Error, ErrorKind, Result};
+use tokio::net::{lookup_host, UdpSocket};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 use tokio::sync::watch;
 
@@ -19,6 +19,11 @@ pub struct SocketManager {
 
 pub type SocketData = (SocketAddr, Vec<u8>);
 
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// Largest payload a single UDP datagram can carry
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
 impl Drop for SocketManager {
     fn drop(&mut self) {
         println!("Closing socket");
@@ -47,23 +52,64 @@ impl SocketManager {
         })
     }
 
-    pub async fn join(port: u16) -> Result<Self> {
-        // Bind to any available local port
-        let bind_addr = SocketAddr::from(([0, 0, 0, 0], 0));
+    pub async fn join(address: &str) -> Result<(Self, SocketAddr)> {
+        let host_addr = Self::resolve(address).await?;
+
+        // Bind to any available local port on the host's address family
+        let bind_addr = match host_addr.ip() {
+            IpAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            IpAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+        };
         let std_socket = std::net::UdpSocket::bind(bind_addr)?;
         std_socket.set_nonblocking(true)?;
 
         let socket = UdpSocket::from_std(std_socket)?;
 
-        let host_addr = SocketAddr::from(([127, 0, 0, 1], port));
-
         // Fire off a join packet (bare minimum)
         socket.send_to(b"JOIN", host_addr).await?;
 
         println!("Joining host at {}", host_addr);
 
-        Ok(Self {
-            socket: Arc::new(socket),
+        Ok((
+            Self {
+                socket: Arc::new(socket),
+            },
+            host_addr,
+        ))
+    }
+
+    ///Turns "ip:port", "[v6]:port", a bare ip or "hostname[:port]" into a socket address.
+    ///Falls back to DEFAULT_PORT when no port is given.
+    pub async fn resolve(address: &str) -> Result<SocketAddr> {
+        let address = address.trim();
+        if address.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "no host address given"));
+        }
+
+        if let Ok(addr) = address.parse::<SocketAddr>() {
+            return Ok(addr);
+        }
+        if let Ok(ip) = address
+            .trim_matches(|c| c == '[' || c == ']')
+            .parse::<IpAddr>()
+        {
+            return Ok(SocketAddr::new(ip, DEFAULT_PORT));
+        }
+
+        let target = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{address}:{DEFAULT_PORT}")
+        };
+        let mut candidates = lookup_host(target)
+            .await
+            .map_err(|e| Error::new(e.kind(), format!("could not resolve {address}: {e}")))?;
+
+        candidates.next().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("{address} did not resolve to any address"),
+            )
         })
     }
 
@@ -75,7 +121,7 @@ impl SocketManager {
     ) {
         let socket = self.socket.clone();
 
-        let mut buf = [0u8; 2048];
+        let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
 
         loop {
             // --- Shutdown check ---
@@ -0,0 +1,212 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    game::{
+        arena::Arena,
+        gameloop::{start_game_loop, LoopConfig},
+        gamemanager::{GameManager, GamePhase, DEFAULT_RECONNECT_GRACE},
+        matchrules::MatchRules,
+    },
+    network::{
+        channels::init_channels,
+        socketmanager::{SocketManager, DEFAULT_PORT},
+    },
+    startup::tasks::StartupTasks,
+};
+
+pub const USAGE: &str = "usage: rebound-server [--port <port>] [--players <count>] \
+[--target-score <score>] [--time-limit <secs>] [--sudden-death <true|false>] [--best-of <rounds>] \
+[--tick-rate <hz>] [--snapshot-rate <hz>] [--replay-dir <dir>] [--map <name or file>] \
+[--reconnect-grace <secs>]";
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub port: u16,
+    pub player_count: u8,
+    pub rules: MatchRules,
+    pub game_loop: LoopConfig,
+    /// Matches get recorded here when set
+    pub replay_dir: Option<PathBuf>,
+    pub arena: Arena,
+    /// Seconds a dropped client's players wait for it to come back
+    pub reconnect_grace: f32,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            player_count: 2,
+            rules: MatchRules::default(),
+            game_loop: LoopConfig::default(),
+            replay_dir: None,
+            arena: Arena::default(),
+            reconnect_grace: DEFAULT_RECONNECT_GRACE,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut config = ServerConfig::default();
+        let mut args = args.into_iter();
+
+        while let Some(flag) = args.next() {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for {flag}"))?;
+
+            match flag.as_str() {
+                "--port" | "-p" => config.port = parse_value(&flag, &value)?,
+                "--players" | "-n" => config.player_count = parse_value(&flag, &value)?,
+                "--target-score" | "-s" => config.rules.score_limit = parse_value(&flag, &value)?,
+                "--time-limit" | "-t" => config.rules.time_limit = parse_value(&flag, &value)?,
+                "--sudden-death" => config.rules.sudden_death = parse_value(&flag, &value)?,
+                "--best-of" => config.rules.best_of = parse_value(&flag, &value)?,
+                "--tick-rate" => config.game_loop.tick_rate = parse_value(&flag, &value)?,
+                "--snapshot-rate" => config.game_loop.snapshot_rate = parse_value(&flag, &value)?,
+                "--replay-dir" => config.replay_dir = Some(PathBuf::from(value)),
+                "--map" | "-m" => config.arena = parse_map(&value)?,
+                "--reconnect-grace" => config.reconnect_grace = parse_value(&flag, &value)?,
+                _ => return Err(format!("unknown argument {flag}")),
+            }
+        }
+
+        if config.player_count == 0 {
+            return Err("--players must be at least 1".into());
+        }
+        config.rules.validate()?;
+        if config.game_loop.tick_rate <= 0.0 || config.game_loop.snapshot_rate <= 0.0 {
+            return Err("--tick-rate and --snapshot-rate must be positive".into());
+        }
+        if !config.reconnect_grace.is_finite() || config.reconnect_grace < 0.0 {
+            return Err("--reconnect-grace can't be negative".into());
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {flag}: {value}"))
+}
+
+///A built in map name, or the path to a map file.
+fn parse_map(value: &str) -> Result<Arena, String> {
+    let path = std::path::Path::new(value);
+    let arena = if path.extension().is_some() {
+        Arena::load(path)
+    } else {
+        Arena::find(value, None)
+    };
+    arena.map_err(|e| format!("--map {value}: {e}"))
+}
+
+///Runs the host side of the game with no window or frontend client attached.
+pub struct HeadlessServer {
+    tasks: StartupTasks,
+    gm: Arc<Mutex<GameManager>>,
+}
+
+impl HeadlessServer {
+    pub fn new(gm: Arc<Mutex<GameManager>>) -> Self {
+        Self {
+            tasks: StartupTasks::new(gm.clone()),
+            gm,
+        }
+    }
+
+    pub async fn init_host(&mut self, port: u16) -> std::io::Result<()> {
+        // Bind first so a taken port fails the whole startup
+        let sm = SocketManager::host(port).await?;
+
+        let (senders, receivers) = init_channels();
+
+        self.tasks.init_gm(&senders.snapshot_tx, receivers.game_rx);
+
+        self.tasks.init_network(
+            &senders,
+            receivers.snapshot_rx,
+            receivers.client_message_rx,
+            receivers.client_dead_rx,
+            receivers.incoming_socket_data_rx,
+        );
+
+        self.tasks
+            .init_ttl_manager(&senders, receivers.client_tick_rx);
+
+        // No local client, so nothing reads the host client's events
+        drop(receivers.client_event_rx);
+
+        self.tasks.init_socket(
+            sm,
+            &senders,
+            receivers.outgoing_socket_data_rx,
+            receivers.shutdown_rx,
+        );
+
+        self.tasks.set_shutdown(senders.shutdown_tx.clone());
+        Ok(())
+    }
+
+    ///Starts a match whenever the lobby is full and the game is waiting.
+    pub fn try_auto_start(&self) {
+        let mut gm = self.gm.lock().unwrap();
+        if gm.phase == GamePhase::Waiting
+            && gm.world.curr_player_count() > 0
+            && gm.start_game().is_ok()
+        {
+            println!("Lobby full and ready, starting match");
+        }
+    }
+
+    pub async fn close_tasks(&mut self) {
+        self.tasks.close_tasks().await;
+    }
+}
+
+///Entry point for the `rebound-server` binary. Runs until ctrl-c.
+pub async fn run_server(config: ServerConfig) -> std::io::Result<()> {
+    let gm = Arc::new(Mutex::new(GameManager::new(config.arena.clone())));
+    {
+        let mut gm = gm.lock().unwrap();
+        gm.set_game_settings(config.player_count, config.rules.clone())
+            .map_err(std::io::Error::other)?;
+        gm.replay_dir = config.replay_dir.clone();
+        gm.reconnect_grace = config.reconnect_grace;
+        // Joining clients are told the rate, so it has to be set before the network starts
+        gm.set_tick_rate(config.game_loop.tick_rate);
+    }
+
+    let mut server = HeadlessServer::new(gm.clone());
+    server.init_host(config.port).await?;
+
+    start_game_loop(gm, config.game_loop);
+
+    println!(
+        "Dedicated server up on port {} ({}, {} players, {:?}, {} Hz sim, {} Hz snapshots)",
+        config.port,
+        config.arena.name,
+        config.player_count,
+        config.rules,
+        config.game_loop.tick_rate,
+        config.game_loop.snapshot_rate
+    );
+
+    let mut lobby_check = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            _ = lobby_check.tick() => server.try_auto_start(),
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    server.close_tasks().await;
+    Ok(())
+}
@@ -1 +1,4 @@
+pub mod headless;
+#[cfg(feature = "desktop")]
 pub mod startup;
+pub mod tasks;
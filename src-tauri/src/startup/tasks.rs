@@ -0,0 +1,140 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+
+use crate::{
+    game::gamemanager::GameManager,
+    network::{
+        channels::HostChannelSenders,
+        clientid::ClientId,
+        clientrequest::{ClientMessage, ClientRequest},
+        networkhandler::NetworkHandler,
+        serverevent::ServerEvent,
+        socketmanager::{SocketData, SocketManager},
+        ttlmanager::TTLManager,
+    },
+};
+
+///The tasks one startup spawned, and the wiring the app and the dedicated server share.
+pub struct StartupTasks {
+    tasks: Vec<JoinHandle<()>>,
+    gm: Arc<Mutex<GameManager>>,
+    shutdown_tx: Option<tokio::sync::watch::Sender<bool>>,
+}
+
+impl StartupTasks {
+    pub fn new(gm: Arc<Mutex<GameManager>>) -> Self {
+        Self {
+            tasks: Vec::new(),
+            gm,
+            shutdown_tx: None,
+        }
+    }
+
+    pub fn push(&mut self, handle: JoinHandle<()>) {
+        self.tasks.push(handle);
+    }
+
+    ///Sent to on close, for the tasks that shut down by themselves.
+    pub fn set_shutdown(&mut self, shutdown_tx: tokio::sync::watch::Sender<bool>) {
+        self.shutdown_tx = Some(shutdown_tx);
+    }
+
+    pub async fn close_tasks(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            // shutdown_tx is now owned, self.shutdown_tx is None
+            let _ = shutdown_tx.send(true);
+            println!("Shutdown Called.");
+        }
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        //abort open tasks and clear list
+        for handle in self.tasks.drain(..) {
+            handle.abort();
+        }
+
+        println!("All listeners aborted.");
+    }
+
+    pub fn init_gm(
+        &self,
+        snapshot_tx: &UnboundedSender<ServerEvent>,
+        game_rx: UnboundedReceiver<(ClientRequest, ClientId)>,
+    ) {
+        let mut gm = self.gm.lock().unwrap(); // okay to panic on poisoned mutex
+        gm.setup_game_manager(Some(snapshot_tx.clone()), Some(game_rx));
+    }
+
+    pub fn init_network(
+        &mut self,
+        senders: &HostChannelSenders,
+        snapshot_rx: UnboundedReceiver<ServerEvent>,
+        client_message_rx: UnboundedReceiver<ClientMessage>,
+        client_dead_rx: UnboundedReceiver<ClientId>,
+        incoming_socket_data: UnboundedReceiver<SocketData>,
+    ) {
+        // Create the network manager
+        let mut nm = NetworkHandler::new(
+            senders.game_tx.clone(), // clone sender
+            client_message_rx,       // move receiver
+            snapshot_rx,
+            senders.client_event_tx.clone(),
+            senders.client_tick_tx.clone(),
+            client_dead_rx,
+            incoming_socket_data,
+            senders.outgoing_socket_data_tx.clone(),
+        );
+
+        {
+            let gm = self.gm.lock().unwrap();
+            nm.set_tick_rate(gm.tick_rate());
+            nm.set_reconnect_grace(gm.reconnect_grace);
+        }
+
+        //Network Listening
+        let nm_handle: JoinHandle<()> = tokio::spawn(async move {
+            nm.start_listening().await;
+        });
+
+        self.tasks.push(nm_handle);
+    }
+
+    pub fn init_ttl_manager(
+        &mut self,
+        senders: &HostChannelSenders,
+        client_tick_rx: UnboundedReceiver<ClientId>,
+    ) {
+        let ttl = TTLManager::new(client_tick_rx, senders.client_dead_tx.clone());
+
+        let ttl_handle = tokio::spawn(async move {
+            ttl.run().await;
+        });
+
+        self.tasks.push(ttl_handle);
+    }
+
+    pub fn init_socket(
+        &mut self,
+        mut sm: SocketManager,
+        senders: &HostChannelSenders,
+        outgoing_socket_data_rx: UnboundedReceiver<SocketData>,
+        shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    ) {
+        // Get incoming clone
+        let incoming_tx = senders.incoming_socket_data_tx.clone();
+        let sm_handle: JoinHandle<()> = tokio::spawn(async move {
+            //Start Polling
+            sm.run(incoming_tx, outgoing_socket_data_rx, shutdown_rx)
+                .await;
+        });
+
+        self.tasks.push(sm_handle);
+    }
+}
@@ -55,6 +55,9 @@ export class AudioSystem {
       case "GoalScored":
         audio.playEffect("goal");
         break;
+      case "PlayerDashed":
+        audio.playEffect("dash");
+        break;
     }
   }
 
@@ -0,0 +1,30 @@
+import { Shape } from "./Payload/State";
+
+// Mirrors game/arena.rs, what select_map hands back
+export type Zone = {
+  name: string;
+  x: number;
+  y: number;
+  angle: number;
+  color: string | null;
+} & Shape;
+
+export type Arena = {
+  name: string;
+  width: number;
+  height: number;
+  zones: Zone[];
+};
+
+// Size of the default map, used until the host picks one
+const DEFAULT_ARENA: Arena = { name: "Classic", width: 320, height: 180, zones: [] };
+
+class ArenaStore {
+  current: Arena = DEFAULT_ARENA;
+
+  set(arena: Arena) {
+    this.current = arena;
+  }
+}
+
+export const arenaStore = new ArenaStore();
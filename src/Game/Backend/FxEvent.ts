@@ -1,6 +1,14 @@
+import { GoalRecord } from "./Payload/ScoreManager";
+
 export type FxEvent =
   | { type: "BallWallHit"; intensity: number }
-  | { type: "GoalScored"; team_id: number };
+  | { type: "GoalScored"; goal: GoalRecord }
+  | {
+      type: "PlayerDashed";
+      player_id: number;
+      pos: [number, number];
+      angle: number;
+    };
 
 export function normalizeFxEvent(e: any): FxEvent {
   const key = Object.keys(e)[0];
@@ -12,6 +20,9 @@ export function normalizeFxEvent(e: any): FxEvent {
     case "BallWallHit":
       return { type: "BallWallHit", ...e[key] };
 
+    case "PlayerDashed":
+      return { type: "PlayerDashed", ...e[key] };
+
     default:
       throw new Error("Unknown FxEvent: " + key);
   }
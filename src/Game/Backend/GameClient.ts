@@ -1,25 +1,63 @@
 import { listen } from "@tauri-apps/api/event";
+import { ref } from "vue";
 import { FxEventBus } from "./FxEventBus";
 import { SnapshotStore } from "./SnapshotStore";
 import { GamePayload } from "./Payload/GamePayload";
+import { LobbyState } from "./Payload/LobbyState";
+
+export type LobbySettings = {
+  player_count: number;
+  rules: {
+    score_limit: number;
+    time_limit: number;
+    sudden_death: boolean;
+    best_of: number;
+    game_over_delay: number;
+  };
+};
 
 class GameClient {
   snapshot = new SnapshotStore();
   fxEventBus = new FxEventBus();
 
+  // The host is always client 0, joining clients get theirs on "joined"
+  clientId = 0;
+  // Lobby changes arrive as events, ahead of the next snapshot
+  private lobby = ref<LobbyState | null>(null);
+  settings = ref<LobbySettings | null>(null);
+  kickedPlayers = ref<number[]>([]);
+
   start() {
     listen<GamePayload>("game-state", (event) => {
       const payload = GamePayload.from(event.payload);
       this.onPayload(payload);
     });
+    listen("lobby_updated", (event) => {
+      this.lobby.value = LobbyState.from(event.payload);
+    });
+    listen<LobbySettings>("lobby_settings", (event) => {
+      this.settings.value = event.payload;
+    });
+    listen<number>("kicked", (event) => {
+      this.kickedPlayers.value.push(event.payload);
+    });
   }
 
   onPayload(payload: GamePayload) {
     this.snapshot.update(payload);
+    this.lobby.value = null;
 
     this.fxEventBus.emit(payload.fx_events);
   }
 
+  get lobby_state(): LobbyState | undefined {
+    return this.lobby.value ?? this.snapshot.lobby_state;
+  }
+
+  ownsPlayer(clientId: number | null): boolean {
+    return clientId === this.clientId;
+  }
+
   stop() {
     //later
   }
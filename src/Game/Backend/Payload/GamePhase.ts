@@ -1,7 +1,9 @@
 export class GamePhase {
   constructor(
-    public type: "Waiting" | "Playing" | "Countdown" | "GameOver",
-    public time_left: number | null = null
+    public type: "Waiting" | "Playing" | "Countdown" | "Celebrating" | "GameOver",
+    public time_left: number | null = null,
+    public winner: number | null = null, // GameOver only, null for a draw
+    public conceded: number | null = null // Celebrating only, kicks off next
   ) {}
 
   static from(obj: any): GamePhase {
@@ -9,7 +11,6 @@ export class GamePhase {
     if (typeof obj === "string") {
       if (obj === "Waiting") return new GamePhase("Waiting");
       if (obj === "Playing") return new GamePhase("Playing");
-      if (obj === "GameOver") return new GamePhase("GameOver");
       throw new Error(`Unknown GamePhase string: ${obj}`);
     }
 
@@ -17,6 +18,21 @@ export class GamePhase {
     if (obj.Countdown !== undefined) {
       return new GamePhase("Countdown", obj.Countdown.time_left);
     }
+    if (obj.Celebrating !== undefined) {
+      return new GamePhase(
+        "Celebrating",
+        obj.Celebrating.time_left,
+        null,
+        obj.Celebrating.conceded,
+      );
+    }
+    if (obj.GameOver !== undefined) {
+      return new GamePhase(
+        "GameOver",
+        obj.GameOver.time_left,
+        obj.GameOver.winner,
+      );
+    }
 
     throw new Error("Unknown GamePhase format: " + JSON.stringify(obj));
   }
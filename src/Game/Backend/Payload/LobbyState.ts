@@ -10,17 +10,48 @@ export class LobbyState {
   static from(obj: any): LobbyState {
     return new LobbyState(
       obj.expected_players,
-      obj.players.map((p: any) => new LobbyPlayer(p.player_id, p.team_id)),
+      obj.players.map(
+        (p: any) =>
+          new LobbyPlayer(
+            p.player_id,
+            p.team_id,
+            p.name ?? `Player ${p.player_id}`,
+            p.client_id ?? null,
+            p.ready ?? false,
+            p.bot ?? null,
+            p.disconnected ?? false,
+          ),
+      ),
     );
   }
 }
 
+export type BotDifficulty = "Easy" | "Normal" | "Hard";
+
 export class LobbyPlayer {
   player_id: number;
   team_id?: number;
+  name: string;
+  client_id: number | null; // null for a bot
+  ready: boolean;
+  bot: BotDifficulty | null; // null for a human
+  disconnected: boolean; // kept for a while in case they come back
 
-  constructor(player_id: number, team_id?: number) {
+  constructor(
+    player_id: number,
+    team_id?: number,
+    name: string = `Player ${player_id}`,
+    client_id: number | null = null,
+    ready: boolean = false,
+    bot: BotDifficulty | null = null,
+    disconnected: boolean = false,
+  ) {
     this.player_id = player_id;
     this.team_id = team_id;
+    this.name = name;
+    this.client_id = client_id;
+    this.ready = ready;
+    this.bot = bot;
+    this.disconnected = disconnected;
   }
 }
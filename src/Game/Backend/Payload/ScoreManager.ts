@@ -1,11 +1,51 @@
 import { Team } from "./Team";
 
+export type PlayerStats = {
+  player_id: number;
+  team_id: number;
+  goals: number;
+  assists: number;
+  own_goals: number;
+};
+
+export type GoalRecord = {
+  goal_team: number; // whose goal it went into
+  scoring_teams: number[];
+  scorer: number | null; // on the conceding team for an own goal
+  assist: number | null;
+  own_goal: boolean;
+};
+
+export type MatchRules = {
+  score_limit: number; // 0 plays to the clock
+  time_limit: number; // seconds, 0 for no clock
+  sudden_death: boolean;
+  best_of: number;
+  game_over_delay: number;
+};
+
 export class ScoreManager {
-  constructor(public teams: Team[]) {}
+  constructor(
+    public teams: Team[],
+    public players: PlayerStats[] = [],
+    public goals: GoalRecord[] = [],
+    public rules: MatchRules | null = null,
+    public clock: number | null = null, // seconds left, null untimed or in overtime
+    public overtime: boolean = false,
+    public round: number = 0,
+  ) {}
 
   static from(obj: any): ScoreManager {
     return new ScoreManager(
-      obj.teams.map((t: any) => new Team(t.id, t.name, t.color, t.score))
+      obj.teams.map(
+        (t: any) => new Team(t.id, t.name, t.color, t.score, t.rounds_won),
+      ),
+      obj.players ?? [],
+      obj.goals ?? [],
+      obj.rules ?? null,
+      obj.clock ?? null,
+      obj.overtime ?? false,
+      obj.round ?? 0,
     );
   }
 }
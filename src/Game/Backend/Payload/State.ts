@@ -14,7 +14,8 @@ export enum ActionState {
 
 export type Shape =
   | { type: "circle"; radius: number }
-  | { type: "rectangle"; w: number; h: number };
+  | { type: "rectangle"; w: number; h: number }
+  | { type: "capsule"; radius: number; length: number };
 
 export class State {
   constructor(
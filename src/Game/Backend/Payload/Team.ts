@@ -2,7 +2,8 @@ export class Team {
   constructor(
     public id: number,
     public name: string,
-    public color: string, // Assuming your Rust Color serializes to something like "#RRGGBB"
-    public score: number
+    public color: number[], // Rust Color comes through as [r, g, b, a]
+    public score: number,
+    public rounds_won: number = 0
   ) {}
 }
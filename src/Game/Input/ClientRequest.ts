@@ -6,7 +6,10 @@ export type ClientRequest =
   | { type: "Joined" }
   | { type: "Idle" }
   | { type: "Remove"; id: number } // PlayerId as number
-  | { type: "Input"; entity_id: number; frame: InputFrame };
+  | { type: "Input"; entity_id: number; frame: InputFrame }
+  | { type: "SetName"; entity_id: number; name: string }
+  | { type: "SetReady"; entity_id: number; ready: boolean }
+  | { type: "SwitchTeam"; entity_id: number; team_id: number };
 
 export function sendClientRequest(request: ClientRequest) {
   invoke("client_request", { request }).catch((err) => {
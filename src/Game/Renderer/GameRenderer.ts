@@ -1,7 +1,6 @@
-const GAME_WIDTH = 320;
-const GAME_HEIGHT = 180;
 import { spriteLibrary } from "./SpriteLibrary";
 import { State } from "../Backend/Payload/State";
+import { arenaStore, Zone } from "../Backend/Arena";
 import { animationLibrary } from "./Animation/AnimationLibrary";
 
 import { AnimPlayer } from "./Animation/AnimPlayer";
@@ -37,11 +36,15 @@ export class GameRenderer {
       const deltaMs = currentTime - lastTime;
       lastTime = currentTime;
 
+      const arena = arenaStore.current;
       const scale = this.getScale();
-      const offsetX = (this.canvas.width - GAME_WIDTH * scale) / 2;
-      const offsetY = (this.canvas.height - GAME_HEIGHT * scale) / 2;
+      const offsetX = (this.canvas.width - arena.width * scale) / 2;
+      const offsetY = (this.canvas.height - arena.height * scale) / 2;
 
       this.clear();
+      for (const zone of arena.zones) {
+        this.drawZone(zone, scale, offsetX, offsetY);
+      }
       // Draw current state
       for (const s of gameClient.snapshot.states) {
         this.draw(s, scale, offsetX, offsetY, deltaMs);
@@ -63,8 +66,8 @@ export class GameRenderer {
   getScale() {
     const canvasWidth = this.canvas.width; // already set to canvas.value.width
     const canvasHeight = this.canvas.height; // already set to canvas.value.height
-    const scaleX = canvasWidth / GAME_WIDTH;
-    const scaleY = canvasHeight / GAME_HEIGHT;
+    const scaleX = canvasWidth / arenaStore.current.width;
+    const scaleY = canvasHeight / arenaStore.current.height;
 
     return Math.min(scaleX, scaleY);
   }
@@ -152,6 +155,12 @@ export class GameRenderer {
       w = h = r * 2;
       x -= r; // Center it properly
       y -= r;
+    } else if (s.shape.type === "capsule") {
+      const r = s.shape.radius * scale;
+      w = s.shape.length * scale + r * 2;
+      h = r * 2;
+      x -= w / 2; // Centered on x, y like circles
+      y -= r;
     }
     return { x, y, w, h };
   }
@@ -218,7 +227,40 @@ export class GameRenderer {
       this.ctx.arc(x, y, s.shape.radius * scale, 0, Math.PI * 2);
       this.ctx.fillStyle = s.is_static ? "gray" : "lime";
       this.ctx.fill();
+    } else if (s.shape.type === "capsule") {
+      this.ctx.beginPath();
+      this.ctx.roundRect(x, y, w, h, h / 2);
+      this.ctx.fillStyle = s.is_static ? "gray" : "lime";
+      this.ctx.fill();
     }
+    // Undo applyRotation like the sprite paths do, diagonal walls go through here
+    this.ctx.restore();
+  }
+
+  // Map markings, under everything and never collided with
+  private drawZone(zone: Zone, scale: number, offsetX: number, offsetY: number) {
+    const x = zone.x * scale + offsetX;
+    const y = zone.y * scale + offsetY;
+    this.ctx.save();
+    this.ctx.fillStyle = zone.color ?? "rgba(255, 255, 255, 0.15)";
+    this.ctx.beginPath();
+    if (zone.type === "rectangle") {
+      const w = zone.w * scale;
+      const h = zone.h * scale;
+      this.applyRotation(zone.angle, x + w / 2, y + h / 2);
+      this.ctx.rect(x, y, w, h);
+    } else if (zone.type === "circle") {
+      this.ctx.arc(x, y, zone.radius * scale, 0, Math.PI * 2);
+    } else if (zone.type === "capsule") {
+      const r = zone.radius * scale;
+      const w = zone.length * scale + r * 2;
+      this.applyRotation(zone.angle, x, y);
+      this.ctx.roundRect(x - w / 2, y - r, w, r * 2, r);
+    }
+    this.ctx.fill();
+    // applyRotation's save, if it made one, then ours
+    if (zone.type !== "circle" && zone.angle) this.ctx.restore();
+    this.ctx.restore();
   }
 
   private clear() {